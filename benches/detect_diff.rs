@@ -0,0 +1,51 @@
+//! Diffing 500 untouched domains, with and without matching export hashes.
+//!
+//! Only `detect_diff` is timed. Matching hashes let it skip comparing a
+//! domain's keys; they don't save parsing, which happens at capture either way.
+//!
+//! Run with `cargo bench --bench detect_diff`.
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use plist::{Dictionary, Value};
+
+use defaults_util::bench::{DiffOptions, DomainSettings, Snapshot, detect_diff};
+
+/// 500 domains of 50 string keys each, the same every call
+fn snapshot(with_hashes: bool) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for d in 0..500 {
+        let domain = format!("com.bench.domain{}", d);
+        let mut values = Dictionary::new();
+        for k in 0..50 {
+            values.insert(
+                format!("key{}", k),
+                Value::String(format!("value for key{}", k)),
+            );
+        }
+        if with_hashes {
+            snapshot.export_hashes.insert(domain.clone(), 7);
+        }
+        snapshot.domains.insert(domain, DomainSettings { values });
+    }
+    snapshot
+}
+
+fn bench_identical_domains(c: &mut Criterion) {
+    let mut group = c.benchmark_group("detect_diff identical domains");
+    for (label, with_hashes) in [("full diff", false), ("hash fast path", true)] {
+        let (before, after) = (snapshot(with_hashes), snapshot(with_hashes));
+        group.bench_function(label, |b| {
+            b.iter(|| {
+                detect_diff(
+                    black_box(&before),
+                    black_box(&after),
+                    &DiffOptions::default(),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_identical_domains);
+criterion_main!(benches);
@@ -1,9 +1,28 @@
-use std::time::Instant;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use ratatui::layout::{Position, Rect};
 use ratatui::widgets::ListState;
 
-use crate::defaults::{Snapshot, capture_snapshot};
-use crate::diff::{Change, DiffResult, detect_diff};
+use crate::command::apply::run_command;
+use crate::command::generator::{
+    CommandOptions, CommandScope, apply_host, apply_scope, domain_commands,
+    generate_scoped_command, generate_undo_command, with_verify,
+};
+use crate::command::script::write_script;
+use crate::config::{Config, StatusVerbosity};
+use crate::defaults::reader::{Progress, domain_plist_path, is_apple_service_domain};
+use crate::defaults::{CaptureOptions, Snapshot, capture_snapshot};
+use crate::diff::types::{ChangeKind, DomainDiff};
+use crate::diff::{Change, DiffOptions, DiffResult, detect_diff, is_factory_default};
+use crate::error::{AppError, describe_capture_error};
+use crate::export::{self, write_domain_bundle};
+use crate::ui::theme::ThemePreset;
 
 /// Application screen state
 #[derive(Debug, Clone, PartialEq)]
@@ -18,10 +37,121 @@ pub enum Screen {
     WaitingForChanges,
     /// Diff view screen
     DiffView,
+    /// Second snapshot matched the first
+    NoChanges,
+    /// Earlier captures, any two of which can be diffed
+    History,
     /// Error display
     Error(String),
 }
 
+/// Export action waiting to run after the next draw
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportAction {
+    /// One shell script per domain
+    DomainBundle,
+    /// A single runnable script for the whole diff
+    Script,
+    /// A Markdown report with a table per domain
+    Report,
+}
+
+/// What the text input line is editing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputMode {
+    /// Diff view filter, applied as the user types
+    Filter,
+    /// Domain to scope captures to
+    DomainScope,
+    /// Fuzzy domain name to jump the selection to
+    Jump,
+}
+
+/// Order of the domain list
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SortMode {
+    #[default]
+    Name,
+    /// Most changes first, ties by name
+    ChangeCountDesc,
+}
+
+impl SortMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::ChangeCountDesc => "change count",
+        }
+    }
+
+    /// Next mode when cycling through the options
+    pub fn next(&self) -> Self {
+        match self {
+            SortMode::Name => SortMode::ChangeCountDesc,
+            SortMode::ChangeCountDesc => SortMode::Name,
+        }
+    }
+}
+
+/// Order of the changes within a domain
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ChangeSort {
+    /// The order keys appear in the domain's export
+    #[default]
+    SourceOrder,
+    /// By key
+    Alpha,
+    /// Added, then removed, then modified keys, each by key
+    TypeThenAlpha,
+}
+
+impl ChangeSort {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChangeSort::SourceOrder => "export order",
+            ChangeSort::Alpha => "key",
+            ChangeSort::TypeThenAlpha => "type, then key",
+        }
+    }
+
+    /// Next order when cycling through the options
+    pub fn next(&self) -> Self {
+        match self {
+            ChangeSort::SourceOrder => ChangeSort::Alpha,
+            ChangeSort::Alpha => ChangeSort::TypeThenAlpha,
+            ChangeSort::TypeThenAlpha => ChangeSort::SourceOrder,
+        }
+    }
+
+    /// Reorder one domain's changes (the sort is stable, so ties keep export order)
+    fn sort(&self, changes: &mut [Change]) {
+        let kind_rank = |change: &Change| match change.kind() {
+            Some(ChangeKind::Added) => 0,
+            Some(ChangeKind::Removed) => 1,
+            Some(ChangeKind::Modified) => 2,
+            None => 3,
+        };
+        match self {
+            ChangeSort::SourceOrder => {}
+            ChangeSort::Alpha => changes.sort_by(|a, b| a.key().cmp(b.key())),
+            ChangeSort::TypeThenAlpha => changes.sort_by(|a, b| {
+                kind_rank(a)
+                    .cmp(&kind_rank(b))
+                    .then_with(|| a.key().cmp(b.key()))
+            }),
+        }
+    }
+}
+
+/// What the preview pane shows for the selected change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewMode {
+    /// Generated `defaults` command
+    Command,
+    /// `domain key = new (was old)` summary
+    Summary,
+}
+
 /// Currently focused UI element
 #[derive(Debug, Clone, PartialEq)]
 pub enum Focus {
@@ -43,14 +173,23 @@ pub struct StatusMessage {
     pub text: String,
     pub kind: StatusKind,
     pub created_at: Instant,
+    /// How long the message stays up; `None` uses the configured default
+    pub lifetime: Option<Duration>,
 }
 
+/// Lifetime of info and success messages unless configured otherwise
+const DEFAULT_STATUS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Lifetime of messages that name a file the user may want to copy
+const PATH_STATUS_TIMEOUT: Duration = Duration::from_secs(8);
+
 impl StatusMessage {
     pub fn info(text: impl Into<String>) -> Self {
         Self {
             text: text.into(),
             kind: StatusKind::Info,
             created_at: Instant::now(),
+            lifetime: None,
         }
     }
 
@@ -59,6 +198,7 @@ impl StatusMessage {
             text: text.into(),
             kind: StatusKind::Success,
             created_at: Instant::now(),
+            lifetime: None,
         }
     }
 
@@ -67,13 +207,234 @@ impl StatusMessage {
             text: text.into(),
             kind: StatusKind::Warning,
             created_at: Instant::now(),
+            lifetime: None,
+        }
+    }
+
+    /// Keep the message up for `lifetime` instead of the default
+    pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = Some(lifetime);
+        self
+    }
+
+    /// Check if message is still valid. Without an explicit lifetime, info and
+    /// success messages last `default` and warnings twice as long.
+    pub fn is_valid(&self, default: Duration) -> bool {
+        let lifetime = self.lifetime.unwrap_or(match self.kind {
+            StatusKind::Warning => default.saturating_mul(2),
+            StatusKind::Info | StatusKind::Success => default,
+        });
+        self.created_at.elapsed() < lifetime
+    }
+}
+
+/// Snapshot capture running on a worker thread
+pub struct CaptureJob {
+    cancel: Arc<AtomicBool>,
+    receiver: Receiver<anyhow::Result<Option<Snapshot>>>,
+    progress: Receiver<CaptureProgress>,
+    started: Instant,
+    /// Started by watch mode rather than by the user
+    watch: bool,
+}
+
+/// Function a capture job runs on its worker thread; `capture_snapshot`
+/// outside of tests
+type CaptureFn =
+    fn(&CaptureOptions, &AtomicBool, &mut Progress) -> anyhow::Result<Option<Snapshot>>;
+
+impl CaptureJob {
+    fn spawn(options: CaptureOptions, capture: CaptureFn) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let (progress_sender, progress) = mpsc::channel();
+        let flag = Arc::clone(&cancel);
+        thread::spawn(move || {
+            let mut report = |done, total, domain: &str| {
+                let _ = progress_sender.send(CaptureProgress {
+                    done,
+                    total,
+                    domain: domain.to_string(),
+                });
+            };
+            // The receiver is gone if the capture was cancelled
+            let _ = sender.send(capture(&options, &flag, &mut report));
+        });
+        Self {
+            cancel,
+            receiver,
+            progress,
+            started: Instant::now(),
+            watch: false,
+        }
+    }
+
+    /// Ask the worker threads to stop; their result is never read
+    fn cancel(self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Latest progress report from a running capture
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureProgress {
+    pub done: usize,
+    pub total: usize,
+    /// Domain that finished most recently
+    pub domain: String,
+}
+
+impl CaptureProgress {
+    /// Completed fraction in `0.0..=1.0`
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            (self.done as f64 / self.total as f64).min(1.0)
+        }
+    }
+}
+
+/// A capture kept for the history screen
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    /// Capture number in this session, counting ones that fell out of the history
+    pub number: usize,
+    /// How the snapshot was taken ("first capture", "capture", "loaded")
+    pub source: &'static str,
+    pub taken_at: Instant,
+    pub snapshot: Snapshot,
+}
+
+/// Captures kept for the history screen; older ones are dropped
+const HISTORY_LIMIT: usize = 10;
+
+/// Time between captures in watch mode
+const WATCH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Idle time after which type-ahead ends and letters are commands again
+const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Index of the first domain starting with `query`, falling back to the first containing it
+pub fn find_type_ahead_match<'a>(
+    mut domains: impl Iterator<Item = &'a str> + Clone,
+    query: &str,
+) -> Option<usize> {
+    let query = query.to_lowercase();
+    domains
+        .clone()
+        .position(|d| d.to_lowercase().starts_with(&query))
+        .or_else(|| domains.position(|d| d.to_lowercase().contains(&query)))
+}
+
+/// Score `candidate` for a fuzzy `query`: every query character must appear in
+/// order (case-insensitive). Consecutive matches and matches at the start of a
+/// dot-separated component score higher; longer candidates score slightly lower.
+fn fuzzy_score(candidate: &str, query: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut last: Option<usize> = None;
+    let mut from = 0;
+    for q in query.to_lowercase().chars() {
+        let index = from + candidate[from..].iter().position(|&c| c == q)?;
+        score += match last {
+            Some(prev) if prev + 1 == index => 5,
+            _ if index == 0 || candidate[index - 1] == '.' => 3,
+            _ => 1,
+        };
+        last = Some(index);
+        from = index + 1;
+    }
+    Some(score * 100 - candidate.len() as i64)
+}
+
+/// Index of the domain that best matches a fuzzy query (first wins ties)
+pub fn find_fuzzy_match<'a>(domains: impl Iterator<Item = &'a str>, query: &str) -> Option<usize> {
+    let mut best: Option<(usize, i64)> = None;
+    for (index, domain) in domains.enumerate() {
+        if let Some(score) = fuzzy_score(domain, query)
+            && best.is_none_or(|(_, top)| score > top)
+        {
+            best = Some((index, score));
+        }
+    }
+    best.map(|(index, _)| index)
+}
+
+/// App a domain belongs to: its first two reverse-DNS components
+/// (`com.apple.dock` → `com.apple`). Shorter names stand alone.
+pub fn domain_group(domain: &str) -> Option<&str> {
+    let (index, _) = domain.match_indices('.').nth(1)?;
+    Some(&domain[..index])
+}
+
+/// A row of the domain list
+#[derive(Debug, Clone, PartialEq)]
+pub enum DomainRow {
+    /// Collapsible header for domains sharing a reverse-DNS prefix
+    Group {
+        prefix: String,
+        domains: usize,
+        changes: usize,
+        expanded: bool,
+    },
+    /// Index into the visible domains; `nested` when listed under a header
+    Domain { index: usize, nested: bool },
+}
+
+/// Rows of the domain list. When `grouped`, domains sharing a prefix with
+/// another visible domain gather under one header, placed where the first of
+/// them would be, and are listed only while their prefix is `expanded`.
+pub fn domain_rows(
+    domains: &[DomainDiff],
+    grouped: bool,
+    expanded: &HashSet<String>,
+) -> Vec<DomainRow> {
+    if !grouped {
+        return (0..domains.len())
+            .map(|index| DomainRow::Domain {
+                index,
+                nested: false,
+            })
+            .collect();
+    }
+    let mut members: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, domain_diff) in domains.iter().enumerate() {
+        if let Some(prefix) = domain_group(&domain_diff.domain) {
+            members.entry(prefix).or_default().push(index);
         }
     }
 
-    /// Check if message is still valid (within 3 seconds)
-    pub fn is_valid(&self) -> bool {
-        self.created_at.elapsed().as_secs() < 3
+    let mut rows = Vec::new();
+    for (index, domain_diff) in domains.iter().enumerate() {
+        let group = domain_group(&domain_diff.domain)
+            .and_then(|prefix| members.get_key_value(prefix))
+            .filter(|(_, indices)| indices.len() > 1);
+        let Some((prefix, indices)) = group else {
+            rows.push(DomainRow::Domain {
+                index,
+                nested: false,
+            });
+            continue;
+        };
+        if indices[0] != index {
+            continue;
+        }
+        let is_expanded = expanded.contains(*prefix);
+        rows.push(DomainRow::Group {
+            prefix: prefix.to_string(),
+            domains: indices.len(),
+            changes: indices.iter().map(|&i| domains[i].changes.len()).sum(),
+            expanded: is_expanded,
+        });
+        if is_expanded {
+            rows.extend(indices.iter().map(|&index| DomainRow::Domain {
+                index,
+                nested: true,
+            }));
+        }
     }
+    rows
 }
 
 /// Application state
@@ -82,6 +443,9 @@ pub struct App {
     pub focus: Focus,
     pub snapshot_before: Option<Snapshot>,
     pub snapshot_after: Option<Snapshot>,
+    /// Unfiltered diff between the two snapshots
+    pub full_diff: Option<DiffResult>,
+    /// Diff as shown in the UI (after filters)
     pub diff_result: Option<DiffResult>,
     pub selected_domain_index: usize,
     pub selected_diff_index: usize,
@@ -89,6 +453,103 @@ pub struct App {
     pub status: Option<StatusMessage>,
     pub domain_list_state: ListState,
     pub diff_list_state: ListState,
+    /// Gather domains under collapsible reverse-DNS prefix headers
+    pub group_domains: bool,
+    /// Group prefixes whose domains are listed
+    pub expanded_groups: HashSet<String>,
+    /// Rows of the domain list, rebuilt with the visible diff
+    pub domain_rows: Vec<DomainRow>,
+    /// Group header under the cursor, when it isn't on a domain
+    pub selected_group: Option<String>,
+    pub command_options: CommandOptions,
+    pub pending_export: Option<ExportAction>,
+    pub factory_baseline: Option<Snapshot>,
+    pub hide_factory_defaults: bool,
+    pub hide_removed_only: bool,
+    /// Whether the first snapshot may contain Apple service domains, so a
+    /// later capture doesn't report them all as added
+    pub before_has_apple_services: bool,
+    pub sort_mode: SortMode,
+    pub change_sort: ChangeSort,
+    /// Show only changes of this kind
+    pub change_type_filter: Option<ChangeKind>,
+    pub config: Config,
+    pub theme: ThemePreset,
+    pub capture_job: Option<CaptureJob>,
+    pub capture_progress: Option<CaptureProgress>,
+    pub group_commands_by_domain: bool,
+    pub diff_options: DiffOptions,
+    pub show_stats: bool,
+    pub show_failed_domains: bool,
+    /// Full-screen keybinding overlay
+    pub show_help: bool,
+    /// Characters typed since `'` started a type-ahead jump
+    pub type_ahead: String,
+    /// When the last type-ahead key was typed; `None` when type-ahead is off
+    pub type_ahead_at: Option<Instant>,
+    pub capture_options: CaptureOptions,
+    pub preview_mode: PreviewMode,
+    /// Substring the diff view is narrowed to (domain name or key)
+    pub filter: Option<String>,
+    /// What keystrokes are typed into, if the input line is open
+    pub input_mode: Option<InputMode>,
+    /// Domain name being typed for a scoped capture
+    pub domain_input: String,
+    /// Partial domain name typed after `:`
+    pub jump_query: String,
+    /// Domain the jump query currently selects (`None` when nothing matches)
+    pub jump_match: Option<usize>,
+    /// Domain selected before the jump started, restored on Esc
+    pub jump_origin: usize,
+    /// Change whose full values are shown in a popup
+    pub value_popup: Option<Change>,
+    /// Lines the value popup is scrolled down by
+    pub value_popup_scroll: u16,
+    /// Furthest the value popup can scroll, updated on each draw
+    pub value_popup_max_scroll: u16,
+    /// Domain whose every current key is listed in the all-keys popup
+    pub all_keys_domain: Option<String>,
+    /// Lines scrolled past in the all-keys popup
+    pub all_keys_scroll: u16,
+    /// Furthest the all-keys popup can scroll, updated on each draw
+    pub all_keys_max_scroll: u16,
+    /// Recent snapshots, oldest first
+    pub history: VecDeque<HistoryEntry>,
+    /// Captures recorded so far, for numbering history entries
+    pub history_count: usize,
+    pub history_state: ListState,
+    /// History entry picked as one side of the next comparison
+    pub history_marked: Option<usize>,
+    /// Screen to go back to when the history screen closes
+    pub history_return: Option<Screen>,
+    /// `(domain, key)` to select again once the next diff is detected
+    restore_selection: Option<(String, String)>,
+    /// What capture jobs run, so tests can stand in for `defaults`
+    capture: CaptureFn,
+    /// Command waiting for the user to confirm applying it
+    pub pending_apply: Option<String>,
+    /// Plist file to open in the editor (run from the main loop, which can
+    /// hand the terminal over)
+    pub pending_edit: Option<PathBuf>,
+    /// `(domain, key)` of changes applied from the diff view
+    pub applied: HashSet<(String, String)>,
+    /// Re-capture periodically and diff against the first snapshot
+    pub watch: bool,
+    /// When the last watch-mode capture started
+    pub last_watch_at: Option<Instant>,
+    /// Rows visible in the diff view lists, updated on each draw
+    pub page_size: usize,
+    /// Where the domain and change lists were last drawn, for mouse clicks
+    pub domain_list_area: Rect,
+    pub diff_list_area: Rect,
+    /// Rows the selected change took beyond its first when last drawn
+    pub selected_change_extra_rows: usize,
+    /// Inspect mode: actions that write files or settings are disabled
+    pub read_only: bool,
+    /// Whether anything from the diff was copied, exported or applied
+    pub exported: bool,
+    /// Quitting is waiting for confirmation because nothing was exported
+    pub confirm_quit: bool,
 }
 
 impl App {
@@ -98,6 +559,7 @@ impl App {
             focus: Focus::Domain,
             snapshot_before: None,
             snapshot_after: None,
+            full_diff: None,
             diff_result: None,
             selected_domain_index: 0,
             selected_diff_index: 0,
@@ -105,6 +567,63 @@ impl App {
             status: None,
             domain_list_state: ListState::default(),
             diff_list_state: ListState::default(),
+            group_domains: false,
+            expanded_groups: HashSet::new(),
+            domain_rows: Vec::new(),
+            selected_group: None,
+            command_options: CommandOptions::default(),
+            pending_export: None,
+            factory_baseline: None,
+            hide_factory_defaults: false,
+            hide_removed_only: false,
+            before_has_apple_services: false,
+            sort_mode: SortMode::default(),
+            change_sort: ChangeSort::default(),
+            change_type_filter: None,
+            config: Config::default(),
+            theme: ThemePreset::default(),
+            capture_job: None,
+            capture_progress: None,
+            group_commands_by_domain: false,
+            diff_options: DiffOptions::default(),
+            show_stats: false,
+            show_failed_domains: false,
+            show_help: false,
+            type_ahead: String::new(),
+            type_ahead_at: None,
+            capture_options: CaptureOptions::default(),
+            preview_mode: PreviewMode::Command,
+            filter: None,
+            input_mode: None,
+            domain_input: String::new(),
+            jump_query: String::new(),
+            jump_match: None,
+            jump_origin: 0,
+            value_popup: None,
+            value_popup_scroll: 0,
+            value_popup_max_scroll: 0,
+            all_keys_domain: None,
+            all_keys_scroll: 0,
+            all_keys_max_scroll: 0,
+            history: VecDeque::new(),
+            history_count: 0,
+            history_state: ListState::default(),
+            history_marked: None,
+            history_return: None,
+            restore_selection: None,
+            capture: capture_snapshot,
+            pending_apply: None,
+            pending_edit: None,
+            applied: HashSet::new(),
+            watch: false,
+            last_watch_at: None,
+            page_size: 10,
+            domain_list_area: Rect::default(),
+            diff_list_area: Rect::default(),
+            selected_change_extra_rows: 0,
+            read_only: false,
+            exported: false,
+            confirm_quit: false,
         }
     }
 
@@ -115,26 +634,59 @@ impl App {
 
     /// Get valid status message
     pub fn get_status(&self) -> Option<&StatusMessage> {
-        self.status.as_ref().filter(|s| s.is_valid())
+        self.status.as_ref().filter(|s| {
+            s.is_valid(self.config.status_timeout.unwrap_or(DEFAULT_STATUS_TIMEOUT))
+                && !(self.config.status_verbosity == StatusVerbosity::Quiet
+                    && s.kind == StatusKind::Info)
+        })
     }
 
     /// Reset to initial state
     pub fn reset(&mut self) {
+        self.stop_capture();
         self.screen = Screen::Initial;
         self.focus = Focus::Domain;
         self.snapshot_before = None;
         self.snapshot_after = None;
+        self.full_diff = None;
         self.diff_result = None;
         self.selected_domain_index = 0;
         self.selected_diff_index = 0;
+        self.domain_rows.clear();
+        self.selected_group = None;
         self.domain_list_state.select(None);
         self.diff_list_state.select(None);
+        self.watch = false;
+        self.exported = false;
+        self.restore_selection = None;
         self.status = Some(StatusMessage::info("Reset complete"));
     }
 
+    /// Quit, asking first if the diff view would be lost without anything exported
+    pub fn request_quit(&mut self) {
+        if self.screen == Screen::DiffView && !self.exported {
+            self.confirm_quit = true;
+        } else {
+            self.should_quit = true;
+        }
+    }
+
+    pub fn cancel_quit(&mut self) {
+        self.confirm_quit = false;
+    }
+
     /// Start first snapshot capture (transition to loading screen)
     pub fn start_first_snapshot(&mut self) {
+        if self.reject_if_loading() {
+            return;
+        }
         self.screen = Screen::LoadingFirst;
+        self.before_has_apple_services = self.capture_options.include_apple_services;
+        self.stop_capture();
+        self.capture_job = Some(CaptureJob::spawn(
+            self.capture_options.clone(),
+            self.capture,
+        ));
         self.status = Some(StatusMessage::info(
             "Capturing defaults... This may take a few seconds",
         ));
@@ -142,48 +694,447 @@ impl App {
 
     /// Start second snapshot capture (transition to loading screen)
     pub fn start_second_snapshot(&mut self) {
+        if self.reject_if_loading() {
+            return;
+        }
         self.screen = Screen::LoadingSecond;
+        // A watch capture still running would only be superseded by this one
+        self.stop_capture();
+        self.capture_job = Some(CaptureJob::spawn(
+            self.second_capture_options(),
+            self.capture,
+        ));
         self.status = Some(StatusMessage::info(
             "Capturing defaults and detecting changes...",
         ));
     }
 
-    /// Execute snapshot capture (called from main loop)
-    pub fn execute_capture(&mut self) {
-        match self.screen {
-            Screen::LoadingFirst => self.capture_first_snapshot(),
-            Screen::LoadingSecond => self.capture_second_snapshot(),
-            _ => {}
+    /// Re-capture only the second snapshot and diff it again, keeping the
+    /// selected change selected if it is still there
+    pub fn refresh_second_snapshot(&mut self) {
+        if self.screen != Screen::DiffView || self.snapshot_before.is_none() {
+            self.status = Some(StatusMessage::warning(
+                "Refresh needs a diff; press [Enter] to capture the second snapshot",
+            ));
+            return;
+        }
+        let selected = self.selection_key();
+        self.start_second_snapshot();
+        if self.is_loading() {
+            self.restore_selection = selected;
+        }
+    }
+
+    /// Options for a capture diffed against the first snapshot; Apple service
+    /// domains are only read if the first snapshot read them too
+    fn second_capture_options(&self) -> CaptureOptions {
+        let mut options = self.capture_options.clone();
+        options.include_apple_services &= self.before_has_apple_services;
+        options
+    }
+
+    /// Include or exclude Apple background service domains from captures and the diff
+    pub fn toggle_apple_services(&mut self) {
+        self.capture_options.include_apple_services = !self.capture_options.include_apple_services;
+        self.apply_filters();
+        self.status = Some(StatusMessage::info(
+            if self.capture_options.include_apple_services {
+                "Including Apple service domains"
+            } else {
+                "Excluding Apple service domains"
+            },
+        ));
+    }
+
+    /// Toggle double-read capture that ignores keys changed by reading them
+    pub fn toggle_volatile_detection(&mut self) {
+        self.capture_options.detect_volatile = !self.capture_options.detect_volatile;
+        self.status = Some(StatusMessage::info(
+            if self.capture_options.detect_volatile {
+                "Volatile key detection on (captures take about twice as long)"
+            } else {
+                "Volatile key detection off"
+            },
+        ));
+    }
+
+    /// Cycle whether managed (MDM) preference domains are captured
+    pub fn cycle_managed_preferences(&mut self) {
+        self.capture_options.managed = self.capture_options.managed.next();
+        self.status = Some(StatusMessage::info(format!(
+            "Managed preferences: {}",
+            self.capture_options.managed.label()
+        )));
+    }
+
+    /// Ignore capture triggers while a capture is already running
+    fn reject_if_loading(&mut self) -> bool {
+        if self.is_loading() {
+            self.status = Some(StatusMessage::warning("Capture already in progress"));
+        }
+        self.is_loading()
+    }
+
+    /// Pick up the result of a finished background capture (called from main loop)
+    pub fn poll_capture(&mut self) {
+        let Some(job) = &self.capture_job else {
+            return;
+        };
+        if let Some(progress) = job.progress.try_iter().last() {
+            self.capture_progress = Some(progress);
+        }
+        let result = match job.receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("capture thread exited unexpectedly"))
+            }
+        };
+        let elapsed = job.started.elapsed();
+        let watch = job.watch;
+        self.capture_job = None;
+        self.capture_progress = None;
+
+        // A watch result only applies while the diff it refreshes is on screen
+        if watch && !(self.watch && self.is_watch_screen()) {
+            return;
+        }
+        match result {
+            Ok(Some(snapshot)) => match self.screen {
+                _ if watch => self.finish_watch_capture(snapshot),
+                Screen::LoadingFirst => self.finish_first_snapshot(snapshot, elapsed),
+                Screen::LoadingSecond => self.finish_second_snapshot(snapshot, elapsed),
+                _ => {}
+            },
+            // Cancelled; the previous screen was already restored
+            Ok(None) => {}
+            Err(e) => {
+                self.screen = Screen::Error(describe_capture_error(&e));
+            }
+        }
+    }
+
+    /// Cancel the running capture, if any, without touching the screen
+    fn stop_capture(&mut self) {
+        if let Some(job) = self.capture_job.take() {
+            job.cancel();
+        }
+        self.capture_progress = None;
+    }
+
+    /// Screens watch mode refreshes in the background
+    fn is_watch_screen(&self) -> bool {
+        matches!(
+            self.screen,
+            Screen::WaitingForChanges | Screen::DiffView | Screen::NoChanges
+        )
+    }
+
+    /// Toggle periodic re-capture against the first snapshot
+    pub fn toggle_watch(&mut self) {
+        if !self.watch && self.snapshot_before.is_none() {
+            self.status = Some(StatusMessage::warning(
+                "Capture the first snapshot to watch",
+            ));
+            return;
+        }
+        self.watch = !self.watch;
+        self.last_watch_at = None;
+        if !self.watch && self.capture_job.as_ref().is_some_and(|job| job.watch) {
+            self.stop_capture();
+        }
+        self.status = Some(StatusMessage::info(if self.watch {
+            "Watching for changes every 3 seconds (w to stop)"
+        } else {
+            "Stopped watching"
+        }));
+    }
+
+    /// Start a background capture when watch mode is due (called from the main loop)
+    pub fn tick_watch(&mut self) {
+        if !self.watch
+            || !self.is_watch_screen()
+            || self.capture_job.is_some()
+            || self
+                .last_watch_at
+                .is_some_and(|at| at.elapsed() < WATCH_INTERVAL)
+        {
+            return;
+        }
+        self.last_watch_at = Some(Instant::now());
+        let mut job = CaptureJob::spawn(self.second_capture_options(), self.capture);
+        job.watch = true;
+        self.capture_job = Some(job);
+    }
+
+    /// Re-diff against the first snapshot, keeping the selected change selected
+    fn finish_watch_capture(&mut self, snapshot: Snapshot) {
+        let status = self.status.take();
+
+        self.restore_selection = self.selection_key();
+        self.snapshot_after = Some(snapshot);
+        self.detect_changes();
+        // Keep whatever the user last saw instead of a "Found N changes" every refresh
+        self.status = status;
+    }
+
+    /// Domain and key of the selected change, to find it again after a rebuild
+    fn selection_key(&self) -> Option<(String, String)> {
+        self.selected_change()
+            .map(|c| (c.domain().to_string(), c.key().to_string()))
+    }
+
+    /// Select a change by domain and key, falling back to the domain's first change
+    pub fn select_change(&mut self, domain: &str, key: &str) {
+        let Some(diff) = &self.diff_result else {
+            return;
+        };
+        let Some(domain_index) = diff.domain_diffs.iter().position(|d| d.domain == domain) else {
+            return;
+        };
+        let change_index = diff.domain_diffs[domain_index]
+            .changes
+            .iter()
+            .position(|c| c.key() == key)
+            .unwrap_or(0);
+        self.select_domain(domain_index);
+        self.selected_diff_index = change_index;
+        self.clamp_selection();
+    }
+
+    /// Cancel a running capture and return to the screen it was started from
+    pub fn cancel_capture(&mut self) {
+        if self.capture_job.is_none() {
+            return;
+        }
+        self.stop_capture();
+        self.restore_selection = None;
+
+        self.screen = match self.screen {
+            // A refresh keeps the diff it was started from
+            Screen::LoadingSecond
+                if self
+                    .full_diff
+                    .as_ref()
+                    .is_some_and(|diff| diff.total_changes > 0) =>
+            {
+                Screen::DiffView
+            }
+            Screen::LoadingSecond => Screen::WaitingForChanges,
+            _ => Screen::Initial,
+        };
+        self.status = Some(StatusMessage::warning(
+            "Capture cancelled - Press [Enter] to retry",
+        ));
+    }
+
+    /// Store the first snapshot
+    fn finish_first_snapshot(&mut self, snapshot: Snapshot, elapsed: Duration) {
+        let count = snapshot.domain_count();
+        let skipped = snapshot.failed_domains.len();
+        let retried = snapshot.retried_domains.len();
+        // Diffing against nothing would later look like "No changes detected"
+        if count == 0 {
+            let error = AppError::NoDomains { failed: skipped };
+            self.screen = Screen::Error(describe_capture_error(&error.into()));
+            return;
+        }
+        self.record_history("first capture", &snapshot);
+        self.snapshot_before = Some(snapshot);
+        self.screen = Screen::WaitingForChanges;
+        let mut status = if skipped == 0 {
+            StatusMessage::success(format!(
+                "✓ Captured {} domains in {:.1}s",
+                count,
+                elapsed.as_secs_f64()
+            ))
+        } else {
+            StatusMessage::warning(format!(
+                "Captured {} domains, {} skipped in {:.1}s (f to list)",
+                count,
+                skipped,
+                elapsed.as_secs_f64()
+            ))
+        };
+        if retried > 0 {
+            status.text = format!("{} ({} after retrying)", status.text, retried);
+        }
+        self.status = Some(status);
+    }
+
+    /// Save the "before" snapshot so a later session can diff against it
+    pub fn save_before_snapshot(&mut self, path: &Path) {
+        if self.reject_if_read_only("Saving snapshots") {
+            return;
+        }
+        let Some(snapshot) = &self.snapshot_before else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| snapshot.save_to_file(path));
+        self.status = Some(match result {
+            Ok(()) => StatusMessage::success(format!("✓ Saved snapshot to {}", path.display())),
+            Err(e) => StatusMessage::warning(format!("Saving snapshot failed: {}", e)),
+        });
+    }
+
+    /// Load the factory baseline if one has been saved; a file that exists
+    /// but cannot be read is reported on the status line
+    pub fn load_factory_baseline(&mut self, path: &Path) {
+        if !path.exists() {
+            return;
+        }
+        match Snapshot::load_from_file(path) {
+            Ok(snapshot) => self.factory_baseline = Some(snapshot),
+            Err(e) => {
+                self.status = Some(StatusMessage::warning(format!(
+                    "Could not load factory baseline {}: {}",
+                    path.display(),
+                    e
+                )));
+            }
         }
     }
 
-    /// Capture first snapshot
-    fn capture_first_snapshot(&mut self) {
-        match capture_snapshot() {
+    /// Load a saved snapshot as the "before" and wait for changes
+    pub fn load_before_snapshot(&mut self, path: &Path) {
+        if !matches!(self.screen, Screen::Initial | Screen::WaitingForChanges) {
+            return;
+        }
+        match Snapshot::load_from_file(path) {
             Ok(snapshot) => {
                 let count = snapshot.domain_count();
+                self.record_history("loaded", &snapshot);
                 self.snapshot_before = Some(snapshot);
+                self.before_has_apple_services = true;
+                self.snapshot_after = None;
                 self.screen = Screen::WaitingForChanges;
                 self.status = Some(StatusMessage::success(format!(
-                    "✓ Captured {} domains successfully",
-                    count
+                    "✓ Loaded {} domains from {}",
+                    count,
+                    path.display()
                 )));
             }
             Err(e) => {
-                self.screen = Screen::Error(format!("Failed to capture snapshot: {}", e));
+                self.status = Some(StatusMessage::warning(format!(
+                    "Loading snapshot failed: {}",
+                    e
+                )));
             }
         }
     }
 
-    /// Capture second snapshot and detect diff
-    fn capture_second_snapshot(&mut self) {
-        match capture_snapshot() {
-            Ok(snapshot) => {
-                self.snapshot_after = Some(snapshot);
-                self.detect_changes();
+    /// Keep a copy of a snapshot for the history screen
+    fn record_history(&mut self, source: &'static str, snapshot: &Snapshot) {
+        self.history_count += 1;
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.pop_front();
+            // The marked entry moved up, or was the one dropped
+            self.history_marked = self.history_marked.and_then(|i| i.checked_sub(1));
+        }
+        self.history.push_back(HistoryEntry {
+            number: self.history_count,
+            source,
+            taken_at: Instant::now(),
+            snapshot: snapshot.clone(),
+        });
+    }
+
+    /// Open the history screen, starting at the newest capture
+    pub fn open_history(&mut self) {
+        if !matches!(
+            self.screen,
+            Screen::WaitingForChanges | Screen::DiffView | Screen::NoChanges
+        ) {
+            return;
+        }
+        if self.history.len() < 2 {
+            self.status = Some(StatusMessage::warning(
+                "History needs at least two captures to compare",
+            ));
+            return;
+        }
+        self.history_return = Some(self.screen.clone());
+        self.history_marked = None;
+        self.history_state.select(Some(self.history.len() - 1));
+        self.screen = Screen::History;
+    }
+
+    /// Leave the history screen for the screen it was opened from
+    pub fn close_history(&mut self) {
+        if let Some(screen) = self.history_return.take() {
+            self.screen = screen;
+        }
+    }
+
+    /// Move the history cursor by `delta` entries
+    pub fn move_history(&mut self, delta: isize) {
+        if self.history.is_empty() {
+            return;
+        }
+        let current = self.history_state.selected().unwrap_or(0);
+        let target = (current as isize + delta).clamp(0, self.history.len() as isize - 1);
+        self.history_state.select(Some(target as usize));
+    }
+
+    /// Mark the entry under the cursor; with one already marked, diff the two
+    /// (older against newer)
+    pub fn pick_history_entry(&mut self) {
+        let Some(current) = self.history_state.selected() else {
+            return;
+        };
+        let marked = match self.history_marked {
+            None => {
+                self.history_marked = Some(current);
+                return;
             }
-            Err(e) => {
-                self.screen = Screen::Error(format!("Failed to capture snapshot: {}", e));
+            Some(marked) if marked == current => {
+                self.history_marked = None;
+                return;
+            }
+            Some(marked) => marked,
+        };
+        let (older, newer) = (marked.min(current), marked.max(current));
+        let (before, after) = (&self.history[older], &self.history[newer]);
+        let label = format!("Comparing #{} with #{}", before.number, after.number);
+        let (before, after) = (before.snapshot.clone(), after.snapshot.clone());
+
+        self.history_marked = None;
+        self.history_return = None;
+        self.focus = Focus::Domain;
+        self.load_snapshots(before, after);
+        if let Some(status) = &mut self.status {
+            status.text = format!("{}: {}", label, status.text);
+        }
+    }
+
+    /// Diff two already-loaded snapshots, skipping both captures
+    pub fn load_snapshots(&mut self, before: Snapshot, after: Snapshot) {
+        self.snapshot_before = Some(before);
+        self.snapshot_after = Some(after);
+        self.before_has_apple_services = true;
+        self.detect_changes();
+    }
+
+    /// Store the second snapshot and detect diff
+    fn finish_second_snapshot(&mut self, snapshot: Snapshot, elapsed: Duration) {
+        let count = snapshot.domain_count();
+        let skipped = snapshot.failed_domains.len();
+        self.record_history("capture", &snapshot);
+        self.snapshot_after = Some(snapshot);
+        self.detect_changes();
+        if let Some(status) = &mut self.status {
+            status.text = format!(
+                "{} (captured {} domains in {:.1}s)",
+                status.text,
+                count,
+                elapsed.as_secs_f64()
+            );
+            if skipped > 0 {
+                status.text = format!("{} ({} domains skipped, f to list)", status.text, skipped);
             }
         }
     }
@@ -191,17 +1142,22 @@ impl App {
     /// Detect changes between snapshots
     fn detect_changes(&mut self) {
         if let (Some(before), Some(after)) = (&self.snapshot_before, &self.snapshot_after) {
-            let diff = detect_diff(before, after);
+            let diff = detect_diff(before, after, &self.diff_options);
             let total = diff.total_changes;
 
-            self.diff_result = Some(diff);
-            self.screen = Screen::DiffView;
-            self.domain_list_state.select(Some(0));
-            self.diff_list_state.select(Some(0));
+            // Nothing from the new diff has been copied or exported yet
+            self.exported = false;
+            self.full_diff = Some(diff);
+            self.apply_filters();
+            if let Some((domain, key)) = self.restore_selection.take() {
+                self.select_change(&domain, &key);
+            }
 
             if total == 0 {
+                self.screen = Screen::NoChanges;
                 self.status = Some(StatusMessage::warning("No changes detected"));
             } else {
+                self.screen = Screen::DiffView;
                 self.status = Some(StatusMessage::success(format!(
                     "✓ Found {} change{}",
                     total,
@@ -211,82 +1167,2090 @@ impl App {
         }
     }
 
+    /// Rebuild the visible diff from the full diff and active filters
+    fn apply_filters(&mut self) {
+        self.diff_result = self.full_diff.as_ref().map(|diff| {
+            let mut view = diff.clone();
+            if self.hide_factory_defaults
+                && let Some(baseline) = &self.factory_baseline
+            {
+                view = view.filter_changes(|c| !is_factory_default(c, baseline));
+            }
+            if self.hide_removed_only {
+                view = view.filter_domains(|d| !d.is_removed_only());
+            }
+            if !self.capture_options.include_apple_services {
+                view = view.filter_domains(|d| !is_apple_service_domain(&d.domain));
+            }
+            if let Some(query) = self.filter.as_deref().filter(|q| !q.is_empty()) {
+                let query = query.to_lowercase();
+                view = view.filter_changes(|c| {
+                    c.domain().to_lowercase().contains(&query)
+                        || c.key().to_lowercase().contains(&query)
+                });
+            }
+            if let Some(kind) = self.change_type_filter {
+                view = view.filter_changes(|c| c.kind() == Some(kind));
+            }
+            // The full diff is already sorted by name
+            if self.sort_mode == SortMode::ChangeCountDesc {
+                view.domain_diffs
+                    .sort_by_key(|d| std::cmp::Reverse(d.changes.len()));
+            }
+            for domain_diff in &mut view.domain_diffs {
+                self.change_sort.sort(&mut domain_diff.changes);
+            }
+            view
+        });
+
+        self.selected_domain_index = 0;
+        self.selected_diff_index = 0;
+        self.selected_group = None;
+        self.rebuild_domain_rows();
+        self.clamp_selection();
+    }
+
+    /// Toggle hiding of changes that restore the factory default value
+    pub fn toggle_factory_defaults(&mut self) {
+        if self.factory_baseline.is_none() {
+            self.status = Some(StatusMessage::warning("No factory baseline loaded"));
+            return;
+        }
+        self.hide_factory_defaults = !self.hide_factory_defaults;
+        self.apply_filters();
+        self.status = Some(StatusMessage::info(if self.hide_factory_defaults {
+            "Hiding changes that restore factory defaults"
+        } else {
+            "Showing all changes"
+        }));
+    }
+
+    /// Cycle the domain list order, keeping the selected change selected
+    pub fn cycle_sort_mode(&mut self) {
+        self.sort_mode = self.sort_mode.next();
+        let selected = self.selection_key();
+        self.apply_filters();
+        if let Some((domain, key)) = selected {
+            self.select_change(&domain, &key);
+        }
+        self.status = Some(StatusMessage::info(format!(
+            "Sorting domains by {}",
+            self.sort_mode.label()
+        )));
+    }
+
+    /// Cycle the order of changes within each domain, keeping the selection
+    pub fn cycle_change_sort(&mut self) {
+        self.change_sort = self.change_sort.next();
+        let selected = self.selection_key();
+        self.apply_filters();
+        if let Some((domain, key)) = selected {
+            self.select_change(&domain, &key);
+        }
+        self.status = Some(StatusMessage::info(format!(
+            "Sorting changes by {}",
+            self.change_sort.label()
+        )));
+    }
+
+    /// Show only changes of `kind`, or every change if that filter is already on
+    pub fn toggle_change_type_filter(&mut self, kind: ChangeKind) {
+        self.change_type_filter = if self.change_type_filter == Some(kind) {
+            None
+        } else {
+            Some(kind)
+        };
+        let selected = self.selection_key();
+        self.apply_filters();
+        if let Some((domain, key)) = selected {
+            self.select_change(&domain, &key);
+        }
+        self.status = Some(StatusMessage::info(match self.change_type_filter {
+            Some(kind) => format!("Showing only {} keys", kind.label()),
+            None => "Showing every change".to_string(),
+        }));
+    }
+
+    /// Toggle hiding of domains where every change is a removal
+    pub fn toggle_removed_only(&mut self) {
+        self.hide_removed_only = !self.hide_removed_only;
+        self.apply_filters();
+        self.status = Some(StatusMessage::info(if self.hide_removed_only {
+            "Hiding removed-only domains"
+        } else {
+            "Showing removed-only domains"
+        }));
+    }
+
+    /// Domain under the cursor; `None` on a group header
+    pub fn selected_domain_diff(&self) -> Option<&DomainDiff> {
+        if self.selected_group.is_some() {
+            return None;
+        }
+        self.diff_result
+            .as_ref()?
+            .domain_diffs
+            .get(self.selected_domain_index)
+    }
+
+    /// Get currently selected domain name
+    pub fn selected_domain(&self) -> Option<&str> {
+        self.selected_domain_diff()
+            .map(|domain_diff| domain_diff.domain.as_str())
+    }
+
     /// Get currently selected change
     pub fn selected_change(&self) -> Option<&Change> {
-        self.diff_result
-            .as_ref()
-            .and_then(|diff| diff.domain_diffs.get(self.selected_domain_index))
+        self.selected_domain_diff()
             .and_then(|domain_diff| domain_diff.changes.get(self.selected_diff_index))
     }
 
+    /// Command for the selected change, with sudo for system-scoped domains
+    pub fn selected_command(&self) -> Option<String> {
+        let domain_diff = self.selected_domain_diff()?;
+        let change = domain_diff.changes.get(self.selected_diff_index)?;
+        Some(generate_scoped_command(
+            change,
+            domain_diff.scope,
+            &self.command_options,
+        ))
+    }
+
+    /// Selected change's command followed by its verification read, if enabled
+    pub fn selected_command_with_verify(&self) -> Option<String> {
+        let domain_diff = self.selected_domain_diff()?;
+        let change = domain_diff.changes.get(self.selected_diff_index)?;
+        let command = self.selected_command()?;
+        Some(with_verify(
+            command,
+            change,
+            domain_diff.scope,
+            &self.command_options,
+        ))
+    }
+
+    /// Commands for every change in the selected domain
+    pub fn selected_domain_commands(&self) -> Option<Vec<String>> {
+        let domain_diff = self.selected_domain_diff()?;
+        Some(
+            domain_commands(domain_diff, &self.command_options)
+                .into_iter()
+                .map(|(_, command)| command)
+                .collect(),
+        )
+    }
+
+    /// Command reverting the selected change, with sudo for system-scoped domains
+    pub fn selected_undo_command(&self) -> Option<String> {
+        let domain_diff = self.selected_domain_diff()?;
+        let change = domain_diff.changes.get(self.selected_diff_index)?;
+        Some(apply_scope(
+            apply_host(
+                generate_undo_command(change, &self.command_options),
+                self.command_options.host,
+            ),
+            domain_diff.scope,
+        ))
+    }
+
     /// Move selection up
     pub fn move_up(&mut self) {
-        if self.screen == Screen::DiffView {
-            match self.focus {
-                Focus::Domain => {
-                    if self.selected_domain_index > 0 {
-                        self.selected_domain_index -= 1;
-                        self.selected_diff_index = 0;
-                        self.domain_list_state
-                            .select(Some(self.selected_domain_index));
-                        self.diff_list_state.select(Some(0));
-                    }
-                }
-                Focus::Diff => {
-                    if self.selected_diff_index > 0 {
-                        self.selected_diff_index -= 1;
-                        self.diff_list_state.select(Some(self.selected_diff_index));
-                    }
-                }
-            }
-        }
+        self.move_by(-1);
     }
 
     /// Move selection down
     pub fn move_down(&mut self) {
-        if self.screen == Screen::DiffView
-            && let Some(diff) = &self.diff_result
-        {
-            match self.focus {
-                Focus::Domain => {
-                    if self.selected_domain_index < diff.domain_diffs.len().saturating_sub(1) {
-                        self.selected_domain_index += 1;
-                        self.selected_diff_index = 0;
-                        self.domain_list_state
-                            .select(Some(self.selected_domain_index));
-                        self.diff_list_state.select(Some(0));
-                    }
-                }
-                Focus::Diff => {
-                    if let Some(domain_diff) = diff.domain_diffs.get(self.selected_domain_index)
-                        && self.selected_diff_index < domain_diff.changes.len().saturating_sub(1)
-                    {
-                        self.selected_diff_index += 1;
-                        self.diff_list_state.select(Some(self.selected_diff_index));
-                    }
-                }
-            }
-        }
+        self.move_by(1);
     }
 
-    /// Toggle focus between panes
-    pub fn toggle_focus(&mut self) {
-        if self.screen == Screen::DiffView {
-            self.focus = match self.focus {
-                Focus::Domain => Focus::Diff,
-                Focus::Diff => Focus::Domain,
-            };
-        }
+    /// Move selection up by one screenful
+    pub fn page_up(&mut self) {
+        self.move_by(-(self.page_size as isize));
     }
 
-    /// Check if currently in loading state
-    pub fn is_loading(&self) -> bool {
-        matches!(self.screen, Screen::LoadingFirst | Screen::LoadingSecond)
+    /// Move selection down by one screenful
+    pub fn page_down(&mut self) {
+        self.move_by(self.page_size as isize);
     }
-}
 
-impl Default for App {
-    fn default() -> Self {
-        Self::new()
+    /// Select the first item of the focused list
+    pub fn move_to_top(&mut self) {
+        self.move_by(isize::MIN);
+    }
+
+    /// Select the last item of the focused list
+    pub fn move_to_bottom(&mut self) {
+        self.move_by(isize::MAX);
+    }
+
+    /// Move the focused list's selection by `delta`, stopping at either end
+    fn move_by(&mut self, delta: isize) {
+        if self.screen != Screen::DiffView {
+            return;
+        }
+        let (current, len) = match self.focus {
+            Focus::Domain => (self.selected_row().unwrap_or(0), self.domain_rows.len()),
+            Focus::Diff => (
+                self.selected_diff_index,
+                self.selected_domain_diff().map_or(0, |d| d.changes.len()),
+            ),
+        };
+        if len == 0 {
+            return;
+        }
+        let target = (current as isize)
+            .saturating_add(delta)
+            .clamp(0, len as isize - 1) as usize;
+        if target == current {
+            return;
+        }
+        match self.focus {
+            Focus::Domain => self.select_row(target),
+            Focus::Diff => {
+                self.selected_diff_index = target;
+                self.diff_list_state.select(Some(target));
+            }
+        }
+    }
+
+    /// Focus the list under a click and select the clicked row
+    pub fn click(&mut self, column: u16, row: u16) {
+        if self.screen != Screen::DiffView || self.diff_result.is_none() {
+            return;
+        }
+        if let Some(index) = list_row_at(
+            self.domain_list_area,
+            self.domain_list_state.offset(),
+            column,
+            row,
+        ) {
+            self.focus = Focus::Domain;
+            if index < self.domain_rows.len() && Some(index) != self.selected_row() {
+                self.select_row(index);
+            }
+        } else if let Some(index) = list_row_at(
+            self.diff_list_area,
+            self.diff_list_state.offset(),
+            column,
+            row,
+        ) {
+            let index = skip_expanded_rows(
+                index,
+                self.selected_diff_index,
+                self.selected_change_extra_rows,
+            );
+            self.focus = Focus::Diff;
+            let len = self.selected_domain_diff().map_or(0, |d| d.changes.len());
+            if index < len {
+                self.selected_diff_index = index;
+                self.diff_list_state.select(Some(index));
+            }
+        }
+    }
+
+    /// Move the selection of the list under the pointer by `delta` rows
+    pub fn scroll_at(&mut self, column: u16, row: u16, delta: isize) {
+        if self.screen != Screen::DiffView {
+            return;
+        }
+        let position = Position::new(column, row);
+        if self.domain_list_area.contains(position) {
+            self.focus = Focus::Domain;
+        } else if self.diff_list_area.contains(position) {
+            self.focus = Focus::Diff;
+        } else {
+            return;
+        }
+        self.move_by(delta);
+    }
+
+    /// Select a domain by index, resetting the change selection and
+    /// expanding its group if it is collapsed
+    fn select_domain(&mut self, index: usize) {
+        self.selected_group = None;
+        self.selected_domain_index = index;
+        self.selected_diff_index = 0;
+        if self.selected_row().is_none()
+            && let Some(prefix) = self.selected_domain().and_then(domain_group)
+        {
+            self.expanded_groups.insert(prefix.to_string());
+            self.rebuild_domain_rows();
+        }
+        self.domain_list_state.select(self.selected_row());
+        // Start the new domain's changes scrolled to the top
+        self.diff_list_state = ListState::default().with_selected(Some(0));
+    }
+
+    /// Put the cursor on a row of the domain list
+    fn select_row(&mut self, row: usize) {
+        match self.domain_rows.get(row).cloned() {
+            Some(DomainRow::Group { prefix, .. }) => {
+                // Leaving grouping from here lands on the group's first domain
+                if let Some(index) = self.diff_result.as_ref().and_then(|diff| {
+                    diff.domain_diffs
+                        .iter()
+                        .position(|d| domain_group(&d.domain) == Some(prefix.as_str()))
+                }) {
+                    self.selected_domain_index = index;
+                }
+                self.selected_group = Some(prefix);
+                self.selected_diff_index = 0;
+                self.domain_list_state.select(Some(row));
+                self.diff_list_state = ListState::default();
+            }
+            Some(DomainRow::Domain { index, .. }) => self.select_domain(index),
+            None => {}
+        }
+    }
+
+    /// Row of the domain list under the cursor
+    fn selected_row(&self) -> Option<usize> {
+        self.domain_rows
+            .iter()
+            .position(|row| match (row, &self.selected_group) {
+                (DomainRow::Group { prefix, .. }, Some(selected)) => prefix == selected,
+                (DomainRow::Domain { index, .. }, None) => *index == self.selected_domain_index,
+                _ => false,
+            })
+    }
+
+    /// Rebuild the domain list rows from the visible diff and grouping state
+    fn rebuild_domain_rows(&mut self) {
+        self.domain_rows = self.diff_result.as_ref().map_or_else(Vec::new, |diff| {
+            domain_rows(
+                &diff.domain_diffs,
+                self.group_domains,
+                &self.expanded_groups,
+            )
+        });
+    }
+
+    /// Switch to the next color theme
+    pub fn cycle_theme(&mut self) {
+        self.theme = self.theme.next();
+        self.status = Some(StatusMessage::info(format!(
+            "Color theme: {}",
+            self.theme.label()
+        )));
+    }
+
+    /// Toggle gathering domains under collapsible prefix headers
+    pub fn toggle_domain_grouping(&mut self) {
+        self.group_domains = !self.group_domains;
+        self.selected_group = None;
+        self.rebuild_domain_rows();
+        self.clamp_selection();
+        self.status = Some(StatusMessage::info(if self.group_domains {
+            "Grouping domains by prefix - Enter/Space expands a group"
+        } else {
+            "Listing domains flat"
+        }));
+    }
+
+    /// Expand or collapse the group under the cursor, or the selected domain's group
+    pub fn toggle_group(&mut self) {
+        if self.screen != Screen::DiffView || !self.group_domains {
+            return;
+        }
+        let prefix = match &self.selected_group {
+            Some(prefix) => prefix.clone(),
+            None => match self.selected_domain().and_then(domain_group) {
+                Some(prefix) => prefix.to_string(),
+                None => return,
+            },
+        };
+        let has_header = self
+            .domain_rows
+            .iter()
+            .any(|row| matches!(row, DomainRow::Group { prefix: p, .. } if *p == prefix));
+        if !has_header {
+            return;
+        }
+        if !self.expanded_groups.remove(&prefix) {
+            self.expanded_groups.insert(prefix);
+        } else {
+            // The selected domain is hidden now; keep the cursor on its header
+            self.selected_group = Some(prefix);
+        }
+        self.rebuild_domain_rows();
+        self.clamp_selection();
+    }
+
+    /// Pin the domain and change selection to the visible lists, and clear a
+    /// list's highlight when it is empty. Call after anything that changes
+    /// which domains or changes are shown.
+    fn clamp_selection(&mut self) {
+        let domains = self
+            .diff_result
+            .as_ref()
+            .map_or(0, |diff| diff.domain_diffs.len());
+        self.selected_domain_index = self.selected_domain_index.min(domains.saturating_sub(1));
+        if self.selected_row().is_none() {
+            // The header went away, or the domain is hidden in a collapsed group
+            self.selected_group = None;
+            if self.selected_row().is_none() {
+                self.selected_group = self
+                    .selected_domain()
+                    .and_then(domain_group)
+                    .map(str::to_string);
+            }
+        }
+        let changes = self
+            .selected_domain_diff()
+            .map_or(0, |domain_diff| domain_diff.changes.len());
+        self.selected_diff_index = self.selected_diff_index.min(changes.saturating_sub(1));
+
+        self.domain_list_state.select(self.selected_row());
+        self.diff_list_state
+            .select((changes > 0).then_some(self.selected_diff_index));
+    }
+
+    /// Start a type-ahead jump in the domain list (the `'` key): until the
+    /// keyboard goes idle, typed characters select the first matching domain
+    pub fn start_type_ahead(&mut self) {
+        if self.screen == Screen::DiffView && self.focus == Focus::Domain {
+            self.type_ahead.clear();
+            self.type_ahead_at = Some(Instant::now());
+        }
+    }
+
+    /// Whether typed characters go to the type-ahead rather than being commands
+    pub fn type_ahead_active(&self) -> bool {
+        self.screen == Screen::DiffView
+            && self.focus == Focus::Domain
+            && self
+                .type_ahead_at
+                .is_some_and(|at| at.elapsed() <= TYPE_AHEAD_TIMEOUT)
+    }
+
+    pub fn stop_type_ahead(&mut self) {
+        self.type_ahead_at = None;
+    }
+
+    /// Extend the type-ahead buffer and jump to the first matching domain
+    pub fn type_ahead(&mut self, c: char) {
+        self.type_ahead.push(c);
+        self.type_ahead_at = Some(Instant::now());
+
+        let found = self.diff_result.as_ref().and_then(|diff| {
+            find_type_ahead_match(
+                diff.domain_diffs.iter().map(|d| d.domain.as_str()),
+                &self.type_ahead,
+            )
+        });
+        match found {
+            Some(index) => self.select_domain(index),
+            None => {
+                self.status = Some(StatusMessage::warning(format!(
+                    "No domain matches \"{}\"",
+                    self.type_ahead
+                )));
+            }
+        }
+    }
+
+    /// Show the selected change's full values in a popup
+    pub fn open_value_popup(&mut self) {
+        if self.screen == Screen::DiffView && self.focus == Focus::Diff {
+            self.value_popup = self.selected_change().cloned();
+            self.value_popup_scroll = 0;
+        }
+    }
+
+    /// Scroll the value popup by `delta` lines
+    pub fn scroll_value_popup(&mut self, delta: i32) {
+        self.value_popup_scroll =
+            scrolled(self.value_popup_scroll, delta, self.value_popup_max_scroll);
+    }
+
+    pub fn close_value_popup(&mut self) {
+        self.value_popup = None;
+    }
+
+    /// Toggle the popup listing every key of the selected domain, changed or not
+    pub fn toggle_all_keys(&mut self) {
+        if self.all_keys_domain.take().is_some() || self.screen != Screen::DiffView {
+            return;
+        }
+        self.all_keys_domain = self.selected_domain().map(str::to_string);
+        self.all_keys_scroll = 0;
+    }
+
+    /// Scroll the all-keys popup by `delta` lines
+    pub fn scroll_all_keys(&mut self, delta: i32) {
+        self.all_keys_scroll = scrolled(self.all_keys_scroll, delta, self.all_keys_max_scroll);
+    }
+
+    /// Keys and values of the all-keys domain in the second snapshot, each
+    /// with whether it changed. `None` when that snapshot lacks the domain.
+    pub fn all_keys(&self) -> Option<Vec<(&str, &plist::Value, bool)>> {
+        let domain = self.all_keys_domain.as_deref()?;
+        let settings = self.snapshot_after.as_ref()?.domains.get(domain)?;
+        let changed: HashSet<&str> = self
+            .full_diff
+            .iter()
+            .flat_map(|diff| &diff.domain_diffs)
+            .filter(|d| d.domain == domain)
+            .flat_map(|d| d.changes.iter().map(Change::root_key))
+            .collect();
+        Some(
+            settings
+                .values
+                .iter()
+                .map(|(key, value)| (key.as_str(), value, changed.contains(key.as_str())))
+                .collect(),
+        )
+    }
+
+    /// Open the filter input line, keeping any active query
+    pub fn start_filter(&mut self) {
+        if self.screen != Screen::DiffView {
+            return;
+        }
+        self.filter.get_or_insert_with(String::new);
+        self.input_mode = Some(InputMode::Filter);
+    }
+
+    /// Append a character to the filter query
+    pub fn push_filter_char(&mut self, c: char) {
+        if let Some(query) = &mut self.filter {
+            query.push(c);
+            self.apply_filters();
+        }
+    }
+
+    /// Remove the last character of the filter query
+    pub fn pop_filter_char(&mut self) {
+        if let Some(query) = &mut self.filter {
+            query.pop();
+            self.apply_filters();
+        }
+    }
+
+    /// Close the input line, keeping the query applied
+    pub fn finish_filter(&mut self) {
+        self.input_mode = None;
+        if self.filter.as_deref() == Some("") {
+            self.filter = None;
+        }
+    }
+
+    /// Drop the filter and show every change again
+    pub fn clear_filter(&mut self) {
+        self.filter = None;
+        self.input_mode = None;
+        self.apply_filters();
+    }
+
+    /// Open the input line to type the single domain both captures read
+    pub fn start_domain_scope(&mut self) {
+        if self.screen != Screen::Initial {
+            return;
+        }
+        self.domain_input = self.capture_options.domain.clone().unwrap_or_default();
+        self.input_mode = Some(InputMode::DomainScope);
+    }
+
+    /// Scope captures to the typed domain, or to every domain if it is blank
+    fn finish_domain_scope(&mut self) {
+        self.input_mode = None;
+        let domain = self.domain_input.trim();
+        self.capture_options.domain = (!domain.is_empty()).then(|| domain.to_string());
+        self.status = Some(StatusMessage::info(match &self.capture_options.domain {
+            Some(domain) => format!("Capturing only {}", domain),
+            None => "Capturing every domain".to_string(),
+        }));
+    }
+
+    /// Open the input line to jump to a domain by typing part of its name
+    pub fn start_jump(&mut self) {
+        if self.screen != Screen::DiffView {
+            return;
+        }
+        self.jump_query.clear();
+        self.jump_match = None;
+        self.jump_origin = self.selected_domain_index;
+        self.focus = Focus::Domain;
+        self.input_mode = Some(InputMode::Jump);
+    }
+
+    /// Select the domain best matching the jump query
+    fn update_jump(&mut self) {
+        let Some(diff) = &self.diff_result else {
+            return;
+        };
+        self.jump_match = if self.jump_query.is_empty() {
+            None
+        } else {
+            find_fuzzy_match(
+                diff.domain_diffs.iter().map(|d| d.domain.as_str()),
+                &self.jump_query,
+            )
+        };
+        let target = self.jump_match.unwrap_or(self.jump_origin);
+        if target != self.selected_domain_index || self.selected_group.is_some() {
+            self.select_domain(target);
+        }
+    }
+
+    /// Go back to the domain selected before the jump
+    fn cancel_jump(&mut self) {
+        self.input_mode = None;
+        if self.selected_domain_index != self.jump_origin || self.selected_group.is_some() {
+            self.select_domain(self.jump_origin);
+        }
+    }
+
+    /// Text currently shown in the input line
+    pub fn input_text(&self) -> &str {
+        match self.input_mode {
+            Some(InputMode::Filter) => self.filter.as_deref().unwrap_or_default(),
+            Some(InputMode::DomainScope) => &self.domain_input,
+            Some(InputMode::Jump) => &self.jump_query,
+            None => "",
+        }
+    }
+
+    /// Type a character into the open input line
+    pub fn push_input_char(&mut self, c: char) {
+        match self.input_mode {
+            Some(InputMode::Filter) => self.push_filter_char(c),
+            Some(InputMode::DomainScope) => self.domain_input.push(c),
+            Some(InputMode::Jump) => {
+                self.jump_query.push(c);
+                self.update_jump();
+            }
+            None => {}
+        }
+    }
+
+    /// Delete the last character of the open input line
+    pub fn pop_input_char(&mut self) {
+        match self.input_mode {
+            Some(InputMode::Filter) => self.pop_filter_char(),
+            Some(InputMode::DomainScope) => {
+                self.domain_input.pop();
+            }
+            Some(InputMode::Jump) => {
+                self.jump_query.pop();
+                self.update_jump();
+            }
+            None => {}
+        }
+    }
+
+    /// Accept the input line (Enter)
+    pub fn submit_input(&mut self) {
+        match self.input_mode {
+            Some(InputMode::Filter) => self.finish_filter(),
+            Some(InputMode::DomainScope) => self.finish_domain_scope(),
+            Some(InputMode::Jump) => self.input_mode = None,
+            None => {}
+        }
+    }
+
+    /// Abandon the input line (Esc); the filter is cleared, the domain scope
+    /// kept, and a jump returns to the previous domain
+    pub fn cancel_input(&mut self) {
+        match self.input_mode {
+            Some(InputMode::Filter) => self.clear_filter(),
+            Some(InputMode::DomainScope) => self.input_mode = None,
+            Some(InputMode::Jump) => self.cancel_jump(),
+            None => {}
+        }
+    }
+
+    /// Switch the preview between command and value summary
+    pub fn toggle_preview_mode(&mut self) {
+        self.preview_mode = match self.preview_mode {
+            PreviewMode::Command => PreviewMode::Summary,
+            PreviewMode::Summary => PreviewMode::Command,
+        };
+    }
+
+    /// Toggle focus between panes
+    pub fn toggle_focus(&mut self) {
+        if self.screen == Screen::DiffView {
+            self.focus = match self.focus {
+                Focus::Domain => Focus::Diff,
+                Focus::Diff => Focus::Domain,
+            };
+        }
+    }
+
+    /// Toggle delta comments on numeric changes in generated commands
+    pub fn toggle_delta(&mut self) {
+        self.command_options.show_delta = !self.command_options.show_delta;
+        self.status = Some(StatusMessage::info(if self.command_options.show_delta {
+            "Delta comments enabled"
+        } else {
+            "Delta comments disabled"
+        }));
+    }
+
+    /// Toggle a `defaults read` after each write in copied and exported commands
+    pub fn toggle_verify(&mut self) {
+        self.command_options.verify = !self.command_options.verify;
+        self.status = Some(StatusMessage::info(if self.command_options.verify {
+            "Verification reads enabled"
+        } else {
+            "Verification reads disabled"
+        }));
+    }
+
+    /// Flip between global and `-currentHost` commands
+    pub fn toggle_command_host(&mut self) {
+        self.command_options.host = self.command_options.host.toggle();
+        self.status = Some(StatusMessage::info(match self.command_options.host {
+            CommandScope::Global => "Commands target all hosts",
+            CommandScope::CurrentHost => "Commands target the current host (-currentHost)",
+        }));
+    }
+
+    /// Switch nested changes between `plutil` edits and whole-key `defaults` writes
+    pub fn toggle_plutil(&mut self) {
+        self.command_options.plutil = !self.command_options.plutil;
+        self.status = Some(StatusMessage::info(if self.command_options.plutil {
+            "Nested changes use plutil on the plist file"
+        } else {
+            "Nested changes rewrite the top-level key with defaults"
+        }));
+    }
+
+    /// Switch between double- and single-quoted shell arguments
+    pub fn toggle_quote_style(&mut self) {
+        self.command_options.quote = self.command_options.quote.toggle();
+        self.status = Some(StatusMessage::info(format!(
+            "Commands use {}-quoted arguments",
+            self.command_options.quote.label()
+        )));
+    }
+
+    /// Queue an export (runs after the "Exporting..." status is drawn)
+    pub fn start_export(&mut self, action: ExportAction) {
+        if self.reject_if_read_only("Export") {
+            return;
+        }
+        if self.screen == Screen::DiffView && self.diff_result.is_some() {
+            self.pending_export = Some(action);
+            self.status = Some(StatusMessage::info("Exporting..."));
+        }
+    }
+
+    /// Ask for confirmation before running the selected change's command
+    pub fn request_apply(&mut self) {
+        if self.reject_if_read_only("Apply") || self.reject_if_other_user("Apply") {
+            return;
+        }
+        if self.screen != Screen::DiffView || self.focus != Focus::Diff {
+            return;
+        }
+        match self.selected_command() {
+            // Normalized and whole-domain changes get only a `# ...` note, which
+            // would "succeed" without writing anything
+            Some(command)
+                if command
+                    .lines()
+                    .all(|line| line.trim_start().starts_with('#')) =>
+            {
+                self.status = Some(StatusMessage::warning(
+                    "Nothing to apply: this change has no command",
+                ));
+            }
+            command => self.pending_apply = command,
+        }
+    }
+
+    /// Run the confirmed command and mark the selected change as applied
+    pub fn confirm_apply(&mut self) {
+        let Some(command) = self.pending_apply.take() else {
+            return;
+        };
+        self.status = Some(match run_command(&command) {
+            Ok(()) => {
+                self.exported = true;
+                if let Some(change) = self.selected_change() {
+                    let applied = (change.domain().to_string(), change.key().to_string());
+                    self.applied.insert(applied);
+                }
+                StatusMessage::success("✓ Applied change")
+            }
+            Err(e) => StatusMessage::warning(format!("Apply failed: {}", e)),
+        });
+    }
+
+    pub fn cancel_apply(&mut self) {
+        self.pending_apply = None;
+        self.status = Some(StatusMessage::info("Apply cancelled"));
+    }
+
+    /// Queue the selected domain's plist file for the editor
+    pub fn open_in_editor(&mut self) {
+        if self.reject_if_read_only("Editing") || self.reject_if_other_user("Editing") {
+            return;
+        }
+        let Some(domain) = self.selected_domain() else {
+            return;
+        };
+        // An override path is the file the commands write to
+        let domain = self
+            .command_options
+            .plist_paths
+            .get(domain)
+            .map_or(domain, String::as_str);
+        let current_host = self.command_options.host == CommandScope::CurrentHost;
+        let resolved = std::env::var_os("HOME")
+            .ok_or_else(|| anyhow::anyhow!("HOME is not set"))
+            .and_then(|home| domain_plist_path(domain, current_host, Path::new(&home)));
+        match resolved {
+            Ok(path) => self.pending_edit = Some(path),
+            Err(e) => self.status = Some(StatusMessage::warning(format!("Cannot edit: {}", e))),
+        }
+    }
+
+    /// Report how the editor session ended
+    pub fn finish_edit(&mut self, path: &Path, result: anyhow::Result<()>) {
+        self.status = Some(match result {
+            Ok(()) => StatusMessage::info(format!(
+                "Edited {}; run `killall cfprefsd` so apps see it, then Ctrl+r to re-capture",
+                path.display()
+            )),
+            Err(e) => StatusMessage::warning(format!("Could not open {}: {}", path.display(), e)),
+        });
+    }
+
+    /// Whether a change was applied from the diff view
+    pub fn is_applied(&self, change: &Change) -> bool {
+        self.applied
+            .contains(&(change.domain().to_string(), change.key().to_string()))
+    }
+
+    /// Refuse a mutating action in read-only mode, explaining why in the status bar
+    pub fn reject_if_read_only(&mut self, action: &str) -> bool {
+        if self.read_only {
+            self.status = Some(StatusMessage::warning(format!(
+                "{} is disabled in read-only mode",
+                action
+            )));
+        }
+        self.read_only
+    }
+
+    /// Refuse an action that would change the current user's defaults when the
+    /// diff is of another user's (`--user`)
+    pub fn reject_if_other_user(&mut self, action: &str) -> bool {
+        let Some(user) = &self.capture_options.user else {
+            return false;
+        };
+        self.status = Some(StatusMessage::warning(format!(
+            "{} is disabled while viewing {}'s defaults; run the copied command as {}",
+            action, user, user
+        )));
+        true
+    }
+
+    /// Execute the pending export (called from main loop)
+    pub fn execute_export(&mut self) {
+        match self.pending_export.take() {
+            Some(ExportAction::DomainBundle) => self.export_domain_bundle(),
+            Some(ExportAction::Script) => self.export_script(),
+            Some(ExportAction::Report) => self.export_report(),
+            None => {}
+        }
+    }
+
+    /// Write one script per changed domain to a directory in $HOME
+    fn export_domain_bundle(&mut self) {
+        let Some(diff) = &self.diff_result else {
+            return;
+        };
+        let dir = export::home_dir().join(format!("defaults-util-export-{}", export::timestamp()));
+
+        let mut progress = (0, diff.domain_diffs.len());
+        let result = write_domain_bundle(diff, &dir, &self.command_options, |written, total, _| {
+            progress = (written, total);
+        });
+
+        match result {
+            Ok(files) => {
+                self.exported = true;
+                self.status = Some(StatusMessage::success(format!(
+                    "✓ Exported {} file{} to {}",
+                    files.len(),
+                    if files.len() == 1 { "" } else { "s" },
+                    dir.display()
+                )));
+            }
+            Err(e) => {
+                self.status = Some(StatusMessage::warning(format!(
+                    "Export failed after {}/{} files: {}",
+                    progress.0, progress.1, e
+                )));
+            }
+        }
+    }
+
+    /// Write the whole diff to `~/defaults-util-<timestamp>.sh`
+    fn export_script(&mut self) {
+        let Some(diff) = &self.diff_result else {
+            return;
+        };
+        let path = export::home_dir().join(format!("defaults-util-{}.sh", export::timestamp()));
+
+        self.status = Some(match write_script(diff, &path, &self.command_options) {
+            Ok(()) => {
+                self.exported = true;
+                StatusMessage::success(format!("✓ Saved script to {}", path.display()))
+                    .with_lifetime(PATH_STATUS_TIMEOUT)
+            }
+            Err(e) => StatusMessage::warning(format!("Script export failed: {}", e)),
+        });
+    }
+
+    /// Write the diff as a Markdown report to `~/defaults-util-report.md`
+    fn export_report(&mut self) {
+        let Some(diff) = &self.diff_result else {
+            return;
+        };
+        let path = export::home_dir().join("defaults-util-report.md");
+
+        self.status = Some(match std::fs::write(&path, export::export_markdown(diff)) {
+            Ok(()) => {
+                self.exported = true;
+                StatusMessage::success(format!("✓ Saved report to {}", path.display()))
+                    .with_lifetime(PATH_STATUS_TIMEOUT)
+            }
+            Err(e) => StatusMessage::warning(format!("Report export failed: {}", e)),
+        });
+    }
+
+    /// Toggle keys-only diffing (ignore value changes) and re-run the diff
+    pub fn toggle_keys_only(&mut self) {
+        self.diff_options.keys_only = !self.diff_options.keys_only;
+        if matches!(self.screen, Screen::DiffView | Screen::NoChanges) {
+            self.detect_changes();
+        }
+        self.status = Some(StatusMessage::info(if self.diff_options.keys_only {
+            "Keys-only diff: showing added/removed keys"
+        } else {
+            "Full diff: showing value changes"
+        }));
+    }
+
+    /// Most recent snapshot (after if captured, otherwise before)
+    pub fn latest_snapshot(&self) -> Option<&Snapshot> {
+        self.snapshot_after
+            .as_ref()
+            .or(self.snapshot_before.as_ref())
+    }
+
+    /// Toggle the snapshot statistics overlay
+    pub fn toggle_stats(&mut self) {
+        if self.latest_snapshot().is_some() {
+            self.show_stats = !self.show_stats;
+        } else {
+            self.status = Some(StatusMessage::warning("No snapshot captured yet"));
+        }
+    }
+
+    /// Toggle the overlay listing domains the latest capture skipped
+    pub fn toggle_failed_domains(&mut self) {
+        match self.latest_snapshot() {
+            Some(snapshot) if !snapshot.failed_domains.is_empty() => {
+                self.show_failed_domains = !self.show_failed_domains;
+            }
+            Some(_) => {
+                self.status = Some(StatusMessage::info("Every domain was captured"));
+            }
+            None => {
+                self.status = Some(StatusMessage::warning("No snapshot captured yet"));
+            }
+        }
+    }
+
+    /// Toggle showing differences that normalization treats as equal
+    pub fn toggle_show_normalized(&mut self) {
+        self.diff_options.show_normalized = !self.diff_options.show_normalized;
+        if matches!(self.screen, Screen::DiffView | Screen::NoChanges) {
+            self.detect_changes();
+        }
+        self.status = Some(StatusMessage::info(if self.diff_options.show_normalized {
+            "Showing normalized-away differences"
+        } else {
+            "Hiding normalized-away differences"
+        }));
+    }
+
+    /// Toggle showing changes the ignore list would hide
+    pub fn toggle_show_ignored(&mut self) {
+        if self.diff_options.ignore.is_empty() {
+            self.status = Some(StatusMessage::info("Ignore list is empty"));
+            return;
+        }
+        self.diff_options.show_ignored = !self.diff_options.show_ignored;
+        if matches!(self.screen, Screen::DiffView | Screen::NoChanges) {
+            self.detect_changes();
+        }
+        self.status = Some(StatusMessage::info(if self.diff_options.show_ignored {
+            "Showing ignored domains and keys"
+        } else {
+            "Hiding ignored domains and keys"
+        }));
+    }
+
+    /// Toggle `# === <domain> ===` headers when copying all commands
+    pub fn toggle_domain_headers(&mut self) {
+        self.group_commands_by_domain = !self.group_commands_by_domain;
+        self.status = Some(StatusMessage::info(if self.group_commands_by_domain {
+            "Domain header comments enabled"
+        } else {
+            "Domain header comments disabled"
+        }));
+    }
+
+    /// Check if currently in loading state
+    pub fn is_loading(&self) -> bool {
+        matches!(self.screen, Screen::LoadingFirst | Screen::LoadingSecond)
+    }
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Popup scroll offset moved by `delta` lines and kept within `0..=max`
+fn scrolled(offset: u16, delta: i32, max: u16) -> u16 {
+    let offset = i32::from(offset)
+        .saturating_add(delta)
+        .clamp(0, i32::from(max));
+    u16::try_from(offset).unwrap_or(max)
+}
+
+/// Item index under a point in a bordered list drawn at `area` and scrolled by `offset`
+fn list_row_at(area: Rect, offset: usize, column: u16, row: u16) -> Option<usize> {
+    let inner = Rect {
+        x: area.x + 1,
+        y: area.y + 1,
+        width: area.width.saturating_sub(2),
+        height: area.height.saturating_sub(2),
+    };
+    inner
+        .contains(Position::new(column, row))
+        .then(|| offset + usize::from(row - inner.y))
+}
+
+/// Item under a row index from `list_row_at` when the selected item is drawn
+/// `extra` rows taller than the others
+fn skip_expanded_rows(row_index: usize, selected: usize, extra: usize) -> usize {
+    if row_index <= selected {
+        row_index
+    } else {
+        row_index.saturating_sub(extra).max(selected)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::defaults::types::Scope;
+    use crate::diff::types::DomainDiff;
+
+    pub(crate) fn diff_view_app(domains: &[&str]) -> App {
+        let domain_diffs: Vec<DomainDiff> = domains
+            .iter()
+            .map(|domain| DomainDiff {
+                domain: domain.to_string(),
+                changes: vec![Change::Added {
+                    domain: domain.to_string(),
+                    key: "key".to_string(),
+                    value: plist::Value::Boolean(true),
+                }],
+                scope: Scope::User,
+            })
+            .collect();
+        let mut app = App::new();
+        app.full_diff = Some(DiffResult {
+            total_changes: domain_diffs.len(),
+            domain_diffs,
+        });
+        app.screen = Screen::DiffView;
+        app.apply_filters();
+        app
+    }
+
+    #[test]
+    fn test_skip_expanded_rows() {
+        // Item 1 is selected and drawn on three rows (1, 2, 3)
+        assert_eq!(skip_expanded_rows(0, 1, 2), 0);
+        assert_eq!(skip_expanded_rows(1, 1, 2), 1);
+        assert_eq!(skip_expanded_rows(3, 1, 2), 1);
+        assert_eq!(skip_expanded_rows(4, 1, 2), 2);
+        assert_eq!(skip_expanded_rows(4, 1, 0), 4);
+    }
+
+    #[test]
+    fn test_domain_rows_group_by_prefix() {
+        let app = diff_view_app(&[
+            "NSGlobalDomain",
+            "com.apple.dock",
+            "com.apple.finder",
+            "org.x.y",
+        ]);
+        let diff = app.diff_result.as_ref().unwrap();
+        let domain = |index, nested| DomainRow::Domain { index, nested };
+
+        assert_eq!(
+            domain_rows(&diff.domain_diffs, true, &HashSet::new()),
+            vec![
+                domain(0, false),
+                DomainRow::Group {
+                    prefix: "com.apple".to_string(),
+                    domains: 2,
+                    changes: 2,
+                    expanded: false,
+                },
+                domain(3, false),
+            ]
+        );
+        let expanded = HashSet::from(["com.apple".to_string()]);
+        let rows = domain_rows(&diff.domain_diffs, true, &expanded);
+        assert_eq!(
+            rows[2..],
+            [domain(1, true), domain(2, true), domain(3, false)]
+        );
+        assert_eq!(domain_rows(&diff.domain_diffs, false, &expanded).len(), 4);
+    }
+
+    #[test]
+    fn test_toggle_group() {
+        let mut app = diff_view_app(&["com.apple.dock", "com.apple.finder", "org.x.y"]);
+        app.toggle_domain_grouping();
+        // The first domain is hidden, so the cursor sits on its header
+        assert_eq!(app.selected_group.as_deref(), Some("com.apple"));
+        assert!(app.selected_change().is_none());
+        assert_eq!(app.domain_list_state.selected(), Some(0));
+
+        app.toggle_group();
+        app.move_down();
+        assert_eq!(app.selected_domain(), Some("com.apple.dock"));
+        assert_eq!(app.domain_list_state.selected(), Some(1));
+
+        // Collapsing from a member puts the cursor back on the header
+        app.toggle_group();
+        assert_eq!(app.selected_group.as_deref(), Some("com.apple"));
+        app.move_down();
+        assert_eq!(app.selected_domain(), Some("org.x.y"));
+
+        // Jumping to a hidden domain expands its group
+        app.select_change("com.apple.finder", "key");
+        assert!(app.expanded_groups.contains("com.apple"));
+        assert_eq!(app.domain_list_state.selected(), Some(2));
+    }
+
+    #[test]
+    fn test_clamp_selection() {
+        let mut app = diff_view_app(&["com.a", "com.b"]);
+        app.selected_domain_index = 5;
+        app.selected_diff_index = 3;
+        app.clamp_selection();
+        assert_eq!(app.selected_domain(), Some("com.b"));
+        assert_eq!(app.selected_diff_index, 0);
+        assert_eq!(app.domain_list_state.selected(), Some(1));
+
+        // Nothing visible: no highlight is left behind
+        app.filter = Some("nothing".to_string());
+        app.apply_filters();
+        assert!(app.selected_change().is_none());
+        assert_eq!(app.domain_list_state.selected(), None);
+        assert_eq!(app.diff_list_state.selected(), None);
+    }
+
+    #[test]
+    fn test_new_diff_is_not_exported() {
+        let mut app = App::new();
+        app.load_snapshots(
+            snapshot_with(&[("com.a", &[("x", 1)])]),
+            snapshot_with(&[("com.a", &[("x", 2)])]),
+        );
+        app.exported = true;
+        app.detect_changes();
+        assert!(!app.exported);
+        app.request_quit();
+        assert!(app.confirm_quit);
+    }
+
+    #[test]
+    fn test_quit_asks_until_something_is_exported() {
+        let mut app = diff_view_app(&["com.a"]);
+        app.request_quit();
+        assert!(app.confirm_quit);
+        assert!(!app.should_quit);
+
+        app.cancel_quit();
+        app.exported = true;
+        app.request_quit();
+        assert!(!app.confirm_quit);
+        assert!(app.should_quit);
+
+        let mut app = App::new();
+        app.request_quit();
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn test_status_lifetime() {
+        let mut info = StatusMessage::info("x");
+        let mut warning = StatusMessage::warning("x");
+        info.created_at = Instant::now() - Duration::from_secs(4);
+        warning.created_at = info.created_at;
+        // Warnings outlive info messages of the same age
+        assert!(!info.is_valid(DEFAULT_STATUS_TIMEOUT));
+        assert!(warning.is_valid(DEFAULT_STATUS_TIMEOUT));
+        assert!(info.is_valid(Duration::from_secs(5)));
+
+        let pinned = StatusMessage {
+            created_at: info.created_at,
+            ..StatusMessage::info("x").with_lifetime(Duration::from_secs(1))
+        };
+        assert!(!pinned.is_valid(Duration::from_secs(60)));
+
+        // A huge timeout doesn't overflow when doubled for warnings
+        assert!(warning.is_valid(Duration::MAX));
+    }
+
+    #[test]
+    fn test_list_row_at() {
+        let area = Rect::new(0, 2, 20, 6);
+        // The border rows and columns are not items
+        assert_eq!(list_row_at(area, 0, 5, 2), None);
+        assert_eq!(list_row_at(area, 0, 0, 3), None);
+        assert_eq!(list_row_at(area, 0, 5, 3), Some(0));
+        assert_eq!(list_row_at(area, 4, 5, 6), Some(7));
+        assert_eq!(list_row_at(area, 0, 5, 7), None);
+    }
+
+    #[test]
+    fn test_click_selects_rows() {
+        let mut app = diff_view_app(&["com.a", "com.b", "com.c"]);
+        app.domain_list_area = Rect::new(0, 0, 20, 10);
+        app.diff_list_area = Rect::new(20, 0, 40, 10);
+
+        app.click(5, 3);
+        assert_eq!(app.focus, Focus::Domain);
+        assert_eq!(app.selected_domain_index, 2);
+
+        app.click(30, 1);
+        assert_eq!(app.focus, Focus::Diff);
+        assert_eq!(app.selected_diff_index, 0);
+
+        app.scroll_at(5, 5, -1);
+        assert_eq!(app.focus, Focus::Domain);
+        assert_eq!(app.selected_domain_index, 1);
+
+        // Rows past the end of the list are ignored
+        app.click(5, 8);
+        assert_eq!(app.selected_domain_index, 1);
+    }
+
+    #[test]
+    fn test_find_fuzzy_match() {
+        let domains = [
+            "com.apple.dock",
+            "com.apple.finder",
+            "com.apple.screencapture",
+            "org.mozilla.firefox",
+        ];
+        let find = |query| find_fuzzy_match(domains.iter().copied(), query);
+        assert_eq!(find("dock"), Some(0));
+        assert_eq!(find("finder"), Some(1));
+        assert_eq!(find("scrcap"), Some(2));
+        assert_eq!(find("FIREF"), Some(3));
+        // Consecutive letters beat scattered ones ("fi" is contiguous in both;
+        // "fire" is only contiguous in firefox)
+        assert_eq!(find("fire"), Some(3));
+        assert_eq!(find("xyz"), None);
+    }
+
+    #[test]
+    fn test_jump_selects_and_cancel_restores() {
+        let mut app = diff_view_app(&["com.apple.dock", "com.apple.finder", "org.mozilla.firefox"]);
+        app.start_jump();
+        for c in "moz".chars() {
+            app.push_input_char(c);
+        }
+        assert_eq!(app.selected_domain_index, 2);
+        assert_eq!(app.jump_match, Some(2));
+
+        app.cancel_input();
+        assert_eq!(app.selected_domain_index, 0);
+
+        app.start_jump();
+        for c in "finder".chars() {
+            app.push_input_char(c);
+        }
+        app.submit_input();
+        assert!(app.input_mode.is_none());
+        assert_eq!(app.selected_domain_index, 1);
+    }
+
+    #[test]
+    fn test_find_type_ahead_match() {
+        let domains = ["com.apple.dock", "com.apple.finder", "org.mozilla.firefox"];
+        let iter = || domains.iter().copied();
+        assert_eq!(find_type_ahead_match(iter(), "org"), Some(2));
+        assert_eq!(find_type_ahead_match(iter(), "com.apple.f"), Some(1));
+        // Falls back to substring match
+        assert_eq!(find_type_ahead_match(iter(), "FINDER"), Some(1));
+        assert_eq!(find_type_ahead_match(iter(), "safari"), None);
+    }
+
+    #[test]
+    fn test_type_ahead_ends_when_idle() {
+        let mut app = diff_view_app(&["com.apple.dock", "com.apple.finder"]);
+        assert!(!app.type_ahead_active());
+        app.start_type_ahead();
+        assert!(app.type_ahead_active());
+
+        app.type_ahead_at = Instant::now().checked_sub(TYPE_AHEAD_TIMEOUT * 2);
+        assert!(!app.type_ahead_active());
+
+        // Only the domain list takes type-ahead
+        app.toggle_focus();
+        app.start_type_ahead();
+        assert!(!app.type_ahead_active());
+    }
+
+    #[test]
+    fn test_filter_matches_domain_or_key() {
+        let mut app = diff_view_app(&["com.apple.dock", "com.apple.finder"]);
+        app.start_filter();
+        for c in "FINDER".chars() {
+            app.push_filter_char(c);
+        }
+        assert_eq!(app.diff_result.as_ref().unwrap().domain_diffs.len(), 1);
+        assert_eq!(app.selected_domain(), Some("com.apple.finder"));
+
+        // Every change has the key "key"
+        app.clear_filter();
+        app.start_filter();
+        app.push_filter_char('k');
+        assert_eq!(app.diff_result.as_ref().unwrap().total_changes, 2);
+        app.push_filter_char('z');
+        assert_eq!(app.diff_result.as_ref().unwrap().total_changes, 0);
+        app.pop_filter_char();
+        app.finish_filter();
+        assert!(app.input_mode.is_none());
+        assert_eq!(app.filter.as_deref(), Some("k"));
+
+        app.clear_filter();
+        assert!(app.filter.is_none());
+        assert_eq!(app.diff_result.as_ref().unwrap().total_changes, 2);
+    }
+
+    #[test]
+    fn test_capture_progress_ratio() {
+        let progress = |done, total| CaptureProgress {
+            done,
+            total,
+            domain: String::new(),
+        };
+        assert_eq!(progress(0, 0).ratio(), 0.0);
+        assert_eq!(progress(1, 4).ratio(), 0.25);
+        assert_eq!(progress(5, 4).ratio(), 1.0);
+    }
+
+    #[test]
+    fn test_domain_scope_input() {
+        let mut app = App::new();
+        app.start_domain_scope();
+        assert_eq!(app.input_mode, Some(InputMode::DomainScope));
+        for c in " com.apple.dockx".chars() {
+            app.push_input_char(c);
+        }
+        app.pop_input_char();
+        assert_eq!(app.input_text(), " com.apple.dock");
+        app.submit_input();
+        assert_eq!(
+            app.capture_options.domain.as_deref(),
+            Some("com.apple.dock")
+        );
+
+        // Esc keeps the scope, a blank entry removes it
+        app.start_domain_scope();
+        app.push_input_char('x');
+        app.cancel_input();
+        assert_eq!(
+            app.capture_options.domain.as_deref(),
+            Some("com.apple.dock")
+        );
+        app.start_domain_scope();
+        app.domain_input.clear();
+        app.submit_input();
+        assert!(app.capture_options.domain.is_none());
+    }
+
+    fn snapshot_with(domains: &[(&str, &[(&str, i64)])]) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        for (domain, kvs) in domains {
+            let values = kvs
+                .iter()
+                .map(|(k, v)| (k.to_string(), plist::Value::Integer((*v).into())))
+                .collect();
+            snapshot.domains.insert(
+                domain.to_string(),
+                crate::defaults::types::DomainSettings { values },
+            );
+        }
+        snapshot
+    }
+
+    /// A job whose worker already sent `result`, without running `defaults`
+    fn finished_job(result: anyhow::Result<Option<Snapshot>>, watch: bool) -> CaptureJob {
+        let (sender, receiver) = mpsc::channel();
+        let (_, progress) = mpsc::channel();
+        sender.send(result).unwrap();
+        CaptureJob {
+            cancel: Arc::new(AtomicBool::new(false)),
+            receiver,
+            progress,
+            started: Instant::now(),
+            watch,
+        }
+    }
+
+    #[test]
+    fn test_watch_result_dropped_off_the_diff_screens() {
+        let mut app = App::new();
+        app.load_snapshots(
+            snapshot_with(&[("com.b", &[("x", 1)])]),
+            snapshot_with(&[("com.b", &[("x", 2)])]),
+        );
+        app.watch = true;
+        app.screen = Screen::History;
+        app.capture_job = Some(finished_job(
+            Ok(Some(snapshot_with(&[("com.b", &[("x", 3)])]))),
+            true,
+        ));
+        app.poll_capture();
+        assert_eq!(app.screen, Screen::History);
+        assert!(app.capture_job.is_none());
+        let after = app.snapshot_after.as_ref().unwrap();
+        assert_eq!(
+            after.domains["com.b"].values["x"],
+            plist::Value::Integer(2.into())
+        );
+    }
+
+    #[test]
+    fn test_reset_cancels_watch_capture() {
+        let mut app = diff_view_app(&["com.a"]);
+        app.watch = true;
+        let (_sender, receiver) = mpsc::channel();
+        let (_, progress) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        app.capture_job = Some(CaptureJob {
+            cancel: Arc::clone(&cancel),
+            receiver,
+            progress,
+            started: Instant::now(),
+            watch: true,
+        });
+        app.reset();
+        assert!(cancel.load(Ordering::Relaxed));
+        assert!(app.capture_job.is_none());
+        assert!(!app.watch);
+    }
+
+    #[test]
+    fn test_watch_capture_keeps_selection() {
+        let mut app = App::new();
+        app.snapshot_before = Some(snapshot_with(&[("com.b", &[("x", 1), ("y", 1)])]));
+        app.screen = Screen::WaitingForChanges;
+        app.toggle_watch();
+        assert!(app.watch);
+
+        app.finish_watch_capture(snapshot_with(&[("com.b", &[("x", 2), ("y", 2)])]));
+        assert_eq!(app.screen, Screen::DiffView);
+        app.toggle_focus();
+        app.move_down();
+        assert_eq!(app.selected_change().map(Change::key), Some("y"));
+
+        // A new domain sorts first, but the selection stays on com.b/y
+        app.finish_watch_capture(snapshot_with(&[
+            ("com.a", &[("z", 1)]),
+            ("com.b", &[("x", 2), ("y", 3)]),
+        ]));
+        assert_eq!(app.selected_domain(), Some("com.b"));
+        assert_eq!(app.selected_change().map(Change::key), Some("y"));
+    }
+
+    #[test]
+    fn test_open_in_editor_reports_missing_file() {
+        let mut app = diff_view_app(&["com.a"]);
+        app.command_options
+            .plist_paths
+            .insert("com.a".to_string(), "/nonexistent/App".to_string());
+        app.open_in_editor();
+        assert!(app.pending_edit.is_none());
+        assert_eq!(
+            app.get_status().map(|s| s.text.as_str()),
+            Some("Cannot edit: No plist file at /nonexistent/App.plist")
+        );
+
+        app.read_only = true;
+        app.open_in_editor();
+        assert!(app.get_status().unwrap().text.contains("read-only"));
+    }
+
+    #[test]
+    fn test_empty_first_capture_is_an_error() {
+        let mut app = App::new();
+        app.screen = Screen::LoadingFirst;
+        app.finish_first_snapshot(Snapshot::new(), Duration::from_secs(1));
+        let Screen::Error(message) = &app.screen else {
+            panic!("expected the error screen, got {:?}", app.screen);
+        };
+        assert!(message.contains("listed no preference domains"));
+        assert!(message.contains("Full Disk Access"));
+        assert!(app.snapshot_before.is_none());
+    }
+
+    #[test]
+    fn test_refresh_restores_selection_by_name() {
+        let mut app = App::new();
+        app.load_snapshots(
+            snapshot_with(&[("com.b", &[("x", 1), ("y", 1)])]),
+            snapshot_with(&[("com.b", &[("x", 2), ("y", 2)])]),
+        );
+        app.toggle_focus();
+        app.move_down();
+        let selected = app.selection_key();
+        assert_eq!(selected, Some(("com.b".to_string(), "y".to_string())));
+
+        // What `refresh_second_snapshot` saves before the capture starts
+        app.restore_selection = selected;
+        app.finish_second_snapshot(
+            snapshot_with(&[("com.a", &[("z", 1)]), ("com.b", &[("x", 2), ("y", 3)])]),
+            Duration::from_secs(1),
+        );
+        assert_eq!(app.selected_domain(), Some("com.b"));
+        assert_eq!(app.selected_change().map(Change::key), Some("y"));
+        assert_eq!(app.restore_selection, None);
+
+        // Without a diff there is nothing to refresh
+        app.reset();
+        app.refresh_second_snapshot();
+        assert!(!app.is_loading());
+    }
+
+    #[test]
+    fn test_load_snapshots_opens_diff_view() {
+        let mut app = App::new();
+        app.load_snapshots(
+            snapshot_with(&[("com.a", &[("x", 1)])]),
+            snapshot_with(&[("com.a", &[("x", 2)])]),
+        );
+        assert_eq!(app.screen, Screen::DiffView);
+        assert_eq!(app.selected_domain(), Some("com.a"));
+
+        app.load_snapshots(snapshot_with(&[]), snapshot_with(&[]));
+        assert_eq!(app.screen, Screen::NoChanges);
+    }
+
+    #[test]
+    fn test_only_normalized_changes_are_no_changes() {
+        let mut app = App::new();
+        app.diff_options.numeric_equivalence = true;
+        app.diff_options.show_normalized = true;
+        let mut after = snapshot_with(&[("com.a", &[])]);
+        after
+            .domains
+            .get_mut("com.a")
+            .unwrap()
+            .values
+            .insert("x".to_string(), plist::Value::Real(1.0));
+        app.load_snapshots(snapshot_with(&[("com.a", &[("x", 1)])]), after);
+
+        let diff = app.full_diff.as_ref().unwrap();
+        assert_eq!(diff.domain_diffs[0].changes.len(), 1);
+        assert_eq!(diff.total_changes, 0);
+        assert_eq!(app.screen, Screen::NoChanges);
+    }
+
+    #[test]
+    fn test_toggle_apple_services_refilters_diff() {
+        let mut app = App::new();
+        app.capture_options.include_apple_services = true;
+        app.load_snapshots(
+            snapshot_with(&[]),
+            snapshot_with(&[
+                ("com.apple.dock", &[("x", 1)]),
+                ("com.apple.xpc.activity2", &[("y", 1)]),
+            ]),
+        );
+        assert_eq!(app.diff_result.as_ref().unwrap().domain_diffs.len(), 2);
+        assert!(app.second_capture_options().include_apple_services);
+
+        app.toggle_apple_services();
+        let diff = app.diff_result.as_ref().unwrap();
+        assert_eq!(diff.domain_diffs.len(), 1);
+        assert_eq!(diff.domain_diffs[0].domain, "com.apple.dock");
+
+        // A first snapshot captured without them keeps later captures without them
+        app.before_has_apple_services = false;
+        app.toggle_apple_services();
+        assert!(app.capture_options.include_apple_services);
+        assert!(!app.second_capture_options().include_apple_services);
+    }
+
+    #[test]
+    fn test_all_keys_lists_unchanged_keys() {
+        let mut app = App::new();
+        app.load_snapshots(
+            snapshot_with(&[("com.a", &[("same", 1), ("edit", 1)])]),
+            snapshot_with(&[("com.a", &[("same", 1), ("edit", 2)])]),
+        );
+        assert!(app.all_keys().is_none());
+
+        app.toggle_all_keys();
+        assert_eq!(app.all_keys_domain.as_deref(), Some("com.a"));
+        let keys: Vec<_> = app
+            .all_keys()
+            .unwrap()
+            .into_iter()
+            .map(|(key, _, changed)| (key, changed))
+            .collect();
+        assert_eq!(keys, vec![("same", false), ("edit", true)]);
+
+        app.all_keys_max_scroll = 2;
+        app.scroll_all_keys(10);
+        assert_eq!(app.all_keys_scroll, 2);
+        app.scroll_all_keys(-1);
+        assert_eq!(app.all_keys_scroll, 1);
+
+        app.toggle_all_keys();
+        assert!(app.all_keys_domain.is_none());
+    }
+
+    #[test]
+    fn test_change_sort_orders() {
+        let mut app = App::new();
+        app.load_snapshots(
+            snapshot_with(&[("com.a", &[("gone", 1), ("b", 1)])]),
+            snapshot_with(&[("com.a", &[("z", 1), ("b", 2), ("a", 1)])]),
+        );
+        let keys = |app: &App| -> Vec<String> {
+            app.diff_result.as_ref().unwrap().domain_diffs[0]
+                .changes
+                .iter()
+                .map(|c| c.key().to_string())
+                .collect()
+        };
+        assert_eq!(keys(&app), vec!["z", "b", "a", "gone"]);
+
+        app.cycle_change_sort();
+        assert_eq!(keys(&app), vec!["a", "b", "gone", "z"]);
+
+        app.cycle_change_sort();
+        assert_eq!(keys(&app), vec!["a", "z", "gone", "b"]);
+
+        app.cycle_change_sort();
+        assert_eq!(app.change_sort, ChangeSort::SourceOrder);
+    }
+
+    #[test]
+    fn test_sort_by_change_count_keeps_selection() {
+        let mut app = App::new();
+        app.snapshot_before = Some(snapshot_with(&[]));
+        app.snapshot_after = Some(snapshot_with(&[
+            ("com.a", &[("x", 1)]),
+            ("com.b", &[("x", 1), ("y", 1), ("z", 1)]),
+            ("com.c", &[("x", 1), ("y", 1)]),
+        ]));
+        app.detect_changes();
+        assert_eq!(app.selected_domain(), Some("com.a"));
+
+        app.cycle_sort_mode();
+        let order: Vec<_> = app
+            .diff_result
+            .as_ref()
+            .unwrap()
+            .domain_diffs
+            .iter()
+            .map(|d| d.domain.as_str())
+            .collect();
+        assert_eq!(order, vec!["com.b", "com.c", "com.a"]);
+        assert_eq!(app.selected_domain(), Some("com.a"));
+
+        app.cycle_sort_mode();
+        assert_eq!(app.sort_mode, SortMode::Name);
+        assert_eq!(app.selected_domain_index, 0);
+    }
+
+    #[test]
+    fn test_change_type_filter_updates_counts_and_bounds() {
+        let mut app = App::new();
+        app.snapshot_before = Some(snapshot_with(&[("com.a", &[("gone", 1), ("edit", 1)])]));
+        app.snapshot_after = Some(snapshot_with(&[
+            ("com.a", &[("edit", 2), ("new", 1)]),
+            ("com.b", &[("new", 1)]),
+        ]));
+        app.detect_changes();
+
+        app.toggle_change_type_filter(ChangeKind::Removed);
+        let diff = app.diff_result.as_ref().unwrap();
+        assert_eq!(diff.total_changes, 1);
+        assert_eq!(diff.domain_diffs.len(), 1);
+        app.move_down();
+        assert_eq!(app.selected_domain(), Some("com.a"));
+        app.toggle_focus();
+        app.move_to_bottom();
+        assert_eq!(app.selected_change().map(Change::key), Some("gone"));
+
+        app.toggle_change_type_filter(ChangeKind::Added);
+        assert_eq!(app.diff_result.as_ref().unwrap().total_changes, 2);
+
+        app.toggle_change_type_filter(ChangeKind::Added);
+        assert!(app.change_type_filter.is_none());
+        assert_eq!(app.diff_result.as_ref().unwrap().total_changes, 4);
+    }
+
+    #[test]
+    fn test_watch_requires_first_snapshot() {
+        let mut app = App::new();
+        app.toggle_watch();
+        assert!(!app.watch);
+        app.tick_watch();
+        assert!(app.capture_job.is_none());
+    }
+
+    #[test]
+    fn test_history_compares_any_two_captures() {
+        let round = |value| snapshot_with(&[("com.apple.dock", &[("tilesize", value)])]);
+        let mut app = App::new();
+        app.screen = Screen::LoadingFirst;
+        app.finish_first_snapshot(round(36), Duration::ZERO);
+        for value in [48, 64] {
+            app.screen = Screen::LoadingSecond;
+            app.finish_second_snapshot(round(value), Duration::ZERO);
+        }
+        assert_eq!(app.history.len(), 3);
+
+        // Round 1 against round 3
+        app.open_history();
+        assert_eq!(app.screen, Screen::History);
+        app.pick_history_entry();
+        app.move_history(-2);
+        app.pick_history_entry();
+        assert_eq!(app.screen, Screen::DiffView);
+        assert_eq!(
+            app.selected_change().and_then(|c| c.old_value()),
+            Some(&plist::Value::Integer(36.into()))
+        );
+        assert_eq!(
+            app.selected_change().and_then(|c| c.new_value()),
+            Some(&plist::Value::Integer(64.into()))
+        );
+        assert!(
+            app.get_status()
+                .is_some_and(|s| s.text.starts_with("Comparing #1 with #3"))
+        );
+    }
+
+    #[test]
+    fn test_history_drops_oldest_capture() {
+        let mut app = App::new();
+        for _ in 0..HISTORY_LIMIT + 2 {
+            app.record_history("capture", &Snapshot::new());
+        }
+        assert_eq!(app.history.len(), HISTORY_LIMIT);
+        assert_eq!(app.history.front().map(|e| e.number), Some(3));
+    }
+
+    #[test]
+    fn test_zero_changes_goes_to_no_changes_screen() {
+        let mut app = App::new();
+        app.snapshot_before = Some(Snapshot::new());
+        app.screen = Screen::LoadingSecond;
+
+        app.finish_second_snapshot(Snapshot::new(), Duration::from_millis(6200));
+        assert_eq!(app.screen, Screen::NoChanges);
+        assert_eq!(
+            app.get_status().map(|s| s.text.as_str()),
+            Some("No changes detected (captured 0 domains in 6.2s)")
+        );
+        assert_eq!(app.full_diff.as_ref().map(|d| d.total_changes), Some(0));
+    }
+
+    #[test]
+    fn test_export_is_noop_when_read_only() {
+        let mut app = diff_view_app(&["com.apple.dock"]);
+        app.read_only = true;
+
+        app.start_export(ExportAction::DomainBundle);
+        assert!(app.pending_export.is_none());
+        assert_eq!(
+            app.get_status().map(|s| s.kind.clone()),
+            Some(StatusKind::Warning)
+        );
+    }
+
+    #[test]
+    fn test_selected_domain_follows_selection() {
+        let mut app = diff_view_app(&["com.apple.dock", "com.apple.finder"]);
+        assert_eq!(app.selected_domain(), Some("com.apple.dock"));
+        app.move_down();
+        assert_eq!(app.selected_domain(), Some("com.apple.finder"));
+    }
+
+    #[test]
+    fn test_paging_and_jumps_clamp_to_list() {
+        let domains: Vec<String> = (0..25).map(|i| format!("com.d{:02}", i)).collect();
+        let mut app = diff_view_app(&domains.iter().map(String::as_str).collect::<Vec<_>>());
+        app.page_size = 10;
+
+        app.page_down();
+        assert_eq!(app.selected_domain_index, 10);
+        app.page_down();
+        app.page_down();
+        assert_eq!(app.selected_domain_index, 24);
+        app.page_up();
+        assert_eq!(app.selected_domain_index, 14);
+        app.move_to_top();
+        assert_eq!(app.selected_domain(), Some("com.d00"));
+        app.move_to_bottom();
+        assert_eq!(app.selected_domain(), Some("com.d24"));
+        assert_eq!(app.domain_list_state.selected(), Some(24));
+
+        // The single-change list cannot move
+        app.toggle_focus();
+        app.move_to_bottom();
+        assert_eq!(app.selected_diff_index, 0);
+    }
+
+    #[test]
+    fn test_value_popup_opens_from_changes_pane() {
+        let mut app = diff_view_app(&["com.apple.dock"]);
+        app.open_value_popup();
+        assert!(app.value_popup.is_none());
+
+        app.toggle_focus();
+        app.open_value_popup();
+        assert_eq!(app.value_popup.as_ref().map(Change::key), Some("key"));
+        app.value_popup_max_scroll = 5;
+        app.scroll_value_popup(-1);
+        assert_eq!(app.value_popup_scroll, 0);
+        app.scroll_value_popup(3);
+        assert_eq!(app.value_popup_scroll, 3);
+        app.scroll_value_popup(i32::MAX);
+        assert_eq!(app.value_popup_scroll, 5);
+        app.scroll_value_popup(-1);
+        assert_eq!(app.value_popup_scroll, 4);
+        app.scroll_value_popup(i32::MIN);
+        assert_eq!(app.value_popup_scroll, 0);
+
+        app.close_value_popup();
+        assert!(app.value_popup.is_none());
+    }
+
+    #[test]
+    fn test_apply_requires_confirmation_and_marks_change() {
+        let mut app = diff_view_app(&["com.apple.dock"]);
+        app.toggle_focus();
+        app.request_apply();
+        assert_eq!(
+            app.pending_apply.as_deref(),
+            Some(r#"defaults write "com.apple.dock" "key" -bool true"#)
+        );
+        app.cancel_apply();
+        assert!(app.pending_apply.is_none());
+
+        // Stand in for the real command so the test does not touch defaults
+        app.pending_apply = Some("true".to_string());
+        app.confirm_apply();
+        let change = app.selected_change().unwrap().clone();
+        assert!(app.is_applied(&change));
+
+        app.pending_apply = Some("echo denied >&2; false".to_string());
+        app.confirm_apply();
+        let status = app.get_status().unwrap();
+        assert_eq!(status.kind, StatusKind::Warning);
+        assert_eq!(status.text, "Apply failed: denied");
+    }
+
+    #[test]
+    fn test_apply_refuses_comment_only_command() {
+        let mut app = diff_view_app(&["com.apple.dock"]);
+        app.full_diff.as_mut().unwrap().domain_diffs[0].changes = vec![Change::Normalized {
+            domain: "com.apple.dock".to_string(),
+            key: "key".to_string(),
+            old_value: plist::Value::Integer(1.into()),
+            new_value: plist::Value::Real(1.0),
+            reason: crate::diff::types::Normalization::Numeric,
+        }];
+        app.apply_filters();
+        app.toggle_focus();
+        app.request_apply();
+        assert!(app.pending_apply.is_none());
+        let status = app.get_status().unwrap();
+        assert_eq!(status.kind, StatusKind::Warning);
+        assert!(status.text.starts_with("Nothing to apply"));
+        assert!(!app.exported);
+    }
+
+    #[test]
+    fn test_apply_rejected_when_read_only() {
+        let mut app = diff_view_app(&["com.apple.dock"]);
+        app.read_only = true;
+        app.toggle_focus();
+        app.request_apply();
+        assert!(app.pending_apply.is_none());
+    }
+
+    #[test]
+    fn test_apply_and_edit_rejected_for_other_user() {
+        let mut app = diff_view_app(&["com.apple.dock"]);
+        app.capture_options.user = Some("alice".to_string());
+        app.toggle_focus();
+        app.request_apply();
+        assert!(app.pending_apply.is_none());
+        assert!(
+            app.status
+                .as_ref()
+                .unwrap()
+                .text
+                .contains("alice's defaults")
+        );
+
+        app.open_in_editor();
+        assert!(app.pending_edit.is_none());
+    }
+
+    /// App whose captures return nothing instead of running `defaults`
+    fn offline_app() -> App {
+        let mut app = App::new();
+        app.capture = |_, _, _| Ok(None);
+        app
+    }
+
+    #[test]
+    fn test_cancel_capture_sets_flag_and_restores_screen() {
+        let mut app = offline_app();
+        app.start_first_snapshot();
+        let flag = Arc::clone(&app.capture_job.as_ref().unwrap().cancel);
+
+        app.cancel_capture();
+        assert!(flag.load(Ordering::Relaxed));
+        assert!(app.capture_job.is_none());
+        assert_eq!(app.screen, Screen::Initial);
+        assert!(!app.is_loading());
+
+        app.screen = Screen::WaitingForChanges;
+        app.start_second_snapshot();
+        app.cancel_capture();
+        assert_eq!(app.screen, Screen::WaitingForChanges);
+        assert!(app.snapshot_after.is_none());
+    }
+
+    #[test]
+    fn test_capture_trigger_ignored_while_loading() {
+        let mut app = offline_app();
+        app.start_first_snapshot();
+        assert_eq!(app.screen, Screen::LoadingFirst);
+
+        app.start_second_snapshot();
+        assert_eq!(app.screen, Screen::LoadingFirst);
+        app.start_first_snapshot();
+        assert_eq!(app.screen, Screen::LoadingFirst);
+        assert_eq!(
+            app.get_status().map(|s| s.text.as_str()),
+            Some("Capture already in progress")
+        );
+    }
+
+    #[test]
+    fn test_selected_domain_commands() {
+        let mut app = diff_view_app(&["com.a", "com.b"]);
+        app.selected_domain_index = 1;
+        assert_eq!(
+            app.selected_domain_commands(),
+            Some(vec![
+                r#"defaults write "com.b" "key" -bool true"#.to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_save_and_load_before_snapshot() {
+        let path =
+            std::env::temp_dir().join(format!("defaults-util-before-{}.plist", std::process::id()));
+        let mut app = App::new();
+        let mut snapshot = Snapshot::new();
+        snapshot.domains.insert(
+            "com.a".to_string(),
+            crate::defaults::types::DomainSettings {
+                values: plist::Dictionary::new(),
+            },
+        );
+        app.snapshot_before = Some(snapshot);
+        app.save_before_snapshot(&path);
+
+        let mut loaded = App::new();
+        loaded.load_before_snapshot(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.screen, Screen::WaitingForChanges);
+        assert_eq!(loaded.snapshot_before.map(|s| s.domain_count()), Some(1));
+    }
+
+    #[test]
+    fn test_load_factory_baseline_reports_unreadable_file() {
+        let mut app = App::new();
+        app.load_factory_baseline(&std::env::temp_dir().join("defaults-util-no-baseline.plist"));
+        assert!(app.factory_baseline.is_none());
+        assert!(app.status.is_none());
+
+        let path = std::env::temp_dir().join(format!(
+            "defaults-util-bad-baseline-{}.plist",
+            std::process::id()
+        ));
+        std::fs::write(&path, "not a plist").unwrap();
+        app.load_factory_baseline(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(app.factory_baseline.is_none());
+        assert!(
+            app.status
+                .as_ref()
+                .is_some_and(|status| status.text.starts_with("Could not load factory baseline"))
+        );
+    }
+
+    #[test]
+    fn test_save_before_snapshot_rejected_when_read_only() {
+        let path = std::env::temp_dir().join("defaults-util-never-written.plist");
+        let mut app = App::new();
+        app.read_only = true;
+        app.snapshot_before = Some(Snapshot::new());
+        app.save_before_snapshot(&path);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_first_snapshot_reports_skipped_domains() {
+        let mut app = App::new();
+        let mut snapshot = snapshot_with(&[("com.ok", &[("x", 1)])]);
+        snapshot
+            .failed_domains
+            .push(("com.broken".to_string(), "timed out".to_string()));
+        app.finish_first_snapshot(snapshot, Duration::from_secs(2));
+
+        let status = app.get_status().unwrap();
+        assert_eq!(status.kind, StatusKind::Warning);
+        assert!(status.text.contains("1 domains, 1 skipped in 2.0s"));
+
+        app.toggle_failed_domains();
+        assert!(app.show_failed_domains);
     }
 }
@@ -0,0 +1,330 @@
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use clap::{Parser, Subcommand};
+
+use crate::command::generator::{CommandOptions, QuoteStyle};
+use crate::command::script::collect_commands;
+use crate::defaults::reader::is_apple_service_domain;
+use crate::defaults::{CaptureOptions, MergeStrategy, Snapshot, capture_snapshot};
+use crate::diff::ignore::glob_match;
+use crate::diff::{DiffOptions, DiffResult, detect_diff};
+use crate::export::export_nix;
+
+/// Detect macOS defaults changes and generate reproducible commands
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
+    /// Read-only mode: disable actions that write files or settings
+    #[arg(long)]
+    pub inspect: bool,
+
+    /// Also capture system-wide preferences through sudo; their commands get `sudo`
+    #[arg(long, global = true)]
+    pub include_system: bool,
+
+    /// Also capture Apple background service domains such as `com.apple.xpc.*`,
+    /// and compare them in `diff` and `summary`
+    #[arg(long, global = true)]
+    pub include_apple_services: bool,
+
+    /// Capture another user's defaults through `sudo -u` (run `sudo -v` first)
+    #[arg(long, global = true, value_name = "NAME")]
+    pub user: Option<String>,
+
+    /// Seconds to wait for each `defaults` command before giving up on it
+    #[arg(long, global = true, value_name = "SECONDS")]
+    pub timeout: Option<u64>,
+
+    /// Times to retry a `defaults export` that failed to run (default 2)
+    #[arg(long, global = true, value_name = "COUNT")]
+    pub retries: Option<u32>,
+
+    /// How generated commands quote domains, keys and strings
+    #[arg(long, global = true, value_enum, default_value_t = QuoteStyle::Double)]
+    pub quote: QuoteStyle,
+
+    /// Follow each generated write with a `defaults read` of the key
+    #[arg(long, global = true)]
+    pub verify: bool,
+
+    /// Write changes inside dictionaries and arrays with `plutil -replace` on the
+    /// domain's plist file instead of rewriting the whole key with `defaults`
+    #[arg(long, global = true)]
+    pub plutil: bool,
+
+    /// Write a domain's settings to this plist file instead of addressing it
+    /// by name (repeatable)
+    #[arg(long = "plist-path", global = true, value_name = "DOMAIN=PATH", value_parser = parse_plist_path)]
+    pub plist_paths: Vec<(String, String)>,
+}
+
+/// Parse a `--plist-path DOMAIN=PATH` argument
+fn parse_plist_path(arg: &str) -> Result<(String, String), String> {
+    let (domain, path) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected DOMAIN=PATH".to_string())?;
+    if domain.is_empty() {
+        return Err("the domain is empty".to_string());
+    }
+    if !path.starts_with('/') {
+        return Err(format!("{} is not an absolute path", path));
+    }
+    // A newline would split the generated command across lines
+    if path.chars().any(char::is_control) {
+        return Err("the path contains control characters".to_string());
+    }
+    Ok((domain.to_string(), path.to_string()))
+}
+
+impl Cli {
+    /// Capture options selected by the global flags
+    pub fn capture_options(&self) -> CaptureOptions {
+        CaptureOptions {
+            include_system: self.include_system,
+            include_apple_services: self.include_apple_services,
+            user: self.user.clone(),
+            timeout: self.timeout.map(Duration::from_secs),
+            retries: self.retries,
+            ..Default::default()
+        }
+    }
+
+    /// Command options selected by the global flags
+    pub fn command_options(&self) -> CommandOptions {
+        CommandOptions {
+            quote: self.quote,
+            verify: self.verify,
+            plist_paths: self.plist_paths.iter().cloned().collect(),
+            plutil: self.plutil,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Capture the current defaults into a snapshot file
+    Capture {
+        /// Output snapshot file
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Capture only this domain
+        #[arg(long)]
+        domain: Option<String>,
+    },
+    /// Print the `defaults` commands that turn one snapshot file into another
+    Diff {
+        /// Snapshot file to compare from
+        before: PathBuf,
+        /// Snapshot file to compare to
+        after: PathBuf,
+        /// What to print
+        #[arg(long, value_enum, default_value_t = DiffFormat::Commands)]
+        format: DiffFormat,
+        /// Compare only domains matching this glob (`*` and `?`; repeatable)
+        #[arg(long, value_name = "GLOB", alias = "only-domain")]
+        only: Vec<String>,
+        /// Leave out domains matching this glob (repeatable)
+        #[arg(long, value_name = "GLOB", alias = "exclude-domain")]
+        exclude: Vec<String>,
+    },
+    /// Open the TUI on the diff between two snapshot files
+    Compare {
+        /// Snapshot file to compare from
+        before: PathBuf,
+        /// Snapshot file to compare to
+        after: PathBuf,
+    },
+    /// Print change counts and changed domains between two snapshot files
+    Summary {
+        /// Snapshot file to compare from
+        #[arg(long)]
+        a: PathBuf,
+        /// Snapshot file to compare to
+        #[arg(long)]
+        b: PathBuf,
+    },
+    /// Merge several snapshot files into one composite snapshot
+    Merge {
+        /// Snapshot files, merged in order
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+        /// Output snapshot file
+        #[arg(short, long)]
+        output: PathBuf,
+        /// How to resolve keys present in more than one input
+        #[arg(long, value_enum, default_value_t = MergeStrategy::ErrorOnConflict)]
+        strategy: MergeStrategy,
+    },
+}
+
+/// Output of the `diff` subcommand
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum DiffFormat {
+    /// `defaults write`/`delete` commands
+    Commands,
+    /// A nix-darwin `system.defaults.CustomUserPreferences` fragment
+    Nix,
+}
+
+/// Run a headless subcommand; `compare` is an error, since it needs the TUI
+pub fn run(
+    command: Commands,
+    capture_options: CaptureOptions,
+    command_options: CommandOptions,
+) -> Result<()> {
+    match command {
+        Commands::Capture { output, domain } => run_capture(
+            &output,
+            CaptureOptions {
+                domain,
+                ..capture_options
+            },
+        ),
+        Commands::Diff {
+            before,
+            after,
+            format,
+            only,
+            exclude,
+        } => run_diff(
+            &before,
+            &after,
+            format,
+            &only,
+            &exclude,
+            capture_options.include_apple_services,
+            &command_options,
+        ),
+        // The binary opens the TUI for `compare` before getting here
+        Commands::Compare { .. } => bail!("compare opens the TUI and cannot run headless"),
+        Commands::Summary { a, b } => run_summary(&a, &b, capture_options.include_apple_services),
+        Commands::Merge {
+            inputs,
+            output,
+            strategy,
+        } => run_merge(&inputs, &output, strategy),
+    }
+}
+
+/// Load the two snapshot files of a `compare`
+pub fn load_pair(
+    before: &std::path::Path,
+    after: &std::path::Path,
+) -> Result<(Snapshot, Snapshot)> {
+    Ok((
+        Snapshot::load_from_file(before)?,
+        Snapshot::load_from_file(after)?,
+    ))
+}
+
+fn run_capture(output: &std::path::Path, options: CaptureOptions) -> Result<()> {
+    let started = Instant::now();
+    // Nothing cancels a headless capture
+    let snapshot = capture_snapshot(&options, &AtomicBool::new(false), &mut |_, _, _| {})?
+        .context("capture was cancelled")?;
+    snapshot.save_to_file(output)?;
+
+    eprintln!(
+        "Captured {} domains into {} in {:.1}s",
+        snapshot.domain_count(),
+        output.display(),
+        started.elapsed().as_secs_f64()
+    );
+    if !snapshot.retried_domains.is_empty() {
+        eprintln!(
+            "  {} domains read after retrying",
+            snapshot.retried_domains.len()
+        );
+    }
+    for (domain, error) in &snapshot.failed_domains {
+        eprintln!("  skipped {}: {}", domain, error.trim());
+    }
+    Ok(())
+}
+
+fn run_diff(
+    before: &std::path::Path,
+    after: &std::path::Path,
+    format: DiffFormat,
+    only: &[String],
+    exclude: &[String],
+    include_apple_services: bool,
+    options: &CommandOptions,
+) -> Result<()> {
+    let diff = diff_files(before, after, include_apple_services)?.filter_domains(|d| {
+        (only.is_empty() || only.iter().any(|p| glob_match(p, &d.domain)))
+            && !exclude.iter().any(|p| glob_match(p, &d.domain))
+    });
+
+    match format {
+        DiffFormat::Commands => print!("{}", collect_commands(&diff, options, false)),
+        DiffFormat::Nix => print!("{}", export_nix(&diff)),
+    }
+    Ok(())
+}
+
+fn run_merge(inputs: &[PathBuf], output: &std::path::Path, strategy: MergeStrategy) -> Result<()> {
+    let mut merged = Snapshot::new();
+    for input in inputs {
+        let snapshot = Snapshot::load_from_file(input)?;
+        merged
+            .merge(snapshot, strategy)
+            .with_context(|| format!("while merging {}", input.display()))?;
+    }
+    merged.save_to_file(output)?;
+
+    println!(
+        "Merged {} snapshots ({} domains) into {}",
+        inputs.len(),
+        merged.domain_count(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Diff two snapshot files. Apple service domains are left out unless
+/// `include_apple_services`: a capture without them would otherwise show
+/// every one of them as removed.
+fn diff_files(
+    before: &std::path::Path,
+    after: &std::path::Path,
+    include_apple_services: bool,
+) -> Result<DiffResult> {
+    let before = Snapshot::load_from_file(before)?;
+    let after = Snapshot::load_from_file(after)?;
+    let diff = detect_diff(&before, &after, &DiffOptions::default());
+    Ok(if include_apple_services {
+        diff
+    } else {
+        diff.filter_domains(|d| !is_apple_service_domain(&d.domain))
+    })
+}
+
+fn run_summary(
+    a: &std::path::Path,
+    b: &std::path::Path,
+    include_apple_services: bool,
+) -> Result<()> {
+    let diff = diff_files(a, b, include_apple_services)?;
+    let counts = diff.counts();
+
+    println!("Added: {}", counts.added);
+    println!("Removed: {}", counts.removed);
+    println!("Modified: {}", counts.modified);
+    println!("Total: {}", diff.total_changes);
+    if !diff.domain_diffs.is_empty() {
+        println!("Changed domains:");
+        for domain_diff in &diff.domain_diffs {
+            println!("  {} ({})", domain_diff.domain, domain_diff.change_count());
+        }
+    }
+
+    Ok(())
+}
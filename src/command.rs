@@ -1 +1,3 @@
+pub mod apply;
 pub mod generator;
+pub mod script;
@@ -0,0 +1,109 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Result, bail};
+
+/// Run a generated command line through `sh -c`, failing with its stderr.
+///
+/// Stdin is closed so commands that would prompt (like `sudo`) fail instead of
+/// waiting on the raw-mode terminal.
+pub fn run_command(command: &str) -> Result<()> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        if stderr.is_empty() {
+            bail!("command exited with {}", output.status);
+        }
+        bail!("{}", stderr);
+    }
+    Ok(())
+}
+
+/// Open a file in `$VISUAL` or `$EDITOR` and wait for it to exit, or hand it
+/// to `open` when neither is set. The editor value may carry arguments
+/// (`code -w`), so it runs through `sh -c`.
+///
+/// Text editors cannot read binary plists, so one is converted to XML with
+/// `plutil -convert xml1` first; macOS reads either format.
+pub fn edit_file(path: &Path) -> Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.trim().is_empty());
+    if editor.is_some() && is_binary_plist(path) {
+        let output = Command::new("plutil")
+            .arg("-convert")
+            .arg("xml1")
+            .arg(path)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "binary plist could not be converted to XML: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+    let status = match editor {
+        Some(editor) => Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(path)
+            .status()?,
+        None => Command::new("open").arg(path).status()?,
+    };
+    if !status.success() {
+        bail!("editor exited with {}", status);
+    }
+    Ok(())
+}
+
+/// Whether a file starts with the binary plist magic
+fn is_binary_plist(path: &Path) -> bool {
+    let mut magic = [0u8; 6];
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut magic))
+        .is_ok_and(|()| &magic == b"bplist")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_command_success() {
+        assert!(run_command("true").is_ok());
+    }
+
+    #[test]
+    fn test_run_command_failure_reports_stderr() {
+        let err = run_command("echo 'no such key' >&2; exit 1").unwrap_err();
+        assert_eq!(err.to_string(), "no such key");
+
+        let err = run_command("exit 3").unwrap_err();
+        assert!(err.to_string().contains("exited with"));
+    }
+
+    #[test]
+    fn test_is_binary_plist() {
+        let dir = std::env::temp_dir().join(format!("defaults-util-edit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let binary = dir.join("binary.plist");
+        plist::Value::Boolean(true).to_file_binary(&binary).unwrap();
+        assert!(is_binary_plist(&binary));
+
+        let xml = dir.join("xml.plist");
+        plist::Value::Boolean(true).to_file_xml(&xml).unwrap();
+        assert!(!is_binary_plist(&xml));
+
+        assert!(!is_binary_plist(&dir.join("missing.plist")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,35 +1,720 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
 use plist::Value;
 
+use crate::defaults::parser::ROOT_KEY;
+use crate::defaults::reader::{MANAGED_PREFERENCES_DIR, domain_arg, is_global_domain};
+use crate::defaults::types::{Scope, value_type_label};
 use crate::diff::Change;
+use crate::diff::format::{format_date, format_integer};
+use crate::diff::types::DomainDiff;
+
+/// Which host's preferences a generated command targets
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CommandScope {
+    /// Preferences shared by every host (plain `defaults`)
+    #[default]
+    Global,
+    /// Per-host preferences (`defaults -currentHost`)
+    CurrentHost,
+}
+
+impl CommandScope {
+    pub fn toggle(self) -> Self {
+        match self {
+            CommandScope::Global => CommandScope::CurrentHost,
+            CommandScope::CurrentHost => CommandScope::Global,
+        }
+    }
+}
+
+/// How domains, keys and string values are quoted for the shell
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum QuoteStyle {
+    /// `"..."` with `\`, `"`, `$` and backticks backslash-escaped
+    #[default]
+    Double,
+    /// `'...'` with embedded single quotes written as `'\''`
+    Single,
+}
+
+impl QuoteStyle {
+    pub fn toggle(self) -> Self {
+        match self {
+            QuoteStyle::Double => QuoteStyle::Single,
+            QuoteStyle::Single => QuoteStyle::Double,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            QuoteStyle::Double => "double",
+            QuoteStyle::Single => "single",
+        }
+    }
+}
+
+/// Options controlling command generation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandOptions {
+    /// Append a `# was X, now Y, +D` comment to numeric Modified changes
+    pub show_delta: bool,
+    pub host: CommandScope,
+    pub quote: QuoteStyle,
+    /// Follow each write with a `defaults read` of the written key
+    pub verify: bool,
+    /// Plist files to address instead of a domain's name, for apps whose
+    /// preferences live outside the standard directory
+    pub plist_paths: BTreeMap<String, String>,
+    /// Write nested changes with `plutil` on the domain's plist file, touching
+    /// only the changed value instead of rewriting its top-level key
+    pub plutil: bool,
+}
+
+/// What commands pass to `defaults` for a domain: its override path (without
+/// `.plist`, like the reader) or the domain itself
+fn target_domain<'a>(domain: &'a str, options: &'a CommandOptions) -> &'a str {
+    options
+        .plist_paths
+        .get(domain)
+        .map_or(domain, |path| domain_arg(path))
+}
 
 /// Generate defaults command from a change
-pub fn generate_command(change: &Change) -> String {
+pub fn generate_command(change: &Change, options: &CommandOptions) -> String {
+    apply_host(generate_global_command(change, options, true), options.host)
+}
+
+/// Insert `-currentHost` right after `defaults` for per-host commands
+pub fn apply_host(command: String, host: CommandScope) -> String {
+    match command.strip_prefix("defaults ") {
+        Some(rest) if host == CommandScope::CurrentHost => {
+            format!("defaults -currentHost {}", rest)
+        }
+        _ => command,
+    }
+}
+
+/// With `per_leaf` unset, a nested change always rewrites its top-level value
+fn generate_global_command(change: &Change, options: &CommandOptions, per_leaf: bool) -> String {
+    if change.root_key() == ROOT_KEY {
+        return root_value_comment(change.domain());
+    }
+    let target = target_domain(change.domain(), options);
     match change {
-        Change::Added { domain, key, value } => generate_write_command(domain, key, value),
+        Change::Added { key, value, .. } => {
+            generate_write_command(target, key, value, None, options)
+        }
         Change::Modified {
-            domain,
             key,
+            old_value,
+            new_value,
+            ..
+        } => generate_write_command(target, key, new_value, Some(old_value), options),
+        // `defaults` cannot address nested keys, so rewrite the whole top-level value
+        Change::Nested {
+            key,
+            root_key,
+            old_value,
             new_value,
+            old_root,
+            new_root,
             ..
-        } => generate_write_command(domain, key, new_value),
-        Change::Removed { domain, key, .. } => {
+        } => per_leaf
+            .then(|| {
+                nested_plutil_command(
+                    target,
+                    key,
+                    new_value.as_ref(),
+                    old_value.is_none() || new_value.is_none(),
+                    root_key,
+                    new_root,
+                    options,
+                )
+            })
+            .flatten()
+            .unwrap_or_else(|| {
+                generate_write_command(target, root_key, new_root, Some(old_root), options)
+            }),
+        Change::Removed { key, .. } => {
             format!(
-                "defaults delete \"{}\" \"{}\"",
-                escape_string(domain),
-                escape_string(key)
+                "defaults delete {} {}",
+                domain_argument(target, options.quote),
+                quote(key, options.quote)
+            )
+        }
+        Change::Normalized {
+            domain,
+            key,
+            reason,
+            ..
+        } => format!(
+            "# {} {}: differs only by {} normalization, no command needed",
+            domain,
+            key,
+            reason.label()
+        ),
+    }
+}
+
+/// Generate a command for a change in a domain captured at `scope`.
+///
+/// System-scoped domains are only writable by root, so their commands get `sudo`.
+/// Domains a plain write can't change get a warning comment above the command,
+/// and `sudo` when that is what they need.
+pub fn generate_scoped_command(change: &Change, scope: Scope, options: &CommandOptions) -> String {
+    scoped_command(change, scope, options, true)
+}
+
+fn scoped_command(
+    change: &Change,
+    scope: Scope,
+    options: &CommandOptions,
+    per_leaf: bool,
+) -> String {
+    let command = if per_leaf {
+        generate_command(change, options)
+    } else {
+        apply_host(
+            generate_global_command(change, options, false),
+            options.host,
+        )
+    };
+    if command.starts_with('#') {
+        return command;
+    }
+    match write_restriction(target_domain(change.domain(), options)) {
+        // Captured through sudo already, so the command has it
+        Some(WriteRestriction::NeedsSudo) if scope == Scope::System => apply_scope(command, scope),
+        Some(WriteRestriction::NeedsSudo) => format!(
+            "# Warning: {}\nsudo {}",
+            WriteRestriction::NeedsSudo.warning(),
+            command
+        ),
+        Some(restriction) => format!(
+            "# Warning: {}\n{}",
+            restriction.warning(),
+            apply_scope(command, scope)
+        ),
+        None => apply_scope(command, scope),
+    }
+}
+
+/// Commands for every change in a domain, scoped and (with `options.verify`)
+/// followed by their `defaults read`, paired with the change each came from.
+///
+/// Nested changes under one top-level key share a single rewrite of that key,
+/// emitted for the first of them, unless `plutil` can write every leaf.
+pub fn domain_commands<'a>(
+    domain_diff: &'a DomainDiff,
+    options: &CommandOptions,
+) -> Vec<(&'a Change, String)> {
+    let rewritten: HashSet<&str> = domain_diff
+        .changes
+        .iter()
+        .filter(|change| {
+            matches!(change, Change::Nested { .. })
+                && nested_leaf_command(change, options).is_none()
+        })
+        .map(Change::root_key)
+        .collect();
+    let mut emitted = HashSet::new();
+    domain_diff
+        .changes
+        .iter()
+        .filter(|change| {
+            !matches!(change, Change::Nested { .. })
+                || !rewritten.contains(change.root_key())
+                || emitted.insert(change.root_key())
+        })
+        .map(|change| {
+            let per_leaf = !rewritten.contains(change.root_key());
+            let command = scoped_command(change, domain_diff.scope, options, per_leaf);
+            (
+                change,
+                with_verify(command, change, domain_diff.scope, options),
             )
+        })
+        .collect()
+}
+
+/// The `plutil` command writing just this nested change's leaf, if there is one
+fn nested_leaf_command(change: &Change, options: &CommandOptions) -> Option<String> {
+    let Change::Nested {
+        domain,
+        key,
+        root_key,
+        old_value,
+        new_value,
+        new_root,
+        ..
+    } = change
+    else {
+        return None;
+    };
+    let target = target_domain(domain, options);
+    nested_plutil_command(
+        target,
+        key,
+        new_value.as_ref(),
+        old_value.is_none() || new_value.is_none(),
+        root_key,
+        new_root,
+        options,
+    )
+}
+
+/// Why a plain `defaults write` to a domain would fail or not stick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteRestriction {
+    /// A root-owned file under `/Library/Preferences`
+    NeedsSudo,
+    /// Installed by a configuration profile, which wins over any write
+    Managed,
+    /// Under `/System`, which SIP keeps read-only even for root
+    SipProtected,
+}
+
+impl WriteRestriction {
+    /// Short badge text for the Changes pane
+    pub fn label(self) -> &'static str {
+        match self {
+            WriteRestriction::NeedsSudo => "needs sudo",
+            WriteRestriction::Managed => "managed",
+            WriteRestriction::SipProtected => "SIP-protected",
+        }
+    }
+
+    fn warning(self) -> &'static str {
+        match self {
+            WriteRestriction::NeedsSudo => "this domain is owned by root, so writing it needs sudo",
+            WriteRestriction::Managed => {
+                "this domain comes from a configuration profile, which overrides this write"
+            }
+            WriteRestriction::SipProtected => {
+                "this domain is protected by System Integrity Protection; the write will fail"
+            }
+        }
+    }
+}
+
+/// Guess from a path-style domain whether plain writes to it won't take.
+/// Bundle-id domains belong to the user and are always writable.
+pub fn write_restriction(domain: &str) -> Option<WriteRestriction> {
+    let path = Path::new(domain);
+    if path.starts_with(MANAGED_PREFERENCES_DIR) {
+        Some(WriteRestriction::Managed)
+    } else if path.starts_with("/System") {
+        Some(WriteRestriction::SipProtected)
+    } else if command_requires_sudo(domain) {
+        Some(WriteRestriction::NeedsSudo)
+    } else {
+        None
+    }
+}
+
+/// Whether writing a domain needs root: files under the system-wide
+/// `/Library/Preferences` (a user's `~/Library/Preferences` does not)
+pub fn command_requires_sudo(domain: &str) -> bool {
+    Path::new(domain).starts_with("/Library/Preferences")
+}
+
+/// `defaults read` of the key a change writes, so a script shows the value took.
+/// Returns `None` for changes that write nothing (removals, normalized entries).
+pub fn generate_verify_command(
+    change: &Change,
+    scope: Scope,
+    options: &CommandOptions,
+) -> Option<String> {
+    if matches!(change, Change::Removed { .. } | Change::Normalized { .. })
+        || change.root_key() == ROOT_KEY
+    {
+        return None;
+    }
+    let command = format!(
+        "defaults read {} {}",
+        domain_argument(target_domain(change.domain(), options), options.quote),
+        quote(change.root_key(), options.quote)
+    );
+    Some(apply_scope(apply_host(command, options.host), scope))
+}
+
+/// Append the verification read on its own line when `options.verify` is set
+pub fn with_verify(
+    command: String,
+    change: &Change,
+    scope: Scope,
+    options: &CommandOptions,
+) -> String {
+    match generate_verify_command(change, scope, options) {
+        Some(verify) if options.verify => format!("{}\n{}", command, verify),
+        _ => command,
+    }
+}
+
+/// Prefix `sudo` to a command for a system-scoped domain (comments are left alone)
+pub fn apply_scope(command: String, scope: Scope) -> String {
+    if scope == Scope::System && !command.starts_with('#') {
+        format!("sudo {}", command)
+    } else {
+        command
+    }
+}
+
+/// The change as one JSON object `{domain, key, type, value}`, for
+/// declarative tools like nix-darwin or Ansible. Nested changes give the whole
+/// top-level value (as their commands do); removals have `null` type and value.
+pub fn generate_structured(change: &Change) -> String {
+    let value = match change {
+        Change::Removed { .. } => None,
+        Change::Nested { new_root, .. } => Some(new_root.as_ref()),
+        _ => change.new_value(),
+    };
+    format!(
+        "{{\"domain\": {}, \"key\": {}, \"type\": {}, \"value\": {}}}",
+        json_string(change.domain()),
+        json_string(change.root_key()),
+        value.map_or("null".to_string(), |v| json_string(value_type_label(v))),
+        value.map_or("null".to_string(), json_value)
+    )
+}
+
+/// A plist value as JSON; dates become ISO 8601 strings and data base64
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Boolean(b) => b.to_string(),
+        Value::Integer(i) => format_integer(i),
+        Value::Real(f) if f.is_finite() => f.to_string(),
+        Value::String(s) => json_string(s),
+        Value::Date(d) => json_string(&format_date(d)),
+        Value::Data(d) => json_string(&base64_encode(d)),
+        Value::Array(arr) => format!(
+            "[{}]",
+            arr.iter().map(json_value).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Dictionary(dict) => format!(
+            "{{{}}}",
+            dict.iter()
+                .map(|(k, v)| format!("{}: {}", json_string(k), json_value(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::Uid(uid) => uid.get().to_string(),
+        _ => "null".to_string(),
+    }
+}
+
+/// Quote a JSON string, escaping quotes, backslashes and control characters
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `defaults write` only takes a dictionary for a whole domain, so a domain
+/// exported as an array (kept under `ROOT_KEY`) cannot be written back
+fn root_value_comment(domain: &str) -> String {
+    format!(
+        "# {}: the domain is not a dictionary, so `defaults` cannot write it",
+        domain
+    )
+}
+
+/// Generate the command that reverts a change
+pub fn generate_undo_command(change: &Change, options: &CommandOptions) -> String {
+    if change.root_key() == ROOT_KEY {
+        return root_value_comment(change.domain());
+    }
+    let target = target_domain(change.domain(), options);
+    let style = options.quote;
+    match change {
+        Change::Added { key, .. } => format!(
+            "defaults delete {} {}",
+            domain_argument(target, style),
+            quote(key, style)
+        ),
+        Change::Removed { key, old_value, .. } | Change::Modified { key, old_value, .. } => {
+            format_write_command(target, key, old_value, style)
+        }
+        Change::Nested {
+            key,
+            root_key,
+            old_value,
+            new_value,
+            old_root,
+            ..
+        } => nested_plutil_command(
+            target,
+            key,
+            old_value.as_ref(),
+            old_value.is_none() || new_value.is_none(),
+            root_key,
+            old_root,
+            options,
+        )
+        .unwrap_or_else(|| format_write_command(target, root_key, old_root, style)),
+        Change::Normalized { domain, key, .. } => {
+            format!("# {} {}: nothing to undo", domain, key)
+        }
+    }
+}
+
+/// `plutil` command for a nested change when `options.plutil` is set.
+///
+/// Returns `None` (so the caller rewrites the top-level key with `defaults`)
+/// for per-host preferences, whose file name `plutil` can't know, for paths a
+/// plutil keypath can't spell: keys containing `.`, `[` or `]`, and for array
+/// elements that are added or removed (`resized`), since each insert or remove
+/// shifts the indices the other leaves' commands use.
+/// `root` is the value of `root_key` after the command runs, and `value` the
+/// leaf in it (`None` when the leaf is gone).
+fn nested_plutil_command(
+    domain: &str,
+    key_path: &str,
+    value: Option<&Value>,
+    resized: bool,
+    root_key: &str,
+    root: &Value,
+    options: &CommandOptions,
+) -> Option<String> {
+    if !options.plutil || options.host != CommandScope::Global {
+        return None;
+    }
+    // The diff's path notation is ambiguous for keys with dots in them, so
+    // check that the segments lead back to the changed value
+    let segments = plutil_segments(key_path);
+    let [first, inner @ ..] = segments.as_slice() else {
+        return None;
+    };
+    if first != root_key {
+        return None;
+    }
+    let walk = |path: &[String]| {
+        path.iter().try_fold(root, |value, segment| match value {
+            Value::Dictionary(dict) => dict.get(segment),
+            Value::Array(arr) => arr.get(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+    };
+    let consistent = match value {
+        Some(value) => walk(inner) == Some(value),
+        None => {
+            !inner.is_empty() && walk(&inner[..inner.len() - 1]).is_some() && walk(inner).is_none()
+        }
+    };
+    if !consistent {
+        return None;
+    }
+    if resized
+        && let Some(Value::Array(_)) = inner.split_last().and_then(|(_, parent)| walk(parent))
+    {
+        return None;
+    }
+    generate_plutil_command(domain, key_path, value, options.quote)
+}
+
+/// Segments of a diff path: `window.frame[2]` is `window`, `frame`, `2`
+fn plutil_segments(key_path: &str) -> Vec<String> {
+    key_path
+        .split('.')
+        .flat_map(|part| part.split('[').map(|s| s.trim_end_matches(']')))
+        .map(str::to_string)
+        .collect()
+}
+
+/// `plutil -replace` for one value inside a domain's plist file, or
+/// `plutil -remove` when `value` is `None`.
+///
+/// `key_path` uses the diff's notation (`window.frame.width`, `items[3]`).
+/// `plutil` edits the file behind `cfprefsd`'s back, so the change shows up
+/// once the app (or `cfprefsd`) rereads it. Returns `None` for UIDs, which
+/// `plutil` cannot write.
+pub fn generate_plutil_command(
+    domain: &str,
+    key_path: &str,
+    value: Option<&Value>,
+    style: QuoteStyle,
+) -> Option<String> {
+    let file = plist_file_argument(domain, style);
+    let key_path = quote(&plutil_segments(key_path).join("."), style);
+    let Some(value) = value else {
+        return Some(format!("plutil -remove {} {}", key_path, file));
+    };
+    let argument = match value {
+        Value::Boolean(b) => format!("-bool {}", if *b { "YES" } else { "NO" }),
+        Value::Integer(i) => format!("-integer {}", format_integer(i)),
+        Value::Real(f) => format!("-float {}", f),
+        Value::String(s) => format!("-string {}", quote(s, style)),
+        Value::Date(d) => format!("-date {}", format_date(d)),
+        Value::Data(data) => format!("-data {}", base64_encode(data)),
+        Value::Array(_) | Value::Dictionary(_) => {
+            format!("-xml {}", quote(&value_to_xml_fragment(value), style))
         }
+        _ => return None,
+    };
+    Some(format!(
+        "plutil -replace {} {} {}",
+        key_path, argument, file
+    ))
+}
+
+/// The plist file behind a domain: a path-style domain's own file, otherwise
+/// the one in `~/Library/Preferences`
+fn plist_file_argument(domain: &str, style: QuoteStyle) -> String {
+    if domain.starts_with('/') {
+        return quote(&format!("{}.plist", domain_arg(domain)), style);
+    }
+    let name = if is_global_domain(domain) {
+        ".GlobalPreferences"
+    } else {
+        domain
+    };
+    let file = format!("{}.plist", name);
+    // Left bare when it can be, so the path reads naturally
+    if file
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    {
+        format!("~/Library/Preferences/{}", file)
+    } else {
+        format!("~/Library/Preferences/{}", quote(&file, style))
     }
 }
 
 /// Generate defaults write command
-fn generate_write_command(domain: &str, key: &str, value: &Value) -> String {
-    let domain = escape_string(domain);
-    let key = escape_string(key);
+fn generate_write_command(
+    domain: &str,
+    key: &str,
+    value: &Value,
+    old_value: Option<&Value>,
+    options: &CommandOptions,
+) -> String {
+    if let Some(command) = old_value.and_then(|old| {
+        format_array_add_command(domain, key, old, value, options.quote)
+            .or_else(|| format_dict_add_command(domain, key, old, value, options.quote))
+    }) {
+        return command;
+    }
+    let command = format_write_command(domain, key, value, options.quote);
+    match old_value.and_then(|old| format_numeric_delta(old, value)) {
+        Some(delta) if options.show_delta => format!("{} {}", command, delta),
+        _ => command,
+    }
+}
+
+/// `defaults write -array-add` for the elements appended to an array, so
+/// entries added to the key in the meantime are kept. Returns `None` unless
+/// the new array is the old one plus scalar elements at the end.
+fn format_array_add_command(
+    domain: &str,
+    key: &str,
+    old_value: &Value,
+    new_value: &Value,
+    style: QuoteStyle,
+) -> Option<String> {
+    let (Value::Array(old), Value::Array(new)) = (old_value, new_value) else {
+        return None;
+    };
+    if new.len() <= old.len() || !new.starts_with(old) {
+        return None;
+    }
+    let added = &new[old.len()..];
+    if !added.iter().all(is_array_scalar) {
+        return None;
+    }
+    Some(format!(
+        "defaults write {} {} -array-add {}",
+        domain_argument(domain, style),
+        quote(key, style),
+        format_array_elements(added, style)
+    ))
+}
+
+/// `defaults write -dict-add` for the entries a dictionary gained, leaving the
+/// rest of it (including nested values `-dict` cannot write) untouched.
+/// Returns `None` unless every old entry is unchanged and each new entry is a
+/// value `-dict-add` can take.
+fn format_dict_add_command(
+    domain: &str,
+    key: &str,
+    old_value: &Value,
+    new_value: &Value,
+    style: QuoteStyle,
+) -> Option<String> {
+    let (Value::Dictionary(old), Value::Dictionary(new)) = (old_value, new_value) else {
+        return None;
+    };
+    if new.len() <= old.len() || old.iter().any(|(k, v)| new.get(k) != Some(v)) {
+        return None;
+    }
+    let pairs = new
+        .iter()
+        .filter(|(k, _)| !old.contains_key(k))
+        .map(|(k, v)| format_dict_value(k, v, style))
+        .collect::<Option<Vec<_>>>()?;
+    Some(format!(
+        "defaults write {} {} -dict-add {}",
+        domain_argument(domain, style),
+        quote(key, style),
+        pairs.join(" ")
+    ))
+}
+
+/// Format a `# was X, now Y, +D` comment for numeric value changes
+fn format_numeric_delta(old_value: &Value, new_value: &Value) -> Option<String> {
+    match (old_value, new_value) {
+        (Value::Integer(old), Value::Integer(new)) => {
+            let as_wide = |i: &plist::Integer| {
+                i.as_signed()
+                    .map(i128::from)
+                    .or(i.as_unsigned().map(i128::from))
+            };
+            let (old, new) = (as_wide(old)?, as_wide(new)?);
+            let delta = new - old;
+            Some(format!("# was {}, now {}, {:+}", old, new, delta))
+        }
+        (Value::Real(old), Value::Real(new)) => Some(format!(
+            "# was {}, now {}, {}",
+            old,
+            new,
+            format_real_delta(new - old)
+        )),
+        _ => None,
+    }
+}
+
+/// A signed difference of reals to six decimals without trailing zeros, so
+/// `0.4 - 0.1` reads `+0.3` rather than `+0.30000000000000004`
+fn format_real_delta(delta: f64) -> String {
+    let text = format!("{:+.6}", delta);
+    match text.trim_end_matches('0').trim_end_matches('.') {
+        // A tiny negative delta rounds to zero too
+        "-0" => "+0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+/// Format the defaults write command for a value
+fn format_write_command(domain: &str, key: &str, value: &Value, style: QuoteStyle) -> String {
+    let domain = domain_argument(domain, style);
+    let key = quote(key, style);
     match value {
         Value::Boolean(b) => {
             format!(
-                "defaults write \"{}\" \"{}\" -bool {}",
+                "defaults write {} {} -bool {}",
                 domain,
                 key,
                 if *b { "true" } else { "false" }
@@ -37,71 +722,69 @@ fn generate_write_command(domain: &str, key: &str, value: &Value) -> String {
         }
         Value::Integer(i) => {
             format!(
-                "defaults write \"{}\" \"{}\" -int {}",
+                "defaults write {} {} -int {}",
                 domain,
                 key,
-                i.as_signed().unwrap_or(0)
+                format_integer(i)
             )
         }
         Value::Real(f) => {
-            format!("defaults write \"{}\" \"{}\" -float {}", domain, key, f)
+            format!("defaults write {} {} -float {}", domain, key, f)
         }
         Value::String(s) => {
             format!(
-                "defaults write \"{}\" \"{}\" -string \"{}\"",
+                "defaults write {} {} -string {}",
                 domain,
                 key,
-                escape_string(s)
+                quote(s, style)
             )
         }
         Value::Data(d) => {
             let hex: String = d.iter().map(|b| format!("{:02x}", b)).collect();
-            format!("defaults write \"{}\" \"{}\" -data {}", domain, key, hex)
+            format!("defaults write {} {} -data {}", domain, key, hex)
         }
         Value::Array(arr) => {
-            let elements = format_array_elements(arr);
-            format!(
-                "defaults write \"{}\" \"{}\" -array {}",
-                domain, key, elements
-            )
+            if arr.iter().any(|v| !is_array_scalar(v)) {
+                format_xml_write_command(&domain, &key, value)
+            } else if arr.is_empty() {
+                // `-array` with no elements writes an empty array
+                format!("defaults write {} {} -array", domain, key)
+            } else {
+                let elements = format_array_elements(arr, style);
+                format!("defaults write {} {} -array {}", domain, key, elements)
+            }
         }
         Value::Dictionary(dict) => {
-            if has_nested_structure(dict) {
-                format!(
-                    "# Nested dictionary not supported by defaults command: {} {}",
-                    domain, key
-                )
+            // A bare `-dict` reads like a truncated command, so an empty
+            // dictionary is written as a plist fragment instead
+            if dict.is_empty() || has_nested_structure(dict) {
+                format_xml_write_command(&domain, &key, value)
             } else {
-                let pairs = format_dict_pairs(dict);
-                format!("defaults write \"{}\" \"{}\" -dict {}", domain, key, pairs)
+                let pairs = format_dict_pairs(dict, style);
+                format!("defaults write {} {} -dict {}", domain, key, pairs)
             }
         }
         Value::Date(d) => {
             format!(
-                "defaults write \"{}\" \"{}\" -date \"{}\"",
-                domain,
-                key,
-                d.to_xml_format()
-            )
-        }
-        Value::Uid(u) => {
-            format!(
-                "defaults write \"{}\" \"{}\" -int {} # UID type stored as integer",
+                "defaults write {} {} -date {}",
                 domain,
                 key,
-                u.get()
+                quote(&format_date(d), style)
             )
         }
+        // `defaults` has no UID flag, but its plist parser turns a
+        // `CF$UID` dictionary back into a UID
+        Value::Uid(_) => format_xml_write_command(&domain, &key, value),
         _ => format!("# Unsupported type for key: {}", key),
     }
 }
 
 /// Format array elements as command arguments
-fn format_array_elements(arr: &[Value]) -> String {
+fn format_array_elements(arr: &[Value], style: QuoteStyle) -> String {
     arr.iter()
         .filter_map(|v| match v {
-            Value::String(s) => Some(format!("-string \"{}\"", escape_string(s))),
-            Value::Integer(i) => Some(format!("-int {}", i.as_signed().unwrap_or(0))),
+            Value::String(s) => Some(format!("-string {}", quote(s, style))),
+            Value::Integer(i) => Some(format!("-int {}", format_integer(i))),
             Value::Real(f) => Some(format!("-float {}", f)),
             Value::Boolean(b) => Some(format!("-bool {}", if *b { "true" } else { "false" })),
             _ => None, // Skip complex types
@@ -110,47 +793,208 @@ fn format_array_elements(arr: &[Value]) -> String {
         .join(" ")
 }
 
-/// Check if dictionary contains nested structures
+/// Command that restarts the process reading a domain, for domains whose
+/// changes only take effect after a restart
+pub fn restart_command_for_domain(domain: &str) -> Option<String> {
+    let process = match domain {
+        "com.apple.dock" | "com.apple.spaces" => "Dock",
+        "com.apple.finder" => "Finder",
+        "com.apple.systemuiserver" | "com.apple.menuextra.clock" | "com.apple.screencapture" => {
+            "SystemUIServer"
+        }
+        "com.apple.Safari" => "Safari",
+        "com.apple.controlcenter" => "ControlCenter",
+        _ => return None,
+    };
+    Some(format!("killall {}", process))
+}
+
+/// One ready-to-run line: the command, then the restart that makes it take
+/// effect (`...; killall Dock`). Comment lines are dropped and the rest are
+/// joined with `; `. `None` when the domain has no known restart or the command
+/// is only comments.
+pub fn with_restart(command: &str, domain: &str) -> Option<String> {
+    let restart = restart_command_for_domain(domain)?;
+    let lines: Vec<&str> = command
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+    Some(format!("{}; {}", lines.join("; "), restart))
+}
+
+/// Domain as a `defaults` argument: `-g` for the global domain, otherwise quoted
+fn domain_argument(domain: &str, style: QuoteStyle) -> String {
+    if is_global_domain(domain) {
+        "-g".to_string()
+    } else {
+        quote(domain, style)
+    }
+}
+
+/// Whether `format_array_elements` can express the value as an `-array` argument
+fn is_array_scalar(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::String(_) | Value::Integer(_) | Value::Real(_) | Value::Boolean(_)
+    )
+}
+
+/// Write a nested value by passing it as a single-quoted XML plist fragment,
+/// which `defaults write` parses as a property list
+fn format_xml_write_command(domain: &str, key: &str, value: &Value) -> String {
+    format!(
+        "defaults write {} {} '{}'",
+        domain,
+        key,
+        value_to_xml_fragment(value).replace('\'', r"'\''")
+    )
+}
+
+/// Serialize a value as a compact, single-line XML plist fragment (no header)
+pub fn value_to_xml_fragment(value: &Value) -> String {
+    match value {
+        Value::Boolean(true) => "<true/>".to_string(),
+        Value::Boolean(false) => "<false/>".to_string(),
+        Value::Integer(i) => format!("<integer>{}</integer>", i),
+        Value::Real(f) => format!("<real>{}</real>", f),
+        Value::String(s) => format!("<string>{}</string>", escape_xml(s)),
+        Value::Date(d) => format!("<date>{}</date>", format_date(d)),
+        Value::Data(d) => format!("<data>{}</data>", base64_encode(d)),
+        // XML plists spell keyed-archiver UIDs as a one-key dictionary
+        Value::Uid(u) => format!(
+            "<dict><key>CF$UID</key><integer>{}</integer></dict>",
+            u.get()
+        ),
+        Value::Array(arr) => {
+            let items: String = arr.iter().map(value_to_xml_fragment).collect();
+            format!("<array>{}</array>", items)
+        }
+        Value::Dictionary(dict) => {
+            let entries: String = dict
+                .iter()
+                .map(|(k, v)| format!("<key>{}</key>{}", escape_xml(k), value_to_xml_fragment(v)))
+                .collect();
+            format!("<dict>{}</dict>", entries)
+        }
+        _ => String::new(),
+    }
+}
+
+/// Escape text for XML element content; newlines become character references
+/// so the fragment stays on one line
+fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\n' => out.push_str("&#10;"),
+            '\r' => out.push_str("&#13;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Standard base64 with padding, as used by `<data>` elements and OSC 52
+pub fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Check if dictionary contains nested structures or UIDs, which `-dict` cannot express
 fn has_nested_structure(dict: &plist::Dictionary) -> bool {
     dict.values()
-        .any(|v| matches!(v, Value::Dictionary(_) | Value::Array(_)))
+        .any(|v| matches!(v, Value::Dictionary(_) | Value::Array(_) | Value::Uid(_)))
 }
 
 /// Format dictionary as -dict arguments
-fn format_dict_pairs(dict: &plist::Dictionary) -> String {
+fn format_dict_pairs(dict: &plist::Dictionary, style: QuoteStyle) -> String {
     dict.iter()
-        .filter_map(|(k, v)| format_dict_value(k, v))
+        .filter_map(|(k, v)| format_dict_value(k, v, style))
         .collect::<Vec<_>>()
         .join(" ")
 }
 
 /// Format a single dictionary key-value pair
-fn format_dict_value(key: &str, value: &Value) -> Option<String> {
+fn format_dict_value(key: &str, value: &Value, style: QuoteStyle) -> Option<String> {
+    let key = quote(key, style);
     match value {
         Value::Boolean(b) => Some(format!(
-            "\"{}\" -bool {}",
-            escape_string(key),
+            "{} -bool {}",
+            key,
             if *b { "true" } else { "false" }
         )),
-        Value::Integer(i) => Some(format!(
-            "\"{}\" -int {}",
-            escape_string(key),
-            i.as_signed().unwrap_or(0)
-        )),
-        Value::Real(f) => Some(format!("\"{}\" -float {}", escape_string(key), f)),
-        Value::String(s) => Some(format!(
-            "\"{}\" -string \"{}\"",
-            escape_string(key),
-            escape_string(s)
-        )),
+        Value::Integer(i) => Some(format!("{} -int {}", key, format_integer(i))),
+        Value::Real(f) => Some(format!("{} -float {}", key, f)),
+        Value::String(s) => Some(format!("{} -string {}", key, quote(s, style))),
         Value::Data(d) => {
             let hex: String = d.iter().map(|b| format!("{:02x}", b)).collect();
-            Some(format!("\"{}\" -data {}", escape_string(key), hex))
+            Some(format!("{} -data {}", key, hex))
         }
         _ => None,
     }
 }
 
+/// Quote a string as one shell word.
+///
+/// Strings with newlines, tabs or other control characters use bash's `$'...'`
+/// quoting in either style, so the command stays on one line when pasted.
+fn quote(s: &str, style: QuoteStyle) -> String {
+    if s.chars().any(char::is_control) {
+        return ansi_c_quote(s);
+    }
+    match style {
+        QuoteStyle::Double => format!("\"{}\"", escape_string(s)),
+        QuoteStyle::Single => format!("'{}'", s.replace('\'', r"'\''")),
+    }
+}
+
+/// `$'...'` quoting, where `\n`, `\t` and `\r` stand for the control characters
+fn ansi_c_quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 3);
+    out.push_str("$'");
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\'' => out.push_str("\\'"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            // `\xHH` per UTF-8 byte, since bash 3.2 on macOS has no `\u`
+            c if c.is_control() => {
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    out.push_str(&format!("\\x{:02x}", byte));
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('\'');
+    out
+}
+
 /// Escape string for shell
 fn escape_string(s: &str) -> String {
     s.replace('\\', "\\\\")
@@ -162,9 +1006,111 @@ fn escape_string(s: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::diff::Change;
+    use crate::defaults::Snapshot;
+    use crate::defaults::types::DomainSettings;
+    use crate::diff::{Change, DiffOptions, detect_diff};
     use plist::Value;
 
+    // --- quote tests ---
+
+    /// What the shell makes of a quoted word
+    fn shell_word(quoted: &str) -> String {
+        let output = std::process::Command::new("bash")
+            .args(["-c", &format!("printf %s {}", quoted)])
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_quote_round_trips_through_shell() {
+        for value in [
+            "with spaces",
+            r#"say "hi""#,
+            "it's",
+            "$HOME and `cmd`",
+            r"C:\path\$x",
+            r"'\''",
+            "line1\nline2",
+            "tab\there\r\n\\n 'quoted' \u{1b}",
+        ] {
+            for style in [QuoteStyle::Double, QuoteStyle::Single] {
+                assert_eq!(shell_word(&quote(value, style)), value, "{:?}", style);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quote_newline() {
+        assert_eq!(
+            quote("line1\nline2", QuoteStyle::Double),
+            r"$'line1\nline2'"
+        );
+        assert_eq!(
+            quote("line1\nline2", QuoteStyle::Single),
+            r"$'line1\nline2'"
+        );
+    }
+
+    #[test]
+    fn test_single_quote_style_command() {
+        let change = Change::Added {
+            domain: "com.test".to_string(),
+            key: "it's".to_string(),
+            value: Value::String("$5".to_string()),
+        };
+        let options = CommandOptions {
+            quote: QuoteStyle::Single,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_command(&change, &options),
+            r#"defaults write 'com.test' 'it'\''s' -string '$5'"#
+        );
+    }
+
+    #[test]
+    fn test_generate_verify_command() {
+        let change = Change::Modified {
+            domain: "com.apple.dock".to_string(),
+            key: "tilesize".to_string(),
+            old_value: Value::Integer(36.into()),
+            new_value: Value::Integer(48.into()),
+        };
+        let options = CommandOptions {
+            verify: true,
+            host: CommandScope::CurrentHost,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_verify_command(&change, Scope::System, &options).as_deref(),
+            Some(r#"sudo defaults -currentHost read "com.apple.dock" "tilesize""#)
+        );
+        assert_eq!(
+            with_verify("write".to_string(), &change, Scope::User, &options),
+            "write\ndefaults -currentHost read \"com.apple.dock\" \"tilesize\""
+        );
+
+        let removed = Change::Removed {
+            domain: "com.apple.dock".to_string(),
+            key: "tilesize".to_string(),
+            old_value: Value::Integer(36.into()),
+        };
+        assert_eq!(
+            generate_verify_command(&removed, Scope::User, &options),
+            None
+        );
+        assert_eq!(
+            with_verify(
+                "write".to_string(),
+                &change,
+                Scope::User,
+                &CommandOptions::default()
+            ),
+            "write"
+        );
+    }
+
     // --- escape_string tests ---
 
     #[test]
@@ -202,7 +1148,7 @@ mod tests {
             value: Value::Boolean(true),
         };
         assert_eq!(
-            generate_command(&change),
+            generate_command(&change, &CommandOptions::default()),
             r#"defaults write "com.example" "enabled" -bool true"#
         );
     }
@@ -215,7 +1161,7 @@ mod tests {
             value: Value::String("hello".to_string()),
         };
         assert_eq!(
-            generate_command(&change),
+            generate_command(&change, &CommandOptions::default()),
             r#"defaults write "com.example" "name" -string "hello""#
         );
     }
@@ -228,7 +1174,7 @@ mod tests {
             value: Value::Integer(42.into()),
         };
         assert_eq!(
-            generate_command(&change),
+            generate_command(&change, &CommandOptions::default()),
             r#"defaults write "com.example" "count" -int 42"#
         );
     }
@@ -242,7 +1188,7 @@ mod tests {
             new_value: Value::Boolean(true),
         };
         assert_eq!(
-            generate_command(&change),
+            generate_command(&change, &CommandOptions::default()),
             r#"defaults write "com.example" "flag" -bool true"#
         );
     }
@@ -255,41 +1201,130 @@ mod tests {
             old_value: Value::Boolean(false),
         };
         assert_eq!(
-            generate_command(&change),
+            generate_command(&change, &CommandOptions::default()),
             r#"defaults delete "com.example" "old_key""#
         );
     }
 
-    // --- format_array_elements tests ---
-
     #[test]
-    fn test_format_array_string() {
-        let arr = vec![Value::String("hello".to_string())];
-        assert_eq!(format_array_elements(&arr), r#"-string "hello""#);
+    fn test_generate_command_modified_int_delta() {
+        let change = Change::Modified {
+            domain: "com.example".to_string(),
+            key: "tilesize".to_string(),
+            old_value: Value::Integer(5.into()),
+            new_value: Value::Integer(8.into()),
+        };
+        let options = CommandOptions {
+            show_delta: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_command(&change, &options),
+            r#"defaults write "com.example" "tilesize" -int 8 # was 5, now 8, +3"#
+        );
     }
 
     #[test]
-    fn test_format_array_int() {
-        let arr = vec![Value::Integer(10.into())];
-        assert_eq!(format_array_elements(&arr), "-int 10");
+    fn test_generate_command_modified_real_delta() {
+        let change = Change::Modified {
+            domain: "com.example".to_string(),
+            key: "scale".to_string(),
+            old_value: Value::Real(2.5),
+            new_value: Value::Real(1.5),
+        };
+        let options = CommandOptions {
+            show_delta: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_command(&change, &options),
+            r#"defaults write "com.example" "scale" -float 1.5 # was 2.5, now 1.5, -1"#
+        );
     }
 
     #[test]
-    fn test_format_array_float() {
-        let arr = vec![Value::Real(3.14)];
-        assert_eq!(format_array_elements(&arr), "-float 3.14");
+    fn test_generate_command_delta_disabled() {
+        let change = Change::Modified {
+            domain: "com.example".to_string(),
+            key: "tilesize".to_string(),
+            old_value: Value::Integer(5.into()),
+            new_value: Value::Integer(8.into()),
+        };
+        assert_eq!(
+            generate_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.example" "tilesize" -int 8"#
+        );
+    }
+
+    // --- format_array_elements tests ---
+
+    #[test]
+    fn test_generate_command_empty_array() {
+        let change = Change::Added {
+            domain: "com.test".to_string(),
+            key: "items".to_string(),
+            value: Value::Array(vec![]),
+        };
+        assert_eq!(
+            generate_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.test" "items" -array"#
+        );
+    }
+
+    #[test]
+    fn test_generate_command_empty_dict() {
+        let change = Change::Added {
+            domain: "com.test".to_string(),
+            key: "settings".to_string(),
+            value: Value::Dictionary(plist::Dictionary::new()),
+        };
+        assert_eq!(
+            generate_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.test" "settings" '<dict></dict>'"#
+        );
+    }
+
+    #[test]
+    fn test_format_array_string() {
+        let arr = vec![Value::String("hello".to_string())];
+        assert_eq!(
+            format_array_elements(&arr, QuoteStyle::Double),
+            r#"-string "hello""#
+        );
+    }
+
+    #[test]
+    fn test_format_array_int() {
+        let arr = vec![Value::Integer(10.into())];
+        assert_eq!(format_array_elements(&arr, QuoteStyle::Double), "-int 10");
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)] // 3.14 is just a float, not pi
+    fn test_format_array_float() {
+        let arr = vec![Value::Real(3.14)];
+        assert_eq!(
+            format_array_elements(&arr, QuoteStyle::Double),
+            "-float 3.14"
+        );
     }
 
     #[test]
     fn test_format_array_bool() {
         let arr = vec![Value::Boolean(false)];
-        assert_eq!(format_array_elements(&arr), "-bool false");
+        assert_eq!(
+            format_array_elements(&arr, QuoteStyle::Double),
+            "-bool false"
+        );
     }
 
     #[test]
     fn test_format_array_mixed() {
         let arr = vec![Value::String("a".to_string()), Value::Integer(1.into())];
-        assert_eq!(format_array_elements(&arr), r#"-string "a" -int 1"#);
+        assert_eq!(
+            format_array_elements(&arr, QuoteStyle::Double),
+            r#"-string "a" -int 1"#
+        );
     }
 
     // --- format_dict_pairs tests ---
@@ -298,7 +1333,7 @@ mod tests {
     fn test_format_dict_pairs_basic() {
         let mut dict = plist::Dictionary::new();
         dict.insert("key1".to_string(), Value::Boolean(true));
-        let result = format_dict_pairs(&dict);
+        let result = format_dict_pairs(&dict, QuoteStyle::Double);
         assert_eq!(result, r#""key1" -bool true"#);
     }
 
@@ -306,7 +1341,7 @@ mod tests {
     fn test_format_dict_pairs_string_value() {
         let mut dict = plist::Dictionary::new();
         dict.insert("name".to_string(), Value::String("val".to_string()));
-        let result = format_dict_pairs(&dict);
+        let result = format_dict_pairs(&dict, QuoteStyle::Double);
         assert_eq!(result, r#""name" -string "val""#);
     }
 
@@ -314,7 +1349,7 @@ mod tests {
     fn test_format_dict_pairs_int_value() {
         let mut dict = plist::Dictionary::new();
         dict.insert("num".to_string(), Value::Integer(7.into()));
-        let result = format_dict_pairs(&dict);
+        let result = format_dict_pairs(&dict, QuoteStyle::Double);
         assert_eq!(result, r#""num" -int 7"#);
     }
 
@@ -350,4 +1385,666 @@ mod tests {
         let dict = plist::Dictionary::new();
         assert!(!has_nested_structure(&dict));
     }
+
+    // --- generate_undo_command tests ---
+
+    #[test]
+    fn test_undo_added_deletes_key() {
+        let change = Change::Added {
+            domain: "com.apple.dock".to_string(),
+            key: "autohide".to_string(),
+            value: Value::Boolean(true),
+        };
+        assert_eq!(
+            generate_undo_command(&change, &CommandOptions::default()),
+            r#"defaults delete "com.apple.dock" "autohide""#
+        );
+    }
+
+    #[test]
+    fn test_undo_removed_rewrites_old_value() {
+        let change = Change::Removed {
+            domain: "com.apple.dock".to_string(),
+            key: "tilesize".to_string(),
+            old_value: Value::Integer(36.into()),
+        };
+        assert_eq!(
+            generate_undo_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.apple.dock" "tilesize" -int 36"#
+        );
+    }
+
+    #[test]
+    fn test_undo_modified_writes_old_value() {
+        let change = Change::Modified {
+            domain: "com.apple.dock".to_string(),
+            key: "orientation".to_string(),
+            old_value: Value::String("bottom".to_string()),
+            new_value: Value::String("left".to_string()),
+        };
+        assert_eq!(
+            generate_undo_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.apple.dock" "orientation" -string "bottom""#
+        );
+    }
+
+    // --- nested value tests ---
+
+    #[test]
+    fn test_value_to_xml_fragment_array_of_dicts() {
+        let mut first = plist::Dictionary::new();
+        first.insert("name".to_string(), Value::String("a & b".to_string()));
+        first.insert("size".to_string(), Value::Integer(3.into()));
+        let mut second = plist::Dictionary::new();
+        second.insert("enabled".to_string(), Value::Boolean(false));
+        let value = Value::Array(vec![Value::Dictionary(first), Value::Dictionary(second)]);
+
+        assert_eq!(
+            value_to_xml_fragment(&value),
+            "<array>\
+             <dict><key>name</key><string>a &amp; b</string><key>size</key><integer>3</integer></dict>\
+             <dict><key>enabled</key><false/></dict>\
+             </array>"
+        );
+    }
+
+    #[test]
+    fn test_value_to_xml_fragment_data_is_base64() {
+        assert_eq!(
+            value_to_xml_fragment(&Value::Data(b"hello".to_vec())),
+            "<data>aGVsbG8=</data>"
+        );
+    }
+
+    #[test]
+    fn test_nested_dict_written_as_xml() {
+        let mut inner = plist::Dictionary::new();
+        inner.insert("it's".to_string(), Value::Boolean(true));
+        let mut outer = plist::Dictionary::new();
+        outer.insert("inner".to_string(), Value::Dictionary(inner));
+        let change = Change::Added {
+            domain: "com.test".to_string(),
+            key: "k".to_string(),
+            value: Value::Dictionary(outer),
+        };
+        assert_eq!(
+            generate_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.test" "k" '<dict><key>inner</key><dict><key>it'\''s</key><true/></dict></dict>'"#
+        );
+    }
+
+    // --- CommandScope tests ---
+
+    #[test]
+    fn test_current_host_write() {
+        let change = Change::Added {
+            domain: "com.apple.screensaver".to_string(),
+            key: "idleTime".to_string(),
+            value: Value::Integer(0.into()),
+        };
+        let options = CommandOptions {
+            host: CommandScope::CurrentHost,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_command(&change, &options),
+            r#"defaults -currentHost write "com.apple.screensaver" "idleTime" -int 0"#
+        );
+    }
+
+    #[test]
+    fn test_current_host_leaves_comments_alone() {
+        let change = Change::Normalized {
+            domain: "com.test".to_string(),
+            key: "k".to_string(),
+            old_value: Value::Integer(1.into()),
+            new_value: Value::Real(1.0),
+            reason: crate::diff::types::Normalization::Numeric,
+        };
+        let options = CommandOptions {
+            host: CommandScope::CurrentHost,
+            ..Default::default()
+        };
+        assert!(generate_command(&change, &options).starts_with("# "));
+    }
+
+    #[test]
+    fn test_generate_command_unsigned_integer() {
+        let change = Change::Added {
+            domain: "com.test".to_string(),
+            key: "flags".to_string(),
+            value: Value::Integer(u64::MAX.into()),
+        };
+        assert_eq!(
+            generate_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.test" "flags" -int 18446744073709551615"#
+        );
+
+        let arr = vec![Value::Integer(u64::MAX.into())];
+        assert_eq!(
+            format_array_elements(&arr, QuoteStyle::Double),
+            "-int 18446744073709551615"
+        );
+    }
+
+    #[test]
+    fn test_numeric_delta_unsigned() {
+        let old = Value::Integer((u64::MAX - 2).into());
+        let new = Value::Integer(u64::MAX.into());
+        assert_eq!(
+            format_numeric_delta(&old, &new).as_deref(),
+            Some("# was 18446744073709551613, now 18446744073709551615, +2")
+        );
+    }
+
+    #[test]
+    fn test_numeric_delta_real_is_rounded() {
+        let delta = |old, new| format_numeric_delta(&Value::Real(old), &Value::Real(new));
+        assert_eq!(delta(0.1, 0.4).as_deref(), Some("# was 0.1, now 0.4, +0.3"));
+        assert_eq!(delta(2.5, 0.5).as_deref(), Some("# was 2.5, now 0.5, -2"));
+        assert_eq!(
+            delta(1.0, 1.0000001).as_deref(),
+            Some("# was 1, now 1.0000001, +0")
+        );
+        assert_eq!(
+            delta(1.0000001, 1.0).as_deref(),
+            Some("# was 1.0000001, now 1, +0")
+        );
+    }
+
+    #[test]
+    fn test_uid_change_writes_cf_uid_dict() {
+        let change = Change::Modified {
+            domain: "com.test".to_string(),
+            key: "root".to_string(),
+            old_value: Value::Uid(plist::Uid::new(1)),
+            new_value: Value::Uid(plist::Uid::new(5)),
+        };
+        assert_eq!(
+            generate_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.test" "root" '<dict><key>CF$UID</key><integer>5</integer></dict>'"#
+        );
+        assert_eq!(
+            generate_undo_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.test" "root" '<dict><key>CF$UID</key><integer>1</integer></dict>'"#
+        );
+    }
+
+    #[test]
+    fn test_uid_inside_dict_uses_xml() {
+        let mut dict = plist::Dictionary::new();
+        dict.insert("$top".to_string(), Value::Uid(plist::Uid::new(2)));
+        let change = Change::Added {
+            domain: "com.test".to_string(),
+            key: "archive".to_string(),
+            value: Value::Dictionary(dict),
+        };
+        assert_eq!(
+            generate_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.test" "archive" '<dict><key>$top</key><dict><key>CF$UID</key><integer>2</integer></dict></dict>'"#
+        );
+    }
+
+    // --- global domain tests ---
+
+    #[test]
+    fn test_global_domain_uses_g_flag() {
+        for domain in ["NSGlobalDomain", "Apple Global Domain"] {
+            let change = Change::Added {
+                domain: domain.to_string(),
+                key: "AppleShowAllExtensions".to_string(),
+                value: Value::Boolean(true),
+            };
+            assert_eq!(
+                generate_command(&change, &CommandOptions::default()),
+                r#"defaults write -g "AppleShowAllExtensions" -bool true"#
+            );
+        }
+    }
+
+    #[test]
+    fn test_global_domain_delete_uses_g_flag() {
+        let change = Change::Removed {
+            domain: "Apple Global Domain".to_string(),
+            key: "AppleInterfaceStyle".to_string(),
+            old_value: Value::String("Dark".to_string()),
+        };
+        assert_eq!(
+            generate_command(&change, &CommandOptions::default()),
+            r#"defaults delete -g "AppleInterfaceStyle""#
+        );
+    }
+
+    // --- restart_command_for_domain tests ---
+
+    #[test]
+    fn test_restart_command_for_known_domains() {
+        assert_eq!(
+            restart_command_for_domain("com.apple.dock").as_deref(),
+            Some("killall Dock")
+        );
+        assert_eq!(
+            restart_command_for_domain("com.apple.finder").as_deref(),
+            Some("killall Finder")
+        );
+        assert_eq!(
+            restart_command_for_domain("com.apple.menuextra.clock").as_deref(),
+            Some("killall SystemUIServer")
+        );
+    }
+
+    #[test]
+    fn test_restart_command_for_unknown_domain() {
+        assert_eq!(restart_command_for_domain("com.example.app"), None);
+        assert_eq!(restart_command_for_domain("NSGlobalDomain"), None);
+    }
+
+    #[test]
+    fn test_write_restriction() {
+        assert_eq!(
+            write_restriction("/Library/Preferences/com.apple.loginwindow"),
+            Some(WriteRestriction::NeedsSudo)
+        );
+        assert_eq!(
+            write_restriction("/Library/Managed Preferences/com.apple.dock"),
+            Some(WriteRestriction::Managed)
+        );
+        assert_eq!(
+            write_restriction("/System/Library/Preferences/com.apple.x"),
+            Some(WriteRestriction::SipProtected)
+        );
+        assert_eq!(write_restriction("/Library/PreferencesBackup/x"), None);
+        assert_eq!(write_restriction("com.apple.dock"), None);
+    }
+
+    #[test]
+    fn test_scoped_command_for_root_owned_domain() {
+        let change = Change::Added {
+            domain: "/Library/Preferences/com.apple.loginwindow".to_string(),
+            key: "GuestEnabled".to_string(),
+            value: Value::Boolean(false),
+        };
+        let options = CommandOptions::default();
+        let user = generate_scoped_command(&change, Scope::User, &options);
+        assert!(user.starts_with("# Warning: this domain is owned by root"));
+        assert!(user.ends_with(
+            "\nsudo defaults write \"/Library/Preferences/com.apple.loginwindow\" \"GuestEnabled\" -bool false"
+        ));
+        // A system capture already has sudo and needs no warning
+        let system = generate_scoped_command(&change, Scope::System, &options);
+        assert!(system.starts_with("sudo defaults write"));
+    }
+
+    #[test]
+    fn test_with_restart_one_liner() {
+        assert_eq!(
+            with_restart(
+                "defaults write com.apple.dock autohide -bool true",
+                "com.apple.dock"
+            )
+            .as_deref(),
+            Some("defaults write com.apple.dock autohide -bool true; killall Dock")
+        );
+        assert_eq!(
+            with_restart("defaults write com.example a -int 1", "com.example"),
+            None
+        );
+        assert_eq!(
+            with_restart(
+                "# Warning: owned by root\nsudo defaults write com.apple.dock a -int 1",
+                "com.apple.dock"
+            )
+            .as_deref(),
+            Some("sudo defaults write com.apple.dock a -int 1; killall Dock")
+        );
+        assert_eq!(
+            with_restart("# com.apple.dock a: nothing to write", "com.apple.dock"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_nested_change_rewrites_root_value() {
+        let mut old_root = plist::Dictionary::new();
+        old_root.insert("width".to_string(), Value::Integer(800.into()));
+        let mut new_root = plist::Dictionary::new();
+        new_root.insert("width".to_string(), Value::Integer(1024.into()));
+        let change = Change::Nested {
+            domain: "com.test".to_string(),
+            key: "window.width".to_string(),
+            root_key: "window".to_string(),
+            old_value: Some(Value::Integer(800.into())),
+            new_value: Some(Value::Integer(1024.into())),
+            old_root: Value::Dictionary(old_root).into(),
+            new_root: Value::Dictionary(new_root).into(),
+        };
+        assert_eq!(
+            generate_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.test" "window" -dict "width" -int 1024"#
+        );
+        assert_eq!(
+            generate_undo_command(&change, &CommandOptions::default()),
+            r#"defaults write "com.test" "window" -dict "width" -int 800"#
+        );
+    }
+
+    #[test]
+    fn test_nested_change_with_plutil() {
+        let mut old_frame = plist::Dictionary::new();
+        old_frame.insert("width".to_string(), Value::Integer(800.into()));
+        let mut new_frame = plist::Dictionary::new();
+        new_frame.insert("width".to_string(), Value::Integer(1024.into()));
+        let wrap = |frame: plist::Dictionary| {
+            let mut root = plist::Dictionary::new();
+            root.insert("frame".to_string(), Value::Dictionary(frame));
+            Value::Dictionary(root)
+        };
+        let change = Change::Nested {
+            domain: "com.test".to_string(),
+            key: "window.frame.width".to_string(),
+            root_key: "window".to_string(),
+            old_value: Some(Value::Integer(800.into())),
+            new_value: Some(Value::Integer(1024.into())),
+            old_root: wrap(old_frame).into(),
+            new_root: wrap(new_frame).into(),
+        };
+        let options = CommandOptions {
+            plutil: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_command(&change, &options),
+            r#"plutil -replace "window.frame.width" -integer 1024 ~/Library/Preferences/com.test.plist"#
+        );
+        assert_eq!(
+            generate_undo_command(&change, &options),
+            r#"plutil -replace "window.frame.width" -integer 800 ~/Library/Preferences/com.test.plist"#
+        );
+
+        // Per-host files have a hardware UUID in their name, so fall back
+        let current_host = CommandOptions {
+            host: CommandScope::CurrentHost,
+            ..options.clone()
+        };
+        assert!(
+            generate_command(&change, &current_host).starts_with("defaults -currentHost write")
+        );
+    }
+
+    #[test]
+    fn test_plutil_removal_and_array_index() {
+        let strings = |items: &[&str]| {
+            Value::Array(items.iter().map(|s| Value::String(s.to_string())).collect())
+        };
+        let options = CommandOptions {
+            plutil: true,
+            ..Default::default()
+        };
+
+        // Removing index 1 first would shift index 2, so the array is rewritten once
+        let shrunk = diff_of("items", strings(&["a", "b", "c"]), strings(&["a"]));
+        assert_eq!(shrunk.changes.len(), 2);
+        assert_eq!(
+            commands_of(&shrunk, &options),
+            vec![r#"defaults write "com.test" "items" -array -string "a""#]
+        );
+        // Nor can plutil replace past the end to undo a removal or do an append
+        assert_eq!(
+            generate_undo_command(&shrunk.changes[0], &options),
+            r#"defaults write "com.test" "items" -array -string "a" -string "b" -string "c""#
+        );
+        let grown = diff_of("items", strings(&["a"]), strings(&["a", "b"]));
+        assert_eq!(
+            commands_of(&grown, &options),
+            vec![r#"defaults write "com.test" "items" -array-add -string "b""#]
+        );
+
+        // Elements that stay in place are written by index
+        let edited = diff_of("items", strings(&["a", "b"]), strings(&["a", "x"]));
+        assert_eq!(
+            commands_of(&edited, &options),
+            vec![r#"plutil -replace "items.1" -string "x" ~/Library/Preferences/com.test.plist"#]
+        );
+
+        // Dictionary entries don't shift, so a removed one is removed by name
+        let mut old = plist::Dictionary::new();
+        old.insert("x".to_string(), Value::Boolean(true));
+        old.insert("y".to_string(), Value::Boolean(false));
+        let mut new = old.clone();
+        new.remove("y");
+        let removed = diff_of("prefs", Value::Dictionary(old), Value::Dictionary(new));
+        assert_eq!(
+            commands_of(&removed, &options),
+            vec![r#"plutil -remove "prefs.y" ~/Library/Preferences/com.test.plist"#]
+        );
+        assert_eq!(
+            generate_undo_command(&removed.changes[0], &options),
+            r#"plutil -replace "prefs.y" -bool NO ~/Library/Preferences/com.test.plist"#
+        );
+    }
+
+    #[test]
+    fn test_plutil_falls_back_for_dotted_keys() {
+        // `a.b` is one key, which a plutil keypath cannot address
+        let mut old_root = plist::Dictionary::new();
+        old_root.insert("a.b".to_string(), Value::Boolean(false));
+        let mut new_root = plist::Dictionary::new();
+        new_root.insert("a.b".to_string(), Value::Boolean(true));
+        let change = Change::Nested {
+            domain: "com.test".to_string(),
+            key: "root.a.b".to_string(),
+            root_key: "root".to_string(),
+            old_value: Some(Value::Boolean(false)),
+            new_value: Some(Value::Boolean(true)),
+            old_root: Value::Dictionary(old_root).into(),
+            new_root: Value::Dictionary(new_root).into(),
+        };
+        let options = CommandOptions {
+            plutil: true,
+            ..Default::default()
+        };
+        assert!(generate_command(&change, &options).starts_with("defaults write"));
+        assert_eq!(
+            generate_plutil_command(
+                "/Users/me/Library/Containers/x/prefs",
+                "root",
+                Some(&Value::Boolean(true)),
+                QuoteStyle::Single
+            )
+            .as_deref(),
+            Some("plutil -replace 'root' -bool YES '/Users/me/Library/Containers/x/prefs.plist'")
+        );
+    }
+
+    #[test]
+    fn test_array_rooted_domain_is_not_written() {
+        let change = Change::Modified {
+            domain: "com.test".to_string(),
+            key: ROOT_KEY.to_string(),
+            old_value: Value::Array(vec![]),
+            new_value: Value::Array(vec![Value::String("a".to_string())]),
+        };
+        let options = CommandOptions::default();
+        assert_eq!(
+            generate_command(&change, &options),
+            "# com.test: the domain is not a dictionary, so `defaults` cannot write it"
+        );
+        assert!(generate_undo_command(&change, &CommandOptions::default()).starts_with("# "));
+        assert_eq!(
+            generate_verify_command(&change, Scope::User, &options),
+            None
+        );
+    }
+
+    /// The one domain's diff when `com.test`'s `key` goes from `old` to `new`
+    fn diff_of(key: &str, old: Value, new: Value) -> DomainDiff {
+        let snapshot = |value| {
+            let mut values = plist::Dictionary::new();
+            values.insert(key.to_string(), value);
+            let mut snapshot = Snapshot::new();
+            snapshot
+                .domains
+                .insert("com.test".to_string(), DomainSettings { values });
+            snapshot
+        };
+        let mut diff = detect_diff(&snapshot(old), &snapshot(new), &DiffOptions::default());
+        diff.domain_diffs.remove(0)
+    }
+
+    fn commands_of(domain_diff: &DomainDiff, options: &CommandOptions) -> Vec<String> {
+        domain_commands(domain_diff, options)
+            .into_iter()
+            .map(|(_, command)| command)
+            .collect()
+    }
+
+    #[test]
+    fn test_appended_array_elements_use_array_add() {
+        let strings = |items: &[&str]| {
+            Value::Array(items.iter().map(|s| Value::String(s.to_string())).collect())
+        };
+        let options = CommandOptions::default();
+
+        // One command for both appended elements, not one per element
+        let domain_diff = diff_of("list", strings(&["a"]), strings(&["a", "b", "c"]));
+        assert_eq!(domain_diff.changes.len(), 2);
+        assert_eq!(
+            commands_of(&domain_diff, &options),
+            vec![r#"defaults write "com.test" "list" -array-add -string "b" -string "c""#]
+        );
+        // Undo still restores the whole old array
+        assert_eq!(
+            generate_undo_command(&domain_diff.changes[0], &options),
+            r#"defaults write "com.test" "list" -array -string "a""#
+        );
+
+        // Reordered, shrunk or edited arrays are rewritten
+        for new in [strings(&["b", "a"]), strings(&[]), strings(&["x", "b"])] {
+            let commands = commands_of(&diff_of("list", strings(&["a"]), new), &options);
+            assert_eq!(commands.len(), 1);
+            assert!(commands[0].contains(" -array"));
+            assert!(!commands[0].contains("-array-add"));
+        }
+    }
+
+    #[test]
+    fn test_added_dict_entries_use_dict_add() {
+        let mut old = plist::Dictionary::new();
+        old.insert(
+            "nested".to_string(),
+            Value::Array(vec![Value::Boolean(true)]),
+        );
+        old.insert("size".to_string(), Value::Integer(1.into()));
+        let mut new = old.clone();
+        new.insert("name".to_string(), Value::String("dock".to_string()));
+        new.insert("on".to_string(), Value::Boolean(false));
+        let options = CommandOptions::default();
+
+        // One command for both added entries, not one per entry
+        let domain_diff = diff_of(
+            "prefs",
+            Value::Dictionary(old.clone()),
+            Value::Dictionary(new),
+        );
+        assert_eq!(domain_diff.changes.len(), 2);
+        assert_eq!(
+            commands_of(&domain_diff, &options),
+            vec![
+                r#"defaults write "com.test" "prefs" -dict-add "name" -string "dock" "on" -bool false"#
+            ]
+        );
+
+        // A changed existing entry or a nested new entry needs a full rewrite
+        let mut edited = old.clone();
+        edited.insert("size".to_string(), Value::Integer(2.into()));
+        edited.insert("extra".to_string(), Value::Boolean(true));
+        let mut nested = old.clone();
+        nested.insert(
+            "more".to_string(),
+            Value::Dictionary(plist::Dictionary::new()),
+        );
+        for new in [edited, nested] {
+            let domain_diff = diff_of(
+                "prefs",
+                Value::Dictionary(old.clone()),
+                Value::Dictionary(new),
+            );
+            let commands = commands_of(&domain_diff, &options);
+            assert_eq!(commands.len(), 1);
+            assert!(!commands[0].contains("-dict-add"));
+        }
+    }
+
+    #[test]
+    fn test_plist_path_overrides_domain() {
+        let path = "/Applications/My App/Prefs $HOME.plist";
+        let options = CommandOptions {
+            plist_paths: BTreeMap::from([("com.my.app".to_string(), path.to_string())]),
+            ..Default::default()
+        };
+        let change = Change::Added {
+            domain: "com.my.app".to_string(),
+            key: "on".to_string(),
+            value: Value::Boolean(true),
+        };
+        let command = generate_command(&change, &options);
+        assert_eq!(
+            command,
+            r#"defaults write "/Applications/My App/Prefs \$HOME" "on" -bool true"#
+        );
+        // The quoted path reaches `defaults` unchanged
+        let quoted = command
+            .strip_prefix("defaults write ")
+            .and_then(|rest| rest.strip_suffix(r#" "on" -bool true"#))
+            .unwrap();
+        assert_eq!(shell_word(quoted), "/Applications/My App/Prefs $HOME");
+
+        assert_eq!(
+            generate_undo_command(&change, &options),
+            r#"defaults delete "/Applications/My App/Prefs \$HOME" "on""#
+        );
+        let other = Change::Added {
+            domain: "com.other".to_string(),
+            key: "on".to_string(),
+            value: Value::Boolean(true),
+        };
+        assert!(generate_command(&other, &options).starts_with(r#"defaults write "com.other""#));
+    }
+
+    #[test]
+    fn test_generate_structured() {
+        let change = Change::Modified {
+            domain: "com.apple.dock".to_string(),
+            key: "autohide".to_string(),
+            old_value: Value::Boolean(false),
+            new_value: Value::Boolean(true),
+        };
+        assert_eq!(
+            generate_structured(&change),
+            r#"{"domain": "com.apple.dock", "key": "autohide", "type": "bool", "value": true}"#
+        );
+
+        let change = Change::Added {
+            domain: "com.test".to_string(),
+            key: "names".to_string(),
+            value: Value::Array(vec![
+                Value::String("say \"hi\"\n".to_string()),
+                Value::Integer(3.into()),
+            ]),
+        };
+        assert_eq!(
+            generate_structured(&change),
+            r#"{"domain": "com.test", "key": "names", "type": "array", "value": ["say \"hi\"\n", 3]}"#
+        );
+
+        let change = Change::Removed {
+            domain: "com.test".to_string(),
+            key: "gone".to_string(),
+            old_value: Value::Integer(1.into()),
+        };
+        assert_eq!(
+            generate_structured(&change),
+            r#"{"domain": "com.test", "key": "gone", "type": null, "value": null}"#
+        );
+    }
 }
@@ -0,0 +1,207 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::diff::DiffResult;
+
+use super::generator::{CommandOptions, domain_commands, restart_command_for_domain};
+
+/// Generate commands for every change in the diff, one per line.
+///
+/// Domains that need an app restart get a `killall` line after their commands,
+/// and with `options.verify` each write is followed by a `defaults read`.
+/// With `group_by_domain`, each domain's commands are preceded by a
+/// `# === <domain> ===` header comment, and a command that changes a value's
+/// type is preceded by a warning comment. Domains are emitted in sorted order.
+pub fn collect_commands(
+    diff: &DiffResult,
+    options: &CommandOptions,
+    group_by_domain: bool,
+) -> String {
+    let mut domain_diffs: Vec<_> = diff.domain_diffs.iter().collect();
+    domain_diffs.sort_by(|a, b| a.domain.cmp(&b.domain));
+
+    let mut out = String::new();
+    for domain_diff in domain_diffs {
+        if group_by_domain {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("# === {} ===\n", domain_diff.domain));
+        }
+        for (change, command) in domain_commands(domain_diff, options) {
+            if group_by_domain && let Some((old, new)) = change.type_change() {
+                out.push_str(&format!(
+                    "# {}: type changes from {} to {}\n",
+                    change.key(),
+                    old,
+                    new
+                ));
+            }
+            out.push_str(&command);
+            out.push('\n');
+        }
+        if let Some(restart) = restart_command_for_domain(&domain_diff.domain) {
+            out.push_str(&restart);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// Render the whole diff as a runnable bash script with a comment per domain
+pub fn render_script(diff: &DiffResult, options: &CommandOptions) -> String {
+    format!("#!/bin/bash\n\n{}", collect_commands(diff, options, true))
+}
+
+/// Write the diff as an executable shell script at `path`
+pub fn write_script(diff: &DiffResult, path: &Path, options: &CommandOptions) -> Result<()> {
+    fs::write(path, render_script(diff, options))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defaults::Snapshot;
+    use crate::defaults::types::{DomainSettings, Scope};
+    use crate::diff::types::DomainDiff;
+    use crate::diff::{Change, DiffOptions, detect_diff};
+    use plist::Value;
+
+    fn domain_diff(domain: &str, keys: &[&str]) -> DomainDiff {
+        DomainDiff {
+            domain: domain.to_string(),
+            changes: keys
+                .iter()
+                .map(|key| Change::Added {
+                    domain: domain.to_string(),
+                    key: key.to_string(),
+                    value: Value::Boolean(true),
+                })
+                .collect(),
+            scope: Scope::User,
+        }
+    }
+
+    fn sample_diff() -> DiffResult {
+        DiffResult {
+            domain_diffs: vec![
+                domain_diff("com.b", &["x"]),
+                domain_diff("com.a", &["k1", "k2"]),
+            ],
+            total_changes: 3,
+        }
+    }
+
+    #[test]
+    fn test_collect_commands_grouped() {
+        let script = collect_commands(&sample_diff(), &CommandOptions::default(), true);
+        let lines: Vec<&str> = script.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "# === com.a ===",
+                r#"defaults write "com.a" "k1" -bool true"#,
+                r#"defaults write "com.a" "k2" -bool true"#,
+                "",
+                "# === com.b ===",
+                r#"defaults write "com.b" "x" -bool true"#,
+            ]
+        );
+        assert_eq!(script.matches("# === com.a ===").count(), 1);
+    }
+
+    #[test]
+    fn test_collect_commands_warns_on_type_change() {
+        let diff = DiffResult {
+            domain_diffs: vec![DomainDiff {
+                domain: "com.a".to_string(),
+                changes: vec![Change::Modified {
+                    domain: "com.a".to_string(),
+                    key: "k".to_string(),
+                    old_value: Value::Integer(1.into()),
+                    new_value: Value::Boolean(true),
+                }],
+                scope: Scope::User,
+            }],
+            total_changes: 1,
+        };
+        let script = collect_commands(&diff, &CommandOptions::default(), true);
+        assert!(script.contains("# k: type changes from int to bool\ndefaults write"));
+        let flat = collect_commands(&diff, &CommandOptions::default(), false);
+        assert!(!flat.contains('#'));
+    }
+
+    #[test]
+    fn test_collect_commands_flat() {
+        let script = collect_commands(&sample_diff(), &CommandOptions::default(), false);
+        assert!(!script.contains('#'));
+        assert_eq!(script.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_render_script() {
+        let script = render_script(&sample_diff(), &CommandOptions::default());
+        assert!(script.starts_with("#!/bin/bash\n\n# === com.a ===\n"));
+        assert!(script.ends_with("defaults write \"com.b\" \"x\" -bool true\n"));
+    }
+
+    #[test]
+    fn test_write_script() {
+        let path =
+            std::env::temp_dir().join(format!("defaults-util-script-{}.sh", std::process::id()));
+        write_script(&sample_diff(), &path, &CommandOptions::default()).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            written,
+            render_script(&sample_diff(), &CommandOptions::default())
+        );
+    }
+
+    #[test]
+    fn test_collect_commands_appends_restart_once_per_domain() {
+        let diff = DiffResult {
+            domain_diffs: vec![domain_diff("com.apple.dock", &["autohide", "tilesize"])],
+            total_changes: 2,
+        };
+        let script = collect_commands(&diff, &CommandOptions::default(), false);
+        assert_eq!(script.lines().last(), Some("killall Dock"));
+        assert_eq!(script.matches("killall").count(), 1);
+    }
+
+    #[test]
+    fn test_collect_commands_writes_nested_root_once() {
+        let snapshot = |items: &[i64]| {
+            let mut values = plist::Dictionary::new();
+            values.insert(
+                "list".to_string(),
+                Value::Array(items.iter().map(|&i| Value::Integer(i.into())).collect()),
+            );
+            let mut snapshot = Snapshot::new();
+            snapshot
+                .domains
+                .insert("com.a".to_string(), DomainSettings { values });
+            snapshot
+        };
+        let diff = detect_diff(
+            &snapshot(&[1]),
+            &snapshot(&[1, 2, 3]),
+            &DiffOptions::default(),
+        );
+        assert_eq!(diff.total_changes, 2);
+        let script = collect_commands(&diff, &CommandOptions::default(), false);
+        assert_eq!(
+            script,
+            "defaults write \"com.a\" \"list\" -array-add -int 2 -int 3\n"
+        );
+    }
+}
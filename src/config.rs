@@ -0,0 +1,197 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::diff::ignore::DEFAULT_IGNORE;
+use crate::diff::{DiffOptions, IgnoreList};
+use crate::export::home_dir;
+use crate::ui::theme::ThemePreset;
+
+/// Where status messages are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StatusPosition {
+    /// Each screen's usual place (header in diff view, status bar elsewhere)
+    #[default]
+    Auto,
+    Header,
+    Footer,
+    Hidden,
+}
+
+/// Which status messages are shown
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StatusVerbosity {
+    #[default]
+    Normal,
+    /// Suppress info-level messages
+    Quiet,
+}
+
+/// User configuration loaded from `~/.config/defaults-util/config`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub status_position: StatusPosition,
+    pub status_verbosity: StatusVerbosity,
+    /// Seconds info and success messages stay up (warnings stay twice as long)
+    pub status_timeout: Option<Duration>,
+    /// Normalization options applied when diffing
+    pub diff_options: DiffOptions,
+    pub theme: ThemePreset,
+}
+
+impl Config {
+    /// Load the config and ignore files, falling back to defaults if they are missing
+    pub fn load() -> Self {
+        let mut config: Self = std::fs::read_to_string(config_dir().join("config"))
+            .map(|content| Self::parse(&content))
+            .unwrap_or_default();
+        let ignore = std::fs::read_to_string(config_dir().join("ignore"))
+            .unwrap_or_else(|_| DEFAULT_IGNORE.to_string());
+        config.diff_options.ignore = IgnoreList::parse(&ignore);
+        config
+    }
+
+    /// Parse `key = value` lines; unknown keys and values are ignored
+    pub fn parse(content: &str) -> Self {
+        let mut config = Self::default();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            match (key.trim(), value.trim()) {
+                ("status_position", "auto") => config.status_position = StatusPosition::Auto,
+                ("status_position", "header") => config.status_position = StatusPosition::Header,
+                ("status_position", "footer") => config.status_position = StatusPosition::Footer,
+                ("status_position", "hidden") => config.status_position = StatusPosition::Hidden,
+                ("status_verbosity", "normal") => config.status_verbosity = StatusVerbosity::Normal,
+                ("status_verbosity", "quiet") => config.status_verbosity = StatusVerbosity::Quiet,
+                ("theme", name) => {
+                    if let Some(theme) = ThemePreset::parse(name) {
+                        config.theme = theme;
+                    }
+                }
+                ("ignore_whitespace", value) => {
+                    config.diff_options.ignore_whitespace = parse_bool(value)
+                }
+                ("numeric_equivalence", value) => {
+                    config.diff_options.numeric_equivalence = parse_bool(value)
+                }
+                ("bool_int_equivalence", value) => {
+                    config.diff_options.bool_int_equivalence = parse_bool(value)
+                }
+                ("status_timeout", value) => {
+                    // Values too large for a Duration are ignored like invalid ones
+                    if let Ok(seconds) = value.parse::<f64>()
+                        && seconds > 0.0
+                        && let Ok(timeout) = Duration::try_from_secs_f64(seconds)
+                    {
+                        config.status_timeout = Some(timeout);
+                    }
+                }
+                ("float_epsilon", value) => {
+                    if let Ok(epsilon) = value.parse::<f64>()
+                        && epsilon >= 0.0
+                    {
+                        config.diff_options.float_epsilon = epsilon;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        config
+    }
+}
+
+fn parse_bool(value: &str) -> bool {
+    matches!(value, "true" | "yes" | "on" | "1")
+}
+
+/// Directory holding user configuration (`~/.config/defaults-util`)
+pub fn config_dir() -> PathBuf {
+    home_dir().join(".config").join("defaults-util")
+}
+
+/// Path of the optional factory-default baseline snapshot
+pub fn factory_baseline_path() -> PathBuf {
+    config_dir().join("factory.plist")
+}
+
+/// Path where the TUI saves and reloads the "before" snapshot
+pub fn saved_snapshot_path() -> PathBuf {
+    config_dir().join("before.plist")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status_options() {
+        let config = Config::parse(
+            "# comment\nstatus_position = footer\nstatus_verbosity=quiet\nunknown = 1\n",
+        );
+        assert_eq!(config.status_position, StatusPosition::Footer);
+        assert_eq!(config.status_verbosity, StatusVerbosity::Quiet);
+    }
+
+    #[test]
+    fn test_parse_normalization_options() {
+        let config = Config::parse("ignore_whitespace = true\nnumeric_equivalence = yes\n");
+        assert!(config.diff_options.ignore_whitespace);
+        assert!(config.diff_options.numeric_equivalence);
+        assert!(!config.diff_options.bool_int_equivalence);
+    }
+
+    #[test]
+    fn test_parse_theme() {
+        assert_eq!(
+            Config::parse("theme = monochrome").theme,
+            ThemePreset::Monochrome
+        );
+        assert_eq!(Config::parse("theme = neon").theme, ThemePreset::Default);
+    }
+
+    #[test]
+    fn test_parse_empty_uses_defaults() {
+        assert_eq!(Config::parse(""), Config::default());
+    }
+
+    #[test]
+    fn test_parse_status_timeout() {
+        assert_eq!(
+            Config::parse("status_timeout = 1.5").status_timeout,
+            Some(Duration::from_millis(1500))
+        );
+        for content in [
+            "status_timeout = soon",
+            "status_timeout = 0",
+            "status_timeout = 1e20",
+            "status_timeout = inf",
+        ] {
+            assert_eq!(Config::parse(content).status_timeout, None);
+        }
+    }
+
+    #[test]
+    fn test_parse_float_epsilon() {
+        assert_eq!(
+            Config::parse("float_epsilon = 0.01")
+                .diff_options
+                .float_epsilon,
+            0.01
+        );
+        // Invalid or negative values keep the default
+        for content in ["float_epsilon = tiny", "float_epsilon = -1"] {
+            assert_eq!(
+                Config::parse(content).diff_options.float_epsilon,
+                crate::diff::detector::DEFAULT_FLOAT_EPSILON
+            );
+        }
+    }
+}
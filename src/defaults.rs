@@ -2,5 +2,5 @@ pub mod parser;
 pub mod reader;
 pub mod types;
 
-pub use reader::capture_snapshot;
-pub use types::Snapshot;
+pub use reader::{CaptureOptions, capture_snapshot};
+pub use types::{MergeStrategy, Snapshot};
@@ -1,23 +1,145 @@
-use plist::Value;
-use std::collections::HashMap;
+use plist::{Dictionary, Value};
+use std::io::Cursor;
 
 use super::types::DomainSettings;
+use crate::error::AppError;
 use anyhow::Result;
 
+/// Key holding the whole value of a domain whose export is not a dictionary
+/// (some domains export a top-level array)
+pub const ROOT_KEY: &str = "(root)";
+
+/// Serialization format of plist data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlistFormat {
+    Xml,
+    Binary,
+}
+
+/// Detect the plist format from its leading bytes
+pub fn sniff_format(data: &[u8]) -> Option<PlistFormat> {
+    if data.starts_with(b"bplist00") {
+        return Some(PlistFormat::Binary);
+    }
+
+    let text = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let start = text.iter().position(|b| !b.is_ascii_whitespace())?;
+    let text = &text[start..];
+    if text.starts_with(b"<?xml")
+        || text.starts_with(b"<!DOCTYPE plist")
+        || text.starts_with(b"<plist")
+    {
+        Some(PlistFormat::Xml)
+    } else {
+        None
+    }
+}
+
 /// Parse plist data into DomainSettings
-pub fn parse_domain_plist(_domain: &str, data: &[u8]) -> Result<DomainSettings> {
-    let value: Value = plist::from_bytes(data)?;
+pub fn parse_domain_plist(domain: &str, data: &[u8]) -> Result<DomainSettings> {
+    let parse_error = |message: String| AppError::Parse {
+        domain: domain.to_string(),
+        message,
+    };
+    let value = match sniff_format(data) {
+        Some(PlistFormat::Xml) => {
+            Value::from_reader_xml(Cursor::new(data)).map_err(|e| parse_error(e.to_string()))?
+        }
+        Some(PlistFormat::Binary) => {
+            Value::from_reader(Cursor::new(data)).map_err(|e| parse_error(e.to_string()))?
+        }
+        None => {
+            return Err(parse_error(
+                "Unrecognized plist format (expected XML or bplist00)".to_string(),
+            )
+            .into());
+        }
+    };
 
+    // Keep the dictionary as-is so keys stay in export order; any other root
+    // is kept under a sentinel key so its contents still show up in the diff
     let values = match value {
-        Value::Dictionary(dict) => {
-            let mut map = HashMap::new();
-            for (key, val) in dict {
-                map.insert(key, val);
-            }
-            map
+        Value::Dictionary(dict) => dict,
+        other => {
+            let mut dict = Dictionary::new();
+            dict.insert(ROOT_KEY.to_string(), other);
+            dict
         }
-        _ => HashMap::new(),
     };
 
     Ok(DomainSettings { values })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_dict() -> Value {
+        let mut dict = plist::Dictionary::new();
+        dict.insert("flag".to_string(), Value::Boolean(true));
+        dict.insert("count".to_string(), Value::Integer(3.into()));
+        Value::Dictionary(dict)
+    }
+
+    #[test]
+    fn test_parse_xml_plist() {
+        let data = br#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0"><dict><key>flag</key><true/><key>count</key><integer>3</integer></dict></plist>"#;
+        assert_eq!(sniff_format(data), Some(PlistFormat::Xml));
+
+        let settings = parse_domain_plist("com.test", data).unwrap();
+        assert!(matches!(
+            settings.values.get("flag"),
+            Some(Value::Boolean(true))
+        ));
+        assert_eq!(settings.values.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_binary_plist() {
+        let mut data = Vec::new();
+        sample_dict().to_writer_binary(&mut data).unwrap();
+        assert!(data.starts_with(b"bplist00"));
+        assert_eq!(sniff_format(&data), Some(PlistFormat::Binary));
+
+        let settings = parse_domain_plist("com.test", &data).unwrap();
+        assert!(
+            matches!(settings.values.get("count"), Some(Value::Integer(i)) if i.as_signed() == Some(3))
+        );
+    }
+
+    #[test]
+    fn test_parse_unrecognized_format() {
+        let err = parse_domain_plist("com.test", br#"{"flag": true}"#).unwrap_err();
+        assert!(err.to_string().contains("Unrecognized plist format"));
+        assert!(err.to_string().contains("com.test"));
+        assert!(matches!(
+            err.downcast_ref::<AppError>(),
+            Some(AppError::Parse { domain, .. }) if domain == "com.test"
+        ));
+    }
+
+    #[test]
+    fn test_parse_array_root_kept_under_sentinel() {
+        let data = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><array><string>a</string><string>b</string></array></plist>"#;
+        let settings = parse_domain_plist("com.test", data).unwrap();
+        assert_eq!(settings.values.len(), 1);
+        assert!(matches!(
+            settings.values.get(ROOT_KEY),
+            Some(Value::Array(items)) if items.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_parse_preserves_key_order() {
+        let data = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>
+<key>zeta</key><true/><key>alpha</key><true/><key>mid</key><true/>
+</dict></plist>"#;
+        let settings = parse_domain_plist("com.test", data).unwrap();
+        let keys: Vec<&str> = settings.values.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["zeta", "alpha", "mid"]);
+    }
+}
@@ -1,86 +1,1275 @@
-use std::process::Command;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
 use std::time::{Duration, Instant};
 
 use super::parser::parse_domain_plist;
-use super::types::Snapshot;
+use super::types::{DomainSettings, MergeStrategy, Snapshot};
+use crate::diff::detector::values_equal;
+use crate::error::AppError;
 use anyhow::{Result, bail};
 
+/// Command line as the user would type it, for error messages
+fn describe_command(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Read a child's pipe to the end on its own thread, so a child writing more
+/// than the pipe buffer holds doesn't block before it can exit
+fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+        Ok(buf)
+    })
+}
+
 /// Run a command with a timeout, killing the child process if it exceeds the limit.
-fn run_with_timeout(cmd: &mut Command, timeout: Duration) -> Result<std::process::Output> {
-    let mut child = cmd.spawn()?;
+fn run_with_timeout(
+    cmd: &mut Command,
+    timeout: Duration,
+) -> Result<std::process::Output, AppError> {
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
     let start = Instant::now();
     loop {
         match child.try_wait()? {
-            Some(_) => return Ok(child.wait_with_output()?),
+            Some(status) => {
+                let join = |reader: thread::JoinHandle<io::Result<Vec<u8>>>| {
+                    reader
+                        .join()
+                        .unwrap_or_else(|_| Err(io::Error::other("pipe reader panicked")))
+                };
+                return Ok(std::process::Output {
+                    status,
+                    stdout: join(stdout)?,
+                    stderr: join(stderr)?,
+                });
+            }
             None if start.elapsed() > timeout => {
                 let _ = child.kill();
-                bail!("Command timed out after {:?}", timeout);
+                let _ = child.wait();
+                return Err(AppError::Timeout {
+                    command: describe_command(cmd),
+                    after: timeout,
+                });
             }
             None => std::thread::sleep(Duration::from_millis(50)),
         }
     }
 }
 
-/// Get list of all domains
-pub fn list_domains() -> Result<Vec<String>> {
-    let output = run_with_timeout(
-        Command::new("defaults").arg("domains"),
-        Duration::from_secs(10),
-    )?;
-
+/// Run a command with a timeout and return its stdout, failing on a non-zero exit
+fn run_checked(cmd: &mut Command, timeout: Duration) -> Result<Vec<u8>, AppError> {
+    let output = run_with_timeout(cmd, timeout)?;
     if !output.status.success() {
-        bail!(
-            "Failed to execute defaults command: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+        return Err(AppError::DefaultsCommand {
+            command: describe_command(cmd),
+            status: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
     }
+    Ok(output.stdout)
+}
 
-    let domains_str = String::from_utf8_lossy(&output.stdout).into_owned();
-    let domains: Vec<String> = domains_str
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect();
+/// Default limit for `defaults domains`
+pub const LIST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default limit for exporting one domain
+pub const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default number of extra attempts for an export that failed to run
+pub const EXPORT_RETRIES: u32 = 2;
+
+/// Wait before the first retry; doubled for each further retry
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// `defaults`, run as `user` through `sudo -n -u` when one is given
+fn defaults_command(user: Option<&str>) -> Command {
+    match user {
+        Some(user) => {
+            let mut cmd = Command::new("sudo");
+            cmd.args(["-n", "-u", user, "defaults"]);
+            cmd
+        }
+        None => Command::new("defaults"),
+    }
+}
+
+/// The global domain under the name snapshots store it by
+pub const GLOBAL_DOMAIN: &str = "NSGlobalDomain";
+
+/// Whether a domain names the global domain, which `defaults` spells `-g`
+pub fn is_global_domain(domain: &str) -> bool {
+    matches!(domain, "NSGlobalDomain" | "Apple Global Domain")
+}
+
+/// A domain under the name snapshots use, so the global domain is one entry
+/// whichever alias it was given as
+pub fn canonical_domain(domain: &str) -> &str {
+    if is_global_domain(domain) {
+        GLOBAL_DOMAIN
+    } else {
+        domain
+    }
+}
+
+/// Get list of all domains of `user` (the current user when `None`).
+///
+/// An empty list fails with `AppError::NoDomains`: every account has some
+/// domains, so it means `defaults` could not see them.
+pub fn list_domains(timeout: Duration, user: Option<&str>) -> Result<Vec<String>> {
+    let stdout = run_checked(defaults_command(user).arg("domains"), timeout)?;
+    let domains = parse_domain_list(&String::from_utf8_lossy(&stdout));
+    if domains.is_empty() {
+        return Err(AppError::NoDomains { failed: 0 }.into());
+    }
+    Ok(domains)
+}
 
+/// Directory holding system-wide preferences, writable only by root
+pub const SYSTEM_PREFERENCES_DIR: &str = "/Library/Preferences";
+
+/// Directory where MDM profiles install managed preferences
+pub const MANAGED_PREFERENCES_DIR: &str = "/Library/Managed Preferences";
+
+/// List managed preference domains under `dir` as path-style domains.
+///
+/// Computer-level plists live directly in `dir`; per-user ones in `dir/<user>`.
+/// Domains are returned as paths without `.plist`, so the path itself tags them
+/// as managed and `defaults export` can read them.
+pub fn list_managed_domains(dir: &Path, user: Option<&str>) -> Result<Vec<String>> {
+    let mut domains = plist_paths_in(dir)?;
+    if let Some(user) = user {
+        let user_dir = dir.join(user);
+        if user_dir.is_dir() {
+            domains.extend(plist_paths_in(&user_dir)?);
+        }
+    }
+    domains.sort();
     Ok(domains)
 }
 
+/// Paths (without extension) of `.plist` files directly inside `dir`
+fn plist_paths_in(dir: &Path) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "plist") {
+            paths.push(path.with_extension("").to_string_lossy().into_owned());
+        }
+    }
+    Ok(paths)
+}
+
+/// Domains of Apple background services that nobody edits by hand; a name
+/// matches when it equals a prefix or continues it with `.`
+const APPLE_SERVICE_PREFIXES: &[&str] = &[
+    "com.apple.AddressBook.abd",
+    "com.apple.akd",
+    "com.apple.analyticsd",
+    "com.apple.bird",
+    "com.apple.CallHistorySyncHelper",
+    "com.apple.cloudd",
+    "com.apple.CloudKit",
+    "com.apple.corespotlight",
+    "com.apple.coreservices.useractivityd",
+    "com.apple.identityservicesd",
+    "com.apple.ids",
+    "com.apple.knowledge-agent",
+    "com.apple.mediaanalysisd",
+    "com.apple.parsecd",
+    "com.apple.photoanalysisd",
+    "com.apple.proactive",
+    "com.apple.routined",
+    "com.apple.security",
+    "com.apple.suggestions",
+    "com.apple.tipsd",
+    "com.apple.xpc",
+];
+
+/// Whether a domain belongs to an Apple background service
+pub fn is_apple_service_domain(domain: &str) -> bool {
+    APPLE_SERVICE_PREFIXES.iter().any(|prefix| {
+        domain
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+/// Split `defaults domains` output, which separates names with `, ` or newlines.
+///
+/// Names may contain spaces ("Apple Global Domain") and commas followed by
+/// other text, so only a comma before whitespace, another comma or the end
+/// ends a name.
+fn parse_domain_list(output: &str) -> Vec<String> {
+    let mut domains = Vec::new();
+    let mut current = String::new();
+    let mut chars = output.chars().peekable();
+    while let Some(c) = chars.next() {
+        let ends_name = match c {
+            '\n' | '\r' => true,
+            ',' => chars
+                .peek()
+                .is_none_or(|next| next.is_whitespace() || *next == ','),
+            _ => false,
+        };
+        if ends_name {
+            domains.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    domains.push(current);
+    domains
+        .iter()
+        .map(|s| canonical_domain(s.trim()).to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// `defaults domains` leaves out the global domain, so add it to the list
+fn with_global_domain(mut domains: Vec<String>) -> Vec<String> {
+    if !domains.iter().any(|domain| domain == GLOBAL_DOMAIN) {
+        domains.insert(0, GLOBAL_DOMAIN.to_string());
+    }
+    domains
+}
+
+/// Argument to pass to `defaults` for a domain.
+///
+/// Path-style domains are given to `defaults` without the `.plist` extension.
+pub fn domain_arg(domain: &str) -> &str {
+    if domain.starts_with('/') {
+        domain.strip_suffix(".plist").unwrap_or(domain)
+    } else {
+        domain
+    }
+}
+
+/// The plist file behind a domain for the user whose home is `home`.
+///
+/// Path-style domains are their own file. Per-host preferences live in
+/// `ByHost`, named with the hardware UUID; the rest in `Library/Preferences`,
+/// or a sandboxed app's container when that is where the file is.
+pub fn domain_plist_path(domain: &str, current_host: bool, home: &Path) -> Result<PathBuf> {
+    if domain.starts_with('/') {
+        let path = PathBuf::from(format!("{}.plist", domain_arg(domain)));
+        if !path.is_file() {
+            bail!("No plist file at {}", path.display());
+        }
+        return Ok(path);
+    }
+    let name = if is_global_domain(domain) {
+        ".GlobalPreferences"
+    } else {
+        domain
+    };
+    let preferences = home.join("Library/Preferences");
+
+    if current_host {
+        let by_host = preferences.join("ByHost");
+        let entries = fs::read_dir(&by_host)
+            .map_err(|e| anyhow::anyhow!("Could not read {}: {}", by_host.display(), e))?;
+        // `<name>.<UUID>.plist`; a longer domain would leave a `.` before the UUID
+        let found = entries.flatten().map(|entry| entry.path()).find(|path| {
+            path.file_name()
+                .and_then(|file| file.to_str())
+                .and_then(|file| file.strip_prefix(name)?.strip_prefix('.'))
+                .and_then(|rest| rest.strip_suffix(".plist"))
+                .is_some_and(|uuid| !uuid.is_empty() && !uuid.contains('.'))
+        });
+        return found.ok_or_else(|| {
+            anyhow::anyhow!(
+                "No per-host preferences for {} in {}",
+                domain,
+                by_host.display()
+            )
+        });
+    }
+
+    let standard = preferences.join(format!("{}.plist", name));
+    let container = home
+        .join("Library/Containers")
+        .join(domain)
+        .join("Data/Library/Preferences")
+        .join(format!("{}.plist", name));
+    [standard.clone(), container]
+        .into_iter()
+        .find(|path| path.is_file())
+        .ok_or_else(|| anyhow::anyhow!("No plist file for {} at {}", domain, standard.display()))
+}
+
 /// Export domain settings as XML plist
-pub fn export_domain(domain: &str) -> Result<Vec<u8>> {
+pub fn export_domain(domain: &str, timeout: Duration, user: Option<&str>) -> Result<Vec<u8>> {
+    Ok(run_checked(
+        defaults_command(user).args(["export", domain_arg(domain), "-"]),
+        timeout,
+    )?)
+}
+
+/// Export a domain through `sudo -n`, failing instead of prompting for a password
+pub fn export_domain_as_root(domain: &str, timeout: Duration) -> Result<Vec<u8>> {
+    Ok(run_checked(
+        Command::new("sudo").args(["-n", "defaults", "export", domain_arg(domain), "-"]),
+        timeout,
+    )?)
+}
+
+/// Fail early with a clear message when sudo would need to prompt
+fn ensure_sudo_available() -> Result<()> {
     let output = run_with_timeout(
-        Command::new("defaults").args(["export", domain, "-"]),
+        Command::new("sudo").args(["-n", "true"]),
         Duration::from_secs(5),
     )?;
+    if !output.status.success() {
+        bail!("--include-system needs sudo without a prompt; run `sudo -v` first");
+    }
+    Ok(())
+}
 
+/// Fail early with a clear message when sudo cannot run commands as `user`
+/// without prompting (or the user does not exist)
+fn ensure_sudo_as_user(user: &str) -> Result<()> {
+    let output = run_with_timeout(
+        Command::new("sudo").args(["-n", "-u", user, "true"]),
+        Duration::from_secs(5),
+    )?;
     if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
         bail!(
-            "Failed to export domain '{}': {}",
-            domain,
-            String::from_utf8_lossy(&output.stderr)
+            "--user {} needs sudo without a prompt; run `sudo -v` first ({})",
+            user,
+            stderr.trim()
         );
     }
+    Ok(())
+}
+
+/// Whether managed (MDM/profile) preference domains are captured
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ManagedPreferences {
+    /// Only the domains reported by `defaults domains`
+    #[default]
+    Exclude,
+    /// User domains plus managed preference domains
+    Include,
+    /// Managed preference domains only
+    Only,
+}
+
+impl ManagedPreferences {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ManagedPreferences::Exclude => "excluded",
+            ManagedPreferences::Include => "included",
+            ManagedPreferences::Only => "only",
+        }
+    }
 
-    Ok(output.stdout)
+    /// Next mode when cycling through the options
+    pub fn next(&self) -> Self {
+        match self {
+            ManagedPreferences::Exclude => ManagedPreferences::Include,
+            ManagedPreferences::Include => ManagedPreferences::Only,
+            ManagedPreferences::Only => ManagedPreferences::Exclude,
+        }
+    }
 }
 
-/// Capture snapshot of all domain settings
-pub fn capture_snapshot() -> Result<Snapshot> {
-    let domains = list_domains()?;
+/// Options controlling snapshot capture
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaptureOptions {
+    /// Export each domain twice and mark keys that differ between reads as volatile
+    pub detect_volatile: bool,
+    pub managed: ManagedPreferences,
+    /// Also capture system-wide domains in `/Library/Preferences` through sudo
+    pub include_system: bool,
+    /// Capture only this domain (overrides the managed and system options)
+    pub domain: Option<String>,
+    /// Limit for each `defaults` command, overriding the built-in limits
+    pub timeout: Option<Duration>,
+    /// Extra attempts for a failed export, overriding `EXPORT_RETRIES`
+    pub retries: Option<u32>,
+    /// Also capture Apple background service domains (see `is_apple_service_domain`)
+    pub include_apple_services: bool,
+    /// Read another user's defaults through `sudo -u`
+    pub user: Option<String>,
+}
+
+impl CaptureOptions {
+    fn list_timeout(&self) -> Duration {
+        self.timeout.unwrap_or(LIST_TIMEOUT)
+    }
+
+    fn export_timeout(&self) -> Duration {
+        self.timeout.unwrap_or(EXPORT_TIMEOUT)
+    }
+
+    fn retries(&self) -> u32 {
+        self.retries.unwrap_or(EXPORT_RETRIES)
+    }
+
+    /// Whether a listed domain should be captured
+    fn wants_domain(&self, domain: &str) -> bool {
+        self.include_apple_services || !is_apple_service_domain(domain)
+    }
+}
+
+/// Called with `(done, total, domain)` after each domain is read
+pub type Progress<'a> = dyn FnMut(usize, usize, &str) + 'a;
+
+/// Capture snapshot of all domain settings, returning `None` if cancelled
+pub fn capture_snapshot(
+    options: &CaptureOptions,
+    cancel: &AtomicBool,
+    progress: &mut Progress,
+) -> Result<Option<Snapshot>> {
+    if let Some(user) = &options.user {
+        ensure_sudo_as_user(user)?;
+    }
+    if let Some(domain) = &options.domain {
+        let snapshot = capture_domain(domain, options)?;
+        progress(1, 1, domain);
+        return Ok(Some(snapshot));
+    }
+
+    let user = options.user.as_deref();
+    let mut domains = match options.managed {
+        ManagedPreferences::Exclude => {
+            with_global_domain(list_domains(options.list_timeout(), user)?)
+        }
+        ManagedPreferences::Include => {
+            let mut domains = with_global_domain(list_domains(options.list_timeout(), user)?);
+            domains.extend(user_managed_domains(user));
+            domains
+        }
+        ManagedPreferences::Only => user_managed_domains(user),
+    };
+    domains.retain(|domain| options.wants_domain(domain));
+    // List system domains up front so progress can count them too
+    let system_domains = if options.include_system {
+        ensure_sudo_available()?;
+        let mut paths = plist_paths_in(Path::new(SYSTEM_PREFERENCES_DIR))?;
+        paths.retain(|path| options.wants_domain(path.rsplit('/').next().unwrap_or(path)));
+        paths
+    } else {
+        Vec::new()
+    };
+    let total = domains.len() + system_domains.len();
+    let timeout = options.export_timeout();
+
+    let mut user_progress = |done, _, domain: &str| progress(done, total, domain);
+    let Some(mut snapshot) = capture_domains(
+        &domains,
+        |domain| export_domain(domain, timeout, user),
+        options,
+        cancel,
+        &mut user_progress,
+    ) else {
+        return Ok(None);
+    };
+
+    if options.include_system {
+        let offset = domains.len();
+        let mut system_progress = |done, _, domain: &str| progress(offset + done, total, domain);
+        let Some(system) = capture_system_domains(
+            &system_domains,
+            |domain| export_domain_as_root(domain, timeout),
+            options,
+            cancel,
+            &mut system_progress,
+        ) else {
+            return Ok(None);
+        };
+        snapshot.merge(system, MergeStrategy::PreferSelf)?;
+    }
+
+    Ok(Some(snapshot))
+}
+
+/// Capture a snapshot holding just one domain
+pub fn capture_domain(domain: &str, options: &CaptureOptions) -> Result<Snapshot> {
+    let timeout = options.export_timeout();
+    let user = options.user.as_deref();
+    snapshot_of_domain(
+        canonical_domain(domain),
+        |domain| export_domain(domain, timeout, user),
+        options,
+    )
+}
+
+fn snapshot_of_domain(
+    domain: &str,
+    export: impl Fn(&str) -> Result<Vec<u8>>,
+    options: &CaptureOptions,
+) -> Result<Snapshot> {
+    let captured = read_domain(domain, export, options)?;
     let mut snapshot = Snapshot::new();
+    if !captured.volatile.is_empty() {
+        snapshot
+            .volatile_keys
+            .insert(captured.domain.clone(), captured.volatile);
+    }
+    if captured.retried {
+        snapshot.retried_domains.push(captured.domain.clone());
+    }
+    snapshot
+        .export_hashes
+        .insert(captured.domain.clone(), captured.export_hash);
+    snapshot.domains.insert(captured.domain, captured.settings);
+    Ok(snapshot)
+}
+
+/// Capture domains read with elevated privileges, tagging them as system-scoped
+fn capture_system_domains(
+    domains: &[String],
+    export: impl Fn(&str) -> Result<Vec<u8>> + Sync,
+    options: &CaptureOptions,
+    cancel: &AtomicBool,
+    progress: &mut Progress,
+) -> Option<Snapshot> {
+    let mut snapshot = capture_domains(domains, export, options, cancel, progress)?;
+    snapshot.system_domains = snapshot.domains.keys().cloned().collect();
+    Some(snapshot)
+}
 
-    for domain in domains {
-        match export_domain(&domain) {
-            Ok(plist_data) => {
-                if let Ok(settings) = parse_domain_plist(&domain, &plist_data) {
-                    snapshot.domains.insert(domain.clone(), settings);
+/// Managed domains for `user`, or the current user (empty if there are none)
+fn user_managed_domains(user: Option<&str>) -> Vec<String> {
+    let current = std::env::var("USER").ok();
+    let user = user.or(current.as_deref());
+    list_managed_domains(Path::new(MANAGED_PREFERENCES_DIR), user).unwrap_or_default()
+}
+
+/// Number of `defaults export` processes run at once during capture
+const CAPTURE_WORKERS: usize = 8;
+
+/// A successfully read domain
+struct CapturedDomain {
+    domain: String,
+    settings: DomainSettings,
+    export_hash: u64,
+    volatile: HashSet<String>,
+    /// Whether the export only succeeded after a retry
+    retried: bool,
+}
+
+/// Export and parse each domain on a pool of worker threads.
+///
+/// Workers check `cancel` before every export; a cancelled capture returns `None`.
+/// `progress` runs on the calling thread as each domain finishes.
+fn capture_domains(
+    domains: &[String],
+    export: impl Fn(&str) -> Result<Vec<u8>> + Sync,
+    options: &CaptureOptions,
+    cancel: &AtomicBool,
+    progress: &mut Progress,
+) -> Option<Snapshot> {
+    capture_domains_with_workers(domains, export, options, cancel, progress, CAPTURE_WORKERS)
+}
+
+fn capture_domains_with_workers(
+    domains: &[String],
+    export: impl Fn(&str) -> Result<Vec<u8>> + Sync,
+    options: &CaptureOptions,
+    cancel: &AtomicBool,
+    progress: &mut Progress,
+    workers: usize,
+) -> Option<Snapshot> {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut snapshot = Snapshot::new();
+
+    thread::scope(|scope| {
+        for _ in 0..workers.min(domains.len()) {
+            let sender = sender.clone();
+            let (next, export) = (&next, &export);
+            scope.spawn(move || {
+                while !cancel.load(Ordering::Relaxed) {
+                    let Some(domain) = domains.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    let result = read_domain(domain, export, options)
+                        .map_err(|e| (domain.clone(), e.to_string()));
+                    let _ = sender.send(result);
                 }
+            });
+        }
+        drop(sender);
+
+        // Collect while the workers run so progress is reported live
+        for (done, result) in receiver.iter().enumerate() {
+            // Domains that cannot be read are skipped but reported
+            let captured = match result {
+                Ok(captured) => captured,
+                Err(failure) => {
+                    progress(done + 1, domains.len(), &failure.0);
+                    snapshot.failed_domains.push(failure);
+                    continue;
+                }
+            };
+            progress(done + 1, domains.len(), &captured.domain);
+            if !captured.volatile.is_empty() {
+                snapshot
+                    .volatile_keys
+                    .insert(captured.domain.clone(), captured.volatile);
             }
-            Err(_) => {
-                // Skip domains that cannot be read
-                continue;
+            if captured.retried {
+                snapshot.retried_domains.push(captured.domain.clone());
             }
+            snapshot
+                .export_hashes
+                .insert(captured.domain.clone(), captured.export_hash);
+            snapshot.domains.insert(captured.domain, captured.settings);
         }
+    });
+
+    if cancel.load(Ordering::Relaxed) {
+        return None;
     }
+    snapshot.failed_domains.sort();
+    snapshot.retried_domains.sort();
 
-    Ok(snapshot)
+    Some(snapshot)
+}
+
+/// Export and parse a single domain
+fn read_domain(
+    domain: &str,
+    export: impl Fn(&str) -> Result<Vec<u8>>,
+    options: &CaptureOptions,
+) -> Result<CapturedDomain> {
+    let (plist_data, retried) = export_with_retries(domain, &export, options.retries())?;
+    let settings = parse_domain_plist(domain, &plist_data)?;
+    let volatile = if options.detect_volatile {
+        volatile_keys(domain, &settings, &export)
+    } else {
+        HashSet::new()
+    };
+    Ok(CapturedDomain {
+        domain: domain.to_string(),
+        settings,
+        export_hash: export_hash(&plist_data),
+        volatile,
+        retried,
+    })
+}
+
+/// Export a domain, retrying up to `retries` times when the command failed to run
+/// or exited non-zero. Returns the data and whether a retry was needed.
+fn export_with_retries(
+    domain: &str,
+    export: impl Fn(&str) -> Result<Vec<u8>>,
+    retries: u32,
+) -> Result<(Vec<u8>, bool)> {
+    let mut attempt = 0;
+    loop {
+        match export(domain) {
+            Ok(data) => return Ok((data, attempt > 0)),
+            Err(e) if attempt < retries && is_transient(&e) => {
+                thread::sleep(RETRY_BACKOFF * 2u32.pow(attempt));
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Whether an export error may go away on its own (cfprefsd hiccups).
+///
+/// Timeouts are not retried, since waiting again would double a slow capture.
+fn is_transient(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<AppError>(),
+        Some(AppError::DefaultsCommand { .. } | AppError::Io(_))
+    )
+}
+
+/// Hash raw export bytes so unchanged domains can be skipped when diffing
+fn export_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Re-export a domain right away and return keys whose values changed between reads.
+///
+/// Reading some domains updates keys like `LastUsed`, so these keys would show up
+/// as changes caused by the capture itself.
+fn volatile_keys(
+    domain: &str,
+    first: &DomainSettings,
+    export: impl Fn(&str) -> Result<Vec<u8>>,
+) -> HashSet<String> {
+    let Ok(second) = export(domain).and_then(|data| parse_domain_plist(domain, &data)) else {
+        return HashSet::new();
+    };
+
+    first
+        .values
+        .iter()
+        .filter(|(key, value)| {
+            second
+                .values
+                .get(key)
+                .is_none_or(|other| !values_equal(value, other))
+        })
+        .map(|(key, _)| key.clone())
+        .chain(
+            second
+                .values
+                .keys()
+                .filter(|key| !first.values.contains_key(key))
+                .cloned(),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLIST: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>flag</key><true/></dict></plist>"#;
+
+    fn domains(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_is_apple_service_domain() {
+        assert!(is_apple_service_domain("com.apple.xpc.activity2"));
+        assert!(is_apple_service_domain("com.apple.cloudd"));
+        assert!(!is_apple_service_domain("com.apple.cloudddocs"));
+        assert!(!is_apple_service_domain("com.apple.dock"));
+        assert!(!is_apple_service_domain("org.mozilla.firefox"));
+
+        let options = CaptureOptions::default();
+        assert!(!options.wants_domain("com.apple.security.ctkd-db"));
+        let options = CaptureOptions {
+            include_apple_services: true,
+            ..Default::default()
+        };
+        assert!(options.wants_domain("com.apple.security.ctkd-db"));
+    }
+
+    #[test]
+    fn test_defaults_command_for_user() {
+        assert_eq!(
+            describe_command(defaults_command(None).arg("domains")),
+            "defaults domains"
+        );
+        assert_eq!(
+            describe_command(defaults_command(Some("alice")).arg("domains")),
+            "sudo -n -u alice defaults domains"
+        );
+    }
+
+    #[test]
+    fn test_run_checked_reports_exit_and_stderr() {
+        let err = run_checked(
+            Command::new("sh").args(["-c", "echo nope >&2; exit 4"]),
+            Duration::from_secs(5),
+        )
+        .unwrap_err();
+        match err {
+            AppError::DefaultsCommand {
+                command,
+                status,
+                stderr,
+            } => {
+                assert_eq!(command, "sh -c echo nope >&2; exit 4");
+                assert_eq!(status, Some(4));
+                assert_eq!(stderr, "nope\n");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_run_checked_times_out() {
+        let err =
+            run_checked(Command::new("sleep").arg("5"), Duration::from_millis(100)).unwrap_err();
+        assert!(matches!(err, AppError::Timeout { ref command, .. } if command == "sleep 5"));
+    }
+
+    #[test]
+    fn test_run_checked_reads_output_larger_than_pipe_buffer() {
+        // More than the 64 KB a pipe holds, on both streams
+        let output = run_checked(
+            Command::new("sh").args([
+                "-c",
+                "head -c 200000 /dev/zero; head -c 100000 /dev/zero >&2",
+            ]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(output.len(), 200_000);
+    }
+
+    fn command_failure() -> anyhow::Error {
+        AppError::DefaultsCommand {
+            command: "defaults export com.a -".to_string(),
+            status: Some(1),
+            stderr: String::new(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_export_with_retries_recovers() {
+        let attempts = AtomicUsize::new(0);
+        let (data, retried) = export_with_retries(
+            "com.a",
+            |_| {
+                if attempts.fetch_add(1, Ordering::Relaxed) == 0 {
+                    return Err(command_failure());
+                }
+                Ok(PLIST.to_vec())
+            },
+            2,
+        )
+        .unwrap();
+        assert_eq!(data, PLIST);
+        assert!(retried);
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_export_with_retries_gives_up() {
+        let attempts = AtomicUsize::new(0);
+        let result = export_with_retries(
+            "com.a",
+            |_| {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(command_failure())
+            },
+            2,
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_export_with_retries_skips_permanent_errors() {
+        let attempts = AtomicUsize::new(0);
+        let timeout = export_with_retries(
+            "com.a",
+            |_| {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(AppError::Timeout {
+                    command: "defaults export com.a -".to_string(),
+                    after: EXPORT_TIMEOUT,
+                }
+                .into())
+            },
+            2,
+        );
+        assert!(timeout.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+
+        // An empty export is a result, not a failure
+        let (data, retried) = export_with_retries("com.a", |_| Ok(Vec::new()), 2).unwrap();
+        assert!(data.is_empty());
+        assert!(!retried);
+    }
+
+    #[test]
+    fn test_timeout_override() {
+        let options = CaptureOptions::default();
+        assert_eq!(options.list_timeout(), LIST_TIMEOUT);
+        assert_eq!(options.export_timeout(), EXPORT_TIMEOUT);
+
+        let options = CaptureOptions {
+            timeout: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        assert_eq!(options.list_timeout(), Duration::from_secs(30));
+        assert_eq!(options.export_timeout(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_parse_domain_list_commas() {
+        assert_eq!(
+            parse_domain_list("com.apple.dock, com.apple.finder,\ncom.example\n"),
+            domains(&["com.apple.dock", "com.apple.finder", "com.example"])
+        );
+    }
+
+    #[test]
+    fn test_parse_domain_list_newlines() {
+        assert_eq!(
+            parse_domain_list("com.apple.dock\ncom.apple.finder\r\n\ncom.example\n"),
+            domains(&["com.apple.dock", "com.apple.finder", "com.example"])
+        );
+    }
+
+    #[test]
+    fn test_parse_domain_list_mixed() {
+        assert_eq!(
+            parse_domain_list(" com.apple.dock,\ncom.apple.finder, com.example ,, \t"),
+            domains(&["com.apple.dock", "com.apple.finder", "com.example"])
+        );
+        assert!(parse_domain_list("  \n").is_empty());
+    }
+
+    #[test]
+    fn test_parse_domain_list_keeps_spaces_and_inner_commas() {
+        assert_eq!(
+            parse_domain_list("com.apple.dock, Apple Global Domain, My App Prefs, a,b.c\n"),
+            domains(&["com.apple.dock", "NSGlobalDomain", "My App Prefs", "a,b.c"])
+        );
+    }
+
+    #[test]
+    fn test_global_domain_is_captured() {
+        assert!(is_global_domain("NSGlobalDomain"));
+        assert!(is_global_domain("Apple Global Domain"));
+        assert!(!is_global_domain("com.apple.dock"));
+        assert!(!is_global_domain("nsglobaldomain"));
+        assert_eq!(canonical_domain("Apple Global Domain"), GLOBAL_DOMAIN);
+
+        assert_eq!(
+            with_global_domain(domains(&["com.apple.dock"])),
+            domains(&["NSGlobalDomain", "com.apple.dock"])
+        );
+        assert_eq!(
+            with_global_domain(parse_domain_list("com.apple.dock, Apple Global Domain")),
+            domains(&["com.apple.dock", "NSGlobalDomain"])
+        );
+    }
+
+    #[test]
+    fn test_multi_word_domain_survives_export() {
+        // Passed as one argument, so the spaces reach `defaults` intact
+        let export = |domain: &str| -> Result<Vec<u8>> {
+            assert_eq!(domain, "My App Prefs");
+            Ok(br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>k</key><true/></dict></plist>"#
+                .to_vec())
+        };
+        let snapshot =
+            snapshot_of_domain("My App Prefs", export, &CaptureOptions::default()).unwrap();
+        assert!(snapshot.domains["My App Prefs"].values.contains_key("k"));
+    }
+
+    #[test]
+    fn test_list_managed_domains() {
+        let dir =
+            std::env::temp_dir().join(format!("defaults-util-managed-{}", std::process::id()));
+        fs::create_dir_all(dir.join("alice")).unwrap();
+        fs::create_dir_all(dir.join("bob")).unwrap();
+        fs::write(dir.join("com.apple.dock.plist"), PLIST).unwrap();
+        fs::write(dir.join("README.txt"), "not a plist").unwrap();
+        fs::write(dir.join("alice").join("com.apple.Safari.plist"), PLIST).unwrap();
+        fs::write(dir.join("bob").join("com.apple.finder.plist"), PLIST).unwrap();
+
+        let domains = list_managed_domains(&dir, Some("alice")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let expected: Vec<String> = [
+            dir.join("alice").join("com.apple.Safari"),
+            dir.join("com.apple.dock"),
+        ]
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+        assert_eq!(domains, expected);
+    }
+
+    #[test]
+    fn test_list_managed_domains_missing_dir() {
+        assert!(list_managed_domains(Path::new("/nonexistent/managed"), None).is_err());
+    }
+
+    #[test]
+    fn test_domain_plist_path() {
+        let home = std::env::temp_dir().join(format!("defaults-util-home-{}", std::process::id()));
+        let preferences = home.join("Library/Preferences");
+        let container =
+            home.join("Library/Containers/com.example.sandboxed/Data/Library/Preferences");
+        fs::create_dir_all(preferences.join("ByHost")).unwrap();
+        fs::create_dir_all(&container).unwrap();
+        for file in [
+            "com.apple.dock.plist",
+            ".GlobalPreferences.plist",
+            "ByHost/com.apple.dock.extra.1234-ABCD.plist",
+            "ByHost/com.apple.dock.1234-ABCD.plist",
+            "ByHost/.GlobalPreferences.1234-ABCD.plist",
+        ] {
+            fs::write(preferences.join(file), PLIST).unwrap();
+        }
+        fs::write(container.join("com.example.sandboxed.plist"), PLIST).unwrap();
+
+        let resolve = |domain: &str, current_host: bool| {
+            domain_plist_path(domain, current_host, &home)
+                .map(|path| path.strip_prefix(&home).unwrap().to_path_buf())
+        };
+        assert_eq!(
+            resolve("com.apple.dock", false).unwrap(),
+            Path::new("Library/Preferences/com.apple.dock.plist")
+        );
+        assert_eq!(
+            resolve("Apple Global Domain", false).unwrap(),
+            Path::new("Library/Preferences/.GlobalPreferences.plist")
+        );
+        assert_eq!(
+            resolve("com.apple.dock", true).unwrap(),
+            Path::new("Library/Preferences/ByHost/com.apple.dock.1234-ABCD.plist")
+        );
+        assert_eq!(
+            resolve("NSGlobalDomain", true).unwrap(),
+            Path::new("Library/Preferences/ByHost/.GlobalPreferences.1234-ABCD.plist")
+        );
+        assert!(
+            resolve("com.example.sandboxed", false)
+                .unwrap()
+                .starts_with("Library/Containers")
+        );
+        let missing = resolve("com.example.missing", false).unwrap_err();
+        assert!(
+            missing
+                .to_string()
+                .starts_with("No plist file for com.example.missing at")
+        );
+        assert!(resolve("com.example.missing", true).is_err());
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_domain_arg_path_style() {
+        assert_eq!(
+            domain_arg("/Library/Preferences/com.apple.loginwindow.plist"),
+            "/Library/Preferences/com.apple.loginwindow"
+        );
+        assert_eq!(domain_arg("com.example.plist"), "com.example.plist");
+        assert_eq!(domain_arg("com.apple.dock"), "com.apple.dock");
+    }
+
+    #[test]
+    fn test_capture_domains_completes() {
+        let cancel = AtomicBool::new(false);
+        let snapshot = capture_domains(
+            &domains(&["a", "b"]),
+            |_| Ok(PLIST.to_vec()),
+            &CaptureOptions::default(),
+            &cancel,
+            &mut |_, _, _| {},
+        );
+        assert_eq!(snapshot.map(|s| s.domain_count()), Some(2));
+    }
+
+    #[test]
+    fn test_capture_domains_reports_progress() {
+        let cancel = AtomicBool::new(false);
+        let mut reports = Vec::new();
+        capture_domains(
+            &domains(&["a", "b", "c"]),
+            |domain| {
+                if domain == "b" {
+                    anyhow::bail!("unreadable");
+                }
+                Ok(PLIST.to_vec())
+            },
+            &CaptureOptions::default(),
+            &cancel,
+            &mut |done, total, domain| reports.push((done, total, domain.to_string())),
+        );
+
+        assert_eq!(
+            reports.iter().map(|r| (r.0, r.1)).collect::<Vec<_>>(),
+            vec![(1, 3), (2, 3), (3, 3)]
+        );
+        let mut seen: Vec<_> = reports.into_iter().map(|r| r.2).collect();
+        seen.sort();
+        assert_eq!(seen, domains(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn test_snapshot_of_domain() {
+        let snapshot =
+            snapshot_of_domain("com.a", |_| Ok(PLIST.to_vec()), &CaptureOptions::default())
+                .unwrap();
+        assert_eq!(snapshot.domain_count(), 1);
+        assert!(snapshot.domains["com.a"].values.contains_key("flag"));
+        assert!(snapshot.export_hashes.contains_key("com.a"));
+
+        let err = snapshot_of_domain(
+            "com.missing",
+            |_| anyhow::bail!("no such domain"),
+            &CaptureOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "no such domain");
+    }
+
+    #[test]
+    fn test_capture_domains_stops_when_cancelled() {
+        let cancel = AtomicBool::new(false);
+        let exported = AtomicUsize::new(0);
+        let snapshot = capture_domains_with_workers(
+            &domains(&["a", "b", "c"]),
+            |_| {
+                exported.fetch_add(1, Ordering::Relaxed);
+                cancel.store(true, Ordering::Relaxed);
+                Ok(PLIST.to_vec())
+            },
+            &CaptureOptions::default(),
+            &cancel,
+            &mut |_, _, _| {},
+            1,
+        );
+        assert!(snapshot.is_none());
+        assert_eq!(exported.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_parallel_capture_stops_when_cancelled() {
+        let cancel = AtomicBool::new(false);
+        let exported = AtomicUsize::new(0);
+        let names: Vec<String> = (0..100).map(|i| format!("com.example.{}", i)).collect();
+        let snapshot = capture_domains(
+            &names,
+            |_| {
+                exported.fetch_add(1, Ordering::Relaxed);
+                cancel.store(true, Ordering::Relaxed);
+                Ok(PLIST.to_vec())
+            },
+            &CaptureOptions::default(),
+            &cancel,
+            &mut |_, _, _| {},
+        );
+        assert!(snapshot.is_none());
+        assert!(exported.load(Ordering::Relaxed) <= CAPTURE_WORKERS);
+    }
+
+    #[test]
+    fn test_parallel_capture_keeps_every_domain() {
+        let cancel = AtomicBool::new(false);
+        let names: Vec<String> = (0..200).map(|i| format!("com.example.{}", i)).collect();
+        let snapshot = capture_domains(
+            &names,
+            |domain| {
+                // Finish out of order
+                let delay = domain.len() % 3;
+                thread::sleep(std::time::Duration::from_millis(delay as u64));
+                if domain.ends_with(".13") {
+                    anyhow::bail!("unreadable");
+                }
+                Ok(PLIST.to_vec())
+            },
+            &CaptureOptions::default(),
+            &cancel,
+            &mut |_, _, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(snapshot.domain_count(), 199);
+        assert_eq!(
+            snapshot.failed_domains,
+            vec![("com.example.13".to_string(), "unreadable".to_string())]
+        );
+        assert!(
+            names
+                .iter()
+                .filter(|name| !name.ends_with(".13"))
+                .all(|name| snapshot.domains.contains_key(name))
+        );
+    }
+
+    fn counter_plist(n: usize) -> Vec<u8> {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>flag</key><true/><key>LastUsed</key><integer>{}</integer></dict></plist>"#,
+            n
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_volatile_keys_detects_changed_key() {
+        let reads = std::cell::Cell::new(0);
+        let export = |_: &str| {
+            reads.set(reads.get() + 1);
+            Ok(counter_plist(reads.get()))
+        };
+        let first = parse_domain_plist("a", &export("a").unwrap()).unwrap();
+
+        let volatile = volatile_keys("a", &first, export);
+        assert_eq!(volatile, HashSet::from(["LastUsed".to_string()]));
+    }
+
+    #[test]
+    fn test_capture_domains_records_volatile_keys() {
+        let cancel = AtomicBool::new(false);
+        let reads = AtomicUsize::new(0);
+        let options = CaptureOptions {
+            detect_volatile: true,
+            ..Default::default()
+        };
+        let snapshot = capture_domains(
+            &domains(&["a"]),
+            |_| Ok(counter_plist(reads.fetch_add(1, Ordering::Relaxed) + 1)),
+            &options,
+            &cancel,
+            &mut |_, _, _| {},
+        )
+        .unwrap();
+
+        assert!(snapshot.volatile_keys["a"].contains("LastUsed"));
+        assert!(!snapshot.volatile_keys["a"].contains("flag"));
+    }
+
+    #[test]
+    fn test_system_scope_survives_into_commands() {
+        use crate::command::generator::CommandOptions;
+        use crate::command::script::collect_commands;
+        use crate::diff::{DiffOptions, detect_diff};
+
+        let cancel = AtomicBool::new(false);
+        let options = CaptureOptions::default();
+        let system_domain = "/Library/Preferences/com.apple.loginwindow";
+
+        let mut after = capture_domains(
+            &domains(&["com.example.app"]),
+            |_| Ok(PLIST.to_vec()),
+            &options,
+            &cancel,
+            &mut |_, _, _| {},
+        )
+        .unwrap();
+        let system = capture_system_domains(
+            &domains(&[system_domain]),
+            |_| Ok(PLIST.to_vec()),
+            &options,
+            &cancel,
+            &mut |_, _, _| {},
+        )
+        .unwrap();
+        after.merge(system, MergeStrategy::PreferSelf).unwrap();
+
+        let diff = detect_diff(&Snapshot::new(), &after, &DiffOptions::default());
+        let commands = collect_commands(&diff, &CommandOptions::default(), false);
+        assert_eq!(
+            commands,
+            format!(
+                "sudo defaults write \"{}\" \"flag\" -bool true\n\
+                 defaults write \"com.example.app\" \"flag\" -bool true\n",
+                system_domain
+            )
+        );
+    }
 }
@@ -1,28 +1,200 @@
-use plist::Value as PlistValue;
-use std::collections::HashMap;
+use plist::{Dictionary, Value as PlistValue};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
-/// Settings data for a single domain
+use anyhow::{Result, bail};
+
+use crate::diff::detector::values_equal;
+
+/// Settings data for a single domain, in the order `defaults export` wrote them
 #[derive(Debug, Clone)]
 pub struct DomainSettings {
-    pub values: HashMap<String, PlistValue>,
+    pub values: Dictionary,
+}
+
+/// Privilege a domain was captured with
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Scope {
+    /// Read as the current user
+    #[default]
+    User,
+    /// Read through sudo; writes need sudo too
+    System,
+}
+
+/// Precedence when merging snapshots that share a domain/key
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum MergeStrategy {
+    /// Keep the existing value
+    PreferSelf,
+    /// Take the incoming value
+    PreferOther,
+    /// Fail if the same key has different values
+    ErrorOnConflict,
 }
 
 /// Snapshot of all domains
 #[derive(Debug, Clone)]
 pub struct Snapshot {
     pub domains: HashMap<String, DomainSettings>,
+    /// Keys per domain that changed merely by being read
+    pub volatile_keys: HashMap<String, HashSet<String>>,
+    /// Domains captured at system scope (every key in them is system-scoped)
+    pub system_domains: HashSet<String>,
+    /// Hash of each domain's raw export, used to skip diffing untouched
+    /// domains (each export is still parsed when it is captured)
+    pub export_hashes: HashMap<String, u64>,
+    /// Domains that could not be exported or parsed, with the error message
+    pub failed_domains: Vec<(String, String)>,
+    /// Domains that were only read after retrying a failed export
+    pub retried_domains: Vec<String>,
 }
 
 impl Snapshot {
     pub fn new() -> Self {
         Self {
             domains: HashMap::new(),
+            volatile_keys: HashMap::new(),
+            system_domains: HashSet::new(),
+            export_hashes: HashMap::new(),
+            failed_domains: Vec::new(),
+            retried_domains: Vec::new(),
         }
     }
 
     pub fn domain_count(&self) -> usize {
         self.domains.len()
     }
+
+    /// Whether a key was marked volatile during capture
+    pub fn is_volatile(&self, domain: &str, key: &str) -> bool {
+        self.volatile_keys
+            .get(domain)
+            .is_some_and(|keys| keys.contains(key))
+    }
+
+    /// Scope a domain was captured at
+    pub fn scope(&self, domain: &str) -> Scope {
+        if self.system_domains.contains(domain) {
+            Scope::System
+        } else {
+            Scope::User
+        }
+    }
+
+    /// Whether a domain's raw export was byte-identical in both snapshots
+    pub fn same_export(&self, other: &Snapshot, domain: &str) -> bool {
+        self.export_hashes
+            .get(domain)
+            .is_some_and(|hash| other.export_hashes.get(domain) == Some(hash))
+    }
+
+    /// Count values of each type across all domains
+    pub fn type_histogram(&self) -> HashMap<&'static str, usize> {
+        let mut histogram = HashMap::new();
+        for settings in self.domains.values() {
+            for value in settings.values.values() {
+                *histogram.entry(value_type_label(value)).or_insert(0) += 1;
+            }
+        }
+        histogram
+    }
+
+    /// Merge another snapshot into this one, resolving key conflicts with `strategy`
+    pub fn merge(&mut self, other: Snapshot, strategy: MergeStrategy) -> Result<()> {
+        if strategy == MergeStrategy::ErrorOnConflict {
+            for (domain, settings) in &other.domains {
+                let Some(existing) = self.domains.get(domain) else {
+                    continue;
+                };
+                for (key, value) in &settings.values {
+                    if existing
+                        .values
+                        .get(key)
+                        .is_some_and(|current| !values_equal(current, value))
+                    {
+                        bail!("Conflicting values for '{}' in domain '{}'", key, domain);
+                    }
+                }
+            }
+        }
+
+        for (domain, settings) in other.domains {
+            // A merged domain no longer matches either raw export
+            if self.domains.contains_key(&domain) {
+                self.export_hashes.remove(&domain);
+            } else if let Some(hash) = other.export_hashes.get(&domain) {
+                self.export_hashes.insert(domain.clone(), *hash);
+            }
+            let existing = self
+                .domains
+                .entry(domain)
+                .or_insert_with(|| DomainSettings {
+                    values: Dictionary::new(),
+                });
+            for (key, value) in settings.values {
+                if strategy == MergeStrategy::PreferOther || !existing.values.contains_key(&key) {
+                    existing.values.insert(key, value);
+                }
+            }
+        }
+        for (domain, keys) in other.volatile_keys {
+            self.volatile_keys.entry(domain).or_default().extend(keys);
+        }
+        self.system_domains.extend(other.system_domains);
+        self.failed_domains.extend(other.failed_domains);
+        self.retried_domains.extend(other.retried_domains);
+
+        Ok(())
+    }
+
+    /// Save the snapshot as a plist file mapping domain names to settings dictionaries
+    pub fn save_to_file(&self, path: &Path) -> Result<()> {
+        let mut root = plist::Dictionary::new();
+        let mut domains: Vec<_> = self.domains.iter().collect();
+        domains.sort_by(|a, b| a.0.cmp(b.0));
+        for (domain, settings) in domains {
+            root.insert(
+                domain.clone(),
+                PlistValue::Dictionary(settings.values.clone()),
+            );
+        }
+
+        PlistValue::Dictionary(root).to_file_xml(path)?;
+        Ok(())
+    }
+
+    /// Load a snapshot from a plist file mapping domain names to settings dictionaries
+    pub fn load_from_file(path: &Path) -> Result<Snapshot> {
+        let PlistValue::Dictionary(root) = PlistValue::from_file(path)? else {
+            bail!("Snapshot file '{}' is not a dictionary", path.display());
+        };
+
+        let mut snapshot = Snapshot::new();
+        for (domain, value) in root {
+            if let PlistValue::Dictionary(values) = value {
+                snapshot.domains.insert(domain, DomainSettings { values });
+            }
+        }
+
+        Ok(snapshot)
+    }
+}
+
+/// Short label for a plist value's type
+pub fn value_type_label(value: &PlistValue) -> &'static str {
+    match value {
+        PlistValue::Boolean(_) => "bool",
+        PlistValue::Integer(_) => "int",
+        PlistValue::Real(_) => "float",
+        PlistValue::String(_) => "string",
+        PlistValue::Data(_) => "data",
+        PlistValue::Date(_) => "date",
+        PlistValue::Array(_) => "array",
+        PlistValue::Dictionary(_) => "dict",
+        PlistValue::Uid(_) => "uid",
+        _ => "unknown",
+    }
 }
 
 impl Default for Snapshot {
@@ -30,3 +202,134 @@ impl Default for Snapshot {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_type_histogram() {
+        let mut snapshot = Snapshot::new();
+        snapshot.domains.insert(
+            "com.a".to_string(),
+            DomainSettings {
+                values: Dictionary::from_iter([
+                    ("b1".to_string(), PlistValue::Boolean(true)),
+                    ("b2".to_string(), PlistValue::Boolean(false)),
+                    ("s".to_string(), PlistValue::String("x".to_string())),
+                ]),
+            },
+        );
+        snapshot.domains.insert(
+            "com.b".to_string(),
+            DomainSettings {
+                values: Dictionary::from_iter([
+                    ("i".to_string(), PlistValue::Integer(1.into())),
+                    ("b".to_string(), PlistValue::Boolean(true)),
+                ]),
+            },
+        );
+
+        let histogram = snapshot.type_histogram();
+        assert_eq!(histogram.get("bool"), Some(&3));
+        assert_eq!(histogram.get("string"), Some(&1));
+        assert_eq!(histogram.get("int"), Some(&1));
+        assert_eq!(histogram.get("dict"), None);
+    }
+
+    fn snapshot(domains: &[(&str, &[(&str, i64)])]) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        for (domain, kvs) in domains {
+            let values = kvs
+                .iter()
+                .map(|(k, v)| (k.to_string(), PlistValue::Integer((*v).into())))
+                .collect();
+            snapshot
+                .domains
+                .insert(domain.to_string(), DomainSettings { values });
+        }
+        snapshot
+    }
+
+    fn int_at(snapshot: &Snapshot, domain: &str, key: &str) -> Option<i64> {
+        snapshot.domains[domain].values[key].as_signed_integer()
+    }
+
+    fn overlapping() -> (Snapshot, Snapshot) {
+        (
+            snapshot(&[("com.a", &[("shared", 1), ("mine", 1)])]),
+            snapshot(&[
+                ("com.a", &[("shared", 2), ("theirs", 2)]),
+                ("com.b", &[("k", 3)]),
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_merge_prefer_self() {
+        let (mut base, other) = overlapping();
+        base.merge(other, MergeStrategy::PreferSelf).unwrap();
+        assert_eq!(int_at(&base, "com.a", "shared"), Some(1));
+        assert_eq!(int_at(&base, "com.a", "mine"), Some(1));
+        assert_eq!(int_at(&base, "com.a", "theirs"), Some(2));
+        assert_eq!(int_at(&base, "com.b", "k"), Some(3));
+    }
+
+    #[test]
+    fn test_merge_prefer_other() {
+        let (mut base, other) = overlapping();
+        base.merge(other, MergeStrategy::PreferOther).unwrap();
+        assert_eq!(int_at(&base, "com.a", "shared"), Some(2));
+        assert_eq!(int_at(&base, "com.a", "mine"), Some(1));
+        assert_eq!(base.domain_count(), 2);
+    }
+
+    #[test]
+    fn test_merge_error_on_conflict() {
+        let (mut base, other) = overlapping();
+        let err = base
+            .merge(other, MergeStrategy::ErrorOnConflict)
+            .unwrap_err();
+        assert!(err.to_string().contains("shared"));
+        // Nothing was merged
+        assert_eq!(base.domain_count(), 1);
+        assert!(!base.domains["com.a"].values.contains_key("theirs"));
+    }
+
+    #[test]
+    fn test_merge_error_on_conflict_allows_equal_values() {
+        let mut base = snapshot(&[("com.a", &[("shared", 1)])]);
+        let other = snapshot(&[("com.a", &[("shared", 1), ("new", 2)])]);
+        base.merge(other, MergeStrategy::ErrorOnConflict).unwrap();
+        assert_eq!(int_at(&base, "com.a", "new"), Some(2));
+    }
+
+    #[test]
+    fn test_merge_drops_export_hash_of_shared_domain() {
+        let (mut base, mut other) = overlapping();
+        base.export_hashes.insert("com.a".to_string(), 1);
+        other.export_hashes.insert("com.a".to_string(), 2);
+        other.export_hashes.insert("com.b".to_string(), 3);
+        base.merge(other, MergeStrategy::PreferSelf).unwrap();
+        assert_eq!(base.export_hashes.get("com.a"), None);
+        assert_eq!(base.export_hashes.get("com.b"), Some(&3));
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let original = snapshot(&[
+            ("com.a", &[("x", 1)]),
+            ("/Library/Preferences/b", &[("y", 2)]),
+        ]);
+        let path = std::env::temp_dir().join(format!(
+            "defaults-util-snapshot-{}.plist",
+            std::process::id()
+        ));
+        original.save_to_file(&path).unwrap();
+        let loaded = Snapshot::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.domain_count(), 2);
+        assert_eq!(int_at(&loaded, "/Library/Preferences/b", "y"), Some(2));
+    }
+}
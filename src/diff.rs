@@ -1,5 +1,8 @@
 pub mod detector;
+pub mod format;
+pub mod ignore;
 pub mod types;
 
-pub use detector::detect_diff;
+pub use detector::{DiffOptions, detect_diff, is_factory_default};
+pub use ignore::IgnoreList;
 pub use types::{Change, DiffResult};
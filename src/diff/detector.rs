@@ -1,31 +1,100 @@
-use plist::Value;
-use std::collections::HashMap;
+use std::cell::OnceCell;
+use std::sync::Arc;
+
+use plist::{Dictionary, Value};
 
 use crate::defaults::Snapshot;
 
-use super::types::{Change, DiffResult, DomainDiff};
+use super::format::date_seconds;
+use super::ignore::IgnoreList;
+use super::types::{Change, DiffResult, DomainDiff, Normalization};
+
+/// Default tolerance for `Real` comparisons, loose enough to absorb the rounding
+/// noise in serialized window coordinates
+pub const DEFAULT_FLOAT_EPSILON: f64 = 1e-6;
+
+/// Options controlling diff detection
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffOptions {
+    /// Report only added/removed keys, ignoring value changes
+    pub keys_only: bool,
+    /// Treat strings that differ only in whitespace as equal
+    pub ignore_whitespace: bool,
+    /// Treat integers and reals with the same value as equal
+    pub numeric_equivalence: bool,
+    /// Treat booleans and integer 0/1 as equal
+    pub bool_int_equivalence: bool,
+    /// Keep normalized-away differences as `Change::Normalized` instead of hiding them
+    pub show_normalized: bool,
+    /// Reals closer than this are equal
+    pub float_epsilon: f64,
+    /// Domains and keys left out of the diff
+    pub ignore: IgnoreList,
+    /// Keep changes the ignore list matches, to audit what it filters
+    pub show_ignored: bool,
+}
+
+impl DiffOptions {
+    /// Whether the ignore list hides this domain
+    fn ignores_domain(&self, domain: &str) -> bool {
+        !self.show_ignored && self.ignore.ignores_domain(domain)
+    }
+
+    /// Whether the ignore list hides changes under this top-level key
+    fn ignores_key(&self, key: &str) -> bool {
+        !self.show_ignored && self.ignore.ignores_key(key)
+    }
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            keys_only: false,
+            ignore_whitespace: false,
+            numeric_equivalence: false,
+            bool_int_equivalence: false,
+            show_normalized: false,
+            float_epsilon: DEFAULT_FLOAT_EPSILON,
+            ignore: IgnoreList::default(),
+            show_ignored: false,
+        }
+    }
+}
 
 /// Detect diff between two snapshots
-pub fn detect_diff(before: &Snapshot, after: &Snapshot) -> DiffResult {
+pub fn detect_diff(before: &Snapshot, after: &Snapshot, options: &DiffOptions) -> DiffResult {
     let mut domain_diffs = Vec::new();
-    let mut total_changes = 0;
 
     // Check domains that exist in after
     for (domain, after_settings) in &after.domains {
+        if options.ignores_domain(domain) {
+            continue;
+        }
         let mut changes = Vec::new();
 
         match before.domains.get(domain) {
+            // Byte-identical exports cannot differ, so skip the key-by-key diff
+            Some(_) if before.same_export(after, domain) => {}
             Some(before_settings) => {
                 // Detect changes in existing domain
                 changes.extend(detect_domain_changes(
                     domain,
                     &before_settings.values,
                     &after_settings.values,
+                    options,
                 ));
+                // Skip keys that change merely by being read
+                changes.retain(|c| {
+                    !before.is_volatile(domain, c.root_key())
+                        && !after.is_volatile(domain, c.root_key())
+                });
             }
             None => {
                 // New domain (all keys are added)
                 for (key, value) in &after_settings.values {
+                    if options.ignores_key(key) {
+                        continue;
+                    }
                     changes.push(Change::Added {
                         domain: domain.clone(),
                         key: key.clone(),
@@ -36,20 +105,21 @@ pub fn detect_diff(before: &Snapshot, after: &Snapshot) -> DiffResult {
         }
 
         if !changes.is_empty() {
-            total_changes += changes.len();
             domain_diffs.push(DomainDiff {
                 domain: domain.clone(),
                 changes,
+                scope: after.scope(domain),
             });
         }
     }
 
     // Domains that only exist in before (deleted domains)
     for (domain, before_settings) in &before.domains {
-        if !after.domains.contains_key(domain) {
+        if !after.domains.contains_key(domain) && !options.ignores_domain(domain) {
             let changes: Vec<Change> = before_settings
                 .values
                 .iter()
+                .filter(|(key, _)| !options.ignores_key(key))
                 .map(|(key, value)| Change::Removed {
                     domain: domain.clone(),
                     key: key.clone(),
@@ -57,10 +127,13 @@ pub fn detect_diff(before: &Snapshot, after: &Snapshot) -> DiffResult {
                 })
                 .collect();
 
-            total_changes += changes.len();
+            if changes.is_empty() {
+                continue;
+            }
             domain_diffs.push(DomainDiff {
                 domain: domain.clone(),
                 changes,
+                scope: before.scope(domain),
             });
         }
     }
@@ -69,30 +142,62 @@ pub fn detect_diff(before: &Snapshot, after: &Snapshot) -> DiffResult {
     domain_diffs.sort_by(|a, b| a.domain.cmp(&b.domain));
 
     DiffResult {
+        total_changes: domain_diffs.iter().map(DomainDiff::change_count).sum(),
         domain_diffs,
-        total_changes,
     }
 }
 
 /// Detect key changes within a domain
 fn detect_domain_changes(
     domain: &str,
-    before: &HashMap<String, Value>,
-    after: &HashMap<String, Value>,
+    before: &Dictionary,
+    after: &Dictionary,
+    options: &DiffOptions,
 ) -> Vec<Change> {
     let mut changes = Vec::new();
 
     // Check keys that exist in after
     for (key, after_value) in after {
+        if options.ignores_key(key) {
+            continue;
+        }
         match before.get(key) {
+            Some(_) if options.keys_only => {}
             Some(before_value) => {
-                if !values_equal(before_value, after_value) {
-                    changes.push(Change::Modified {
-                        domain: domain.to_string(),
-                        key: key.clone(),
-                        old_value: before_value.clone(),
-                        new_value: after_value.clone(),
-                    });
+                if values_equal_within(before_value, after_value, options.float_epsilon) {
+                    continue;
+                }
+                match (before_value, after_value) {
+                    (Value::Dictionary(_), Value::Dictionary(_)) => {
+                        let root = NestedRoot::new(domain, key, before_value, after_value);
+                        diff_nested(&root, key, before_value, after_value, options, &mut changes);
+                        continue;
+                    }
+                    (Value::Array(old), Value::Array(new)) => {
+                        changes.extend(diff_arrays(domain, key, old, new, options));
+                        continue;
+                    }
+                    _ => {}
+                }
+                match normalization(before_value, after_value, options) {
+                    Some(reason) if options.show_normalized => {
+                        changes.push(Change::Normalized {
+                            domain: domain.to_string(),
+                            key: key.clone(),
+                            old_value: before_value.clone(),
+                            new_value: after_value.clone(),
+                            reason,
+                        });
+                    }
+                    Some(_) => {}
+                    None => {
+                        changes.push(Change::Modified {
+                            domain: domain.to_string(),
+                            key: key.clone(),
+                            old_value: before_value.clone(),
+                            new_value: after_value.clone(),
+                        });
+                    }
                 }
             }
             None => {
@@ -107,7 +212,7 @@ fn detect_domain_changes(
 
     // Keys that only exist in before (deleted)
     for (key, before_value) in before {
-        if !after.contains_key(key) {
+        if !after.contains_key(key) && !options.ignores_key(key) {
             changes.push(Change::Removed {
                 domain: domain.to_string(),
                 key: key.clone(),
@@ -116,21 +221,179 @@ fn detect_domain_changes(
         }
     }
 
-    // Sort by key name
-    changes.sort_by(|a, b| a.key().cmp(b.key()));
+    // Changes follow the export's key order: current keys first, then removed ones
+    changes
+}
+
+/// Top-level value that nested changes belong to
+struct NestedRoot<'a> {
+    domain: &'a str,
+    key: &'a str,
+    old_root: &'a Value,
+    new_root: &'a Value,
+    /// The roots copied once for all of their leaves' changes to share
+    shared: OnceCell<(Arc<Value>, Arc<Value>)>,
+}
 
+impl<'a> NestedRoot<'a> {
+    fn new(domain: &'a str, key: &'a str, old_root: &'a Value, new_root: &'a Value) -> Self {
+        NestedRoot {
+            domain,
+            key,
+            old_root,
+            new_root,
+            shared: OnceCell::new(),
+        }
+    }
+
+    fn change(&self, path: String, old: Option<&Value>, new: Option<&Value>) -> Change {
+        let (old_root, new_root) = self.shared.get_or_init(|| {
+            (
+                Arc::new(self.old_root.clone()),
+                Arc::new(self.new_root.clone()),
+            )
+        });
+        Change::Nested {
+            domain: self.domain.to_string(),
+            key: path,
+            root_key: self.key.to_string(),
+            old_value: old.cloned(),
+            new_value: new.cloned(),
+            old_root: Arc::clone(old_root),
+            new_root: Arc::clone(new_root),
+        }
+    }
+}
+
+/// Compare two arrays index by index, emitting `key[i]` changes for differing
+/// positions plus additions/removals at the tail
+pub fn diff_arrays(
+    domain: &str,
+    key: &str,
+    before: &[Value],
+    after: &[Value],
+    options: &DiffOptions,
+) -> Vec<Change> {
+    let (old_root, new_root) = (Value::Array(before.to_vec()), Value::Array(after.to_vec()));
+    let root = NestedRoot::new(domain, key, &old_root, &new_root);
+    let mut changes = Vec::new();
+    diff_nested(&root, key, &old_root, &new_root, options, &mut changes);
     changes
 }
 
-/// Compare plist::Value recursively
-fn values_equal(a: &Value, b: &Value) -> bool {
+/// Descend into dictionaries and arrays, emitting a change per differing leaf
+/// with a path like `window.frame.width` or `items[3]`. Leaves equal under an
+/// enabled normalization are skipped.
+fn diff_nested(
+    root: &NestedRoot,
+    path: &str,
+    before: &Value,
+    after: &Value,
+    options: &DiffOptions,
+    changes: &mut Vec<Change>,
+) {
+    if values_equal_within(before, after, options.float_epsilon) {
+        return;
+    }
+    match (before, after) {
+        (Value::Dictionary(old), Value::Dictionary(new)) => {
+            for (key, after_value) in new {
+                let child = format!("{}.{}", path, key);
+                match old.get(key) {
+                    Some(before_value) => {
+                        diff_nested(root, &child, before_value, after_value, options, changes)
+                    }
+                    None => changes.push(root.change(child, None, Some(after_value))),
+                }
+            }
+            for (key, before_value) in old {
+                if !new.contains_key(key) {
+                    changes.push(root.change(
+                        format!("{}.{}", path, key),
+                        Some(before_value),
+                        None,
+                    ));
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for index in 0..old.len().max(new.len()) {
+                let child = format!("{}[{}]", path, index);
+                match (old.get(index), new.get(index)) {
+                    (Some(before_value), Some(after_value)) => {
+                        diff_nested(root, &child, before_value, after_value, options, changes)
+                    }
+                    (None, Some(after_value)) => {
+                        changes.push(root.change(child, None, Some(after_value)))
+                    }
+                    (Some(before_value), None) => {
+                        changes.push(root.change(child, Some(before_value), None))
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+        _ => {
+            if normalization(before, after, options).is_none() {
+                changes.push(root.change(path.to_string(), Some(before), Some(after)));
+            }
+        }
+    }
+}
+
+/// Find the enabled normalization under which two differing values are equal
+fn normalization(a: &Value, b: &Value, options: &DiffOptions) -> Option<Normalization> {
+    match (a, b) {
+        (Value::String(a), Value::String(b))
+            if options.ignore_whitespace && a.split_whitespace().eq(b.split_whitespace()) =>
+        {
+            Some(Normalization::Whitespace)
+        }
+        (Value::Integer(i), Value::Real(f)) | (Value::Real(f), Value::Integer(i))
+            if options.numeric_equivalence && i.as_signed().is_some_and(|i| i as f64 == *f) =>
+        {
+            Some(Normalization::Numeric)
+        }
+        (Value::Boolean(flag), Value::Integer(i)) | (Value::Integer(i), Value::Boolean(flag))
+            if options.bool_int_equivalence && i.as_signed() == Some(i64::from(*flag)) =>
+        {
+            Some(Normalization::BoolInt)
+        }
+        _ => None,
+    }
+}
+
+/// Check whether a change merely sets a key to its factory default value
+pub fn is_factory_default(change: &Change, baseline: &Snapshot) -> bool {
+    // Nested changes restore a default only if the whole top-level value matches
+    let new_value = match change {
+        Change::Nested { new_root, .. } => Some(new_root.as_ref()),
+        _ => change.new_value(),
+    };
+    new_value.is_some_and(|value| {
+        baseline
+            .domains
+            .get(change.domain())
+            .and_then(|settings| settings.values.get(change.root_key()))
+            .is_some_and(|default| values_equal(default, value))
+    })
+}
+
+/// Compare plist::Value recursively with the default float tolerance
+pub fn values_equal(a: &Value, b: &Value) -> bool {
+    values_equal_within(a, b, DEFAULT_FLOAT_EPSILON)
+}
+
+/// Compare plist::Value recursively, treating reals within `epsilon` (or both NaN) as equal
+pub fn values_equal_within(a: &Value, b: &Value, epsilon: f64) -> bool {
+    let values_equal = |a: &Value, b: &Value| values_equal_within(a, b, epsilon);
     match (a, b) {
         (Value::Boolean(a), Value::Boolean(b)) => a == b,
         (Value::Integer(a), Value::Integer(b)) => a == b,
-        (Value::Real(a), Value::Real(b)) => (a - b).abs() < f64::EPSILON,
+        (Value::Real(a), Value::Real(b)) => (a.is_nan() && b.is_nan()) || (a - b).abs() <= epsilon,
         (Value::String(a), Value::String(b)) => a == b,
         (Value::Data(a), Value::Data(b)) => a == b,
-        (Value::Date(a), Value::Date(b)) => a == b,
+        (Value::Date(a), Value::Date(b)) => date_seconds(a) == date_seconds(b),
         (Value::Array(a), Value::Array(b)) => {
             a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| values_equal(x, y))
         }
@@ -154,7 +417,7 @@ mod tests {
         let mut snap = Snapshot::new();
         for (domain, kvs) in domains {
             let mut settings = DomainSettings {
-                values: HashMap::new(),
+                values: Dictionary::new(),
             };
             for (k, v) in kvs {
                 settings.values.insert(k.to_string(), v);
@@ -171,7 +434,7 @@ mod tests {
         let before = make_snapshot(vec![]);
         let after = make_snapshot(vec![("com.test", vec![("key1", Value::Boolean(true))])]);
 
-        let result = detect_diff(&before, &after);
+        let result = detect_diff(&before, &after, &DiffOptions::default());
         assert_eq!(result.total_changes, 1);
         assert_eq!(result.domain_diffs.len(), 1);
         match &result.domain_diffs[0].changes[0] {
@@ -192,7 +455,7 @@ mod tests {
         )]);
         let after = make_snapshot(vec![]);
 
-        let result = detect_diff(&before, &after);
+        let result = detect_diff(&before, &after, &DiffOptions::default());
         assert_eq!(result.total_changes, 1);
         match &result.domain_diffs[0].changes[0] {
             Change::Removed {
@@ -213,7 +476,7 @@ mod tests {
         let before = make_snapshot(vec![("com.test", vec![("key1", Value::Integer(1.into()))])]);
         let after = make_snapshot(vec![("com.test", vec![("key1", Value::Integer(2.into()))])]);
 
-        let result = detect_diff(&before, &after);
+        let result = detect_diff(&before, &after, &DiffOptions::default());
         assert_eq!(result.total_changes, 1);
         match &result.domain_diffs[0].changes[0] {
             Change::Modified {
@@ -236,13 +499,227 @@ mod tests {
         let before = make_snapshot(vec![("com.test", vec![("key1", Value::Boolean(true))])]);
         let after = make_snapshot(vec![("com.test", vec![("key1", Value::Boolean(true))])]);
 
-        let result = detect_diff(&before, &after);
+        let result = detect_diff(&before, &after, &DiffOptions::default());
         assert_eq!(result.total_changes, 0);
         assert!(result.domain_diffs.is_empty());
     }
 
+    #[test]
+    fn test_detect_diff_keys_only() {
+        let before = make_snapshot(vec![(
+            "com.test",
+            vec![
+                ("changed", Value::Integer(1.into())),
+                ("removed", Value::Boolean(true)),
+            ],
+        )]);
+        let after = make_snapshot(vec![(
+            "com.test",
+            vec![
+                ("changed", Value::Integer(2.into())),
+                ("added", Value::Boolean(true)),
+            ],
+        )]);
+        let options = DiffOptions {
+            keys_only: true,
+            ..DiffOptions::default()
+        };
+
+        let result = detect_diff(&before, &after, &options);
+        assert_eq!(result.total_changes, 2);
+        let changes = &result.domain_diffs[0].changes;
+        assert!(matches!(&changes[0], Change::Added { key, .. } if key == "added"));
+        assert!(matches!(&changes[1], Change::Removed { key, .. } if key == "removed"));
+    }
+
+    #[test]
+    fn test_detect_diff_skips_volatile_keys() {
+        let before = make_snapshot(vec![(
+            "com.test",
+            vec![
+                ("LastUsed", Value::Integer(1.into())),
+                ("flag", Value::Boolean(false)),
+            ],
+        )]);
+        let mut after = make_snapshot(vec![(
+            "com.test",
+            vec![
+                ("LastUsed", Value::Integer(2.into())),
+                ("flag", Value::Boolean(true)),
+            ],
+        )]);
+        after.volatile_keys.insert(
+            "com.test".to_string(),
+            ["LastUsed".to_string()].into_iter().collect(),
+        );
+
+        let result = detect_diff(&before, &after, &DiffOptions::default());
+        assert_eq!(result.total_changes, 1);
+        assert_eq!(result.domain_diffs[0].changes[0].key(), "flag");
+    }
+
+    fn whitespace_snapshots() -> (Snapshot, Snapshot) {
+        (
+            make_snapshot(vec![(
+                "com.test",
+                vec![("title", Value::String("Hello  world".to_string()))],
+            )]),
+            make_snapshot(vec![(
+                "com.test",
+                vec![("title", Value::String("Hello world ".to_string()))],
+            )]),
+        )
+    }
+
+    #[test]
+    fn test_whitespace_difference_hidden_when_normalized() {
+        let (before, after) = whitespace_snapshots();
+        let options = DiffOptions {
+            ignore_whitespace: true,
+            ..DiffOptions::default()
+        };
+        assert_eq!(detect_diff(&before, &after, &options).total_changes, 0);
+    }
+
+    #[test]
+    fn test_whitespace_difference_tagged_with_show_normalized() {
+        let (before, after) = whitespace_snapshots();
+        let options = DiffOptions {
+            ignore_whitespace: true,
+            show_normalized: true,
+            ..DiffOptions::default()
+        };
+
+        let result = detect_diff(&before, &after, &options);
+        // Shown, but not counted as a change
+        assert_eq!(result.domain_diffs[0].changes.len(), 1);
+        assert_eq!(result.total_changes, 0);
+        assert!(matches!(
+            &result.domain_diffs[0].changes[0],
+            Change::Normalized {
+                reason: Normalization::Whitespace,
+                ..
+            }
+        ));
+        assert_eq!(result.counts().modified, 0);
+    }
+
+    #[test]
+    fn test_normalization_numeric_and_bool_int() {
+        let options = DiffOptions {
+            numeric_equivalence: true,
+            bool_int_equivalence: true,
+            ..DiffOptions::default()
+        };
+        assert_eq!(
+            normalization(&Value::Integer(2.into()), &Value::Real(2.0), &options),
+            Some(Normalization::Numeric)
+        );
+        assert_eq!(
+            normalization(&Value::Boolean(true), &Value::Integer(1.into()), &options),
+            Some(Normalization::BoolInt)
+        );
+        assert_eq!(
+            normalization(&Value::Boolean(true), &Value::Integer(2.into()), &options),
+            None
+        );
+        assert_eq!(
+            normalization(
+                &Value::Integer(2.into()),
+                &Value::Real(2.0),
+                &DiffOptions::default()
+            ),
+            None
+        );
+    }
+
+    // --- is_factory_default tests ---
+
+    #[test]
+    fn test_factory_default_hidden_under_filter() {
+        let baseline = make_snapshot(vec![(
+            "com.test",
+            vec![("restored", Value::Integer(36.into()))],
+        )]);
+        let before = make_snapshot(vec![(
+            "com.test",
+            vec![
+                ("restored", Value::Integer(64.into())),
+                ("custom", Value::Boolean(false)),
+            ],
+        )]);
+        let after = make_snapshot(vec![(
+            "com.test",
+            vec![
+                ("restored", Value::Integer(36.into())),
+                ("custom", Value::Boolean(true)),
+            ],
+        )]);
+
+        let result = detect_diff(&before, &after, &DiffOptions::default());
+        assert_eq!(result.total_changes, 2);
+
+        let filtered = result.filter_changes(|c| !is_factory_default(c, &baseline));
+        assert_eq!(filtered.total_changes, 1);
+        assert_eq!(filtered.domain_diffs[0].changes[0].key(), "custom");
+    }
+
+    #[test]
+    fn test_factory_default_ignores_removed() {
+        let baseline = make_snapshot(vec![("com.test", vec![("key1", Value::Boolean(true))])]);
+        let change = Change::Removed {
+            domain: "com.test".to_string(),
+            key: "key1".to_string(),
+            old_value: Value::Boolean(true),
+        };
+        assert!(!is_factory_default(&change, &baseline));
+    }
+
     // --- values_equal tests ---
 
+    #[test]
+    fn test_detect_diff_ignore_list() {
+        let before = make_snapshot(vec![
+            ("com.apple.spotlight", vec![("x", Value::Integer(1.into()))]),
+            (
+                "com.test",
+                vec![
+                    ("lastSyncTimestamp", Value::Integer(1.into())),
+                    ("autohide", Value::Boolean(false)),
+                ],
+            ),
+        ]);
+        let after = make_snapshot(vec![
+            ("com.apple.spotlight", vec![("x", Value::Integer(2.into()))]),
+            (
+                "com.test",
+                vec![
+                    ("lastSyncTimestamp", Value::Integer(2.into())),
+                    ("autohide", Value::Boolean(true)),
+                ],
+            ),
+            (
+                "com.apple.spotlight.new",
+                vec![("y", Value::Integer(1.into()))],
+            ),
+        ]);
+        let mut options = DiffOptions {
+            ignore: IgnoreList::parse(
+                "domain = com.apple.spotlight*
+key = *Timestamp
+",
+            ),
+            ..Default::default()
+        };
+
+        let result = detect_diff(&before, &after, &options);
+        assert_eq!(result.total_changes, 1);
+        assert_eq!(result.domain_diffs[0].changes[0].key(), "autohide");
+
+        options.show_ignored = true;
+        assert_eq!(detect_diff(&before, &after, &options).total_changes, 4);
+    }
+
     #[test]
     fn test_values_equal_bool() {
         assert!(values_equal(&Value::Boolean(true), &Value::Boolean(true)));
@@ -262,6 +739,67 @@ mod tests {
     }
 
     #[test]
+    fn test_values_equal_within_epsilon() {
+        assert!(values_equal(
+            &Value::Real(100.0),
+            &Value::Real(100.000_000_1)
+        ));
+        assert!(!values_equal_within(
+            &Value::Real(100.0),
+            &Value::Real(100.000_000_1),
+            f64::EPSILON
+        ));
+        assert!(values_equal_within(
+            &Value::Real(1.0),
+            &Value::Real(1.4),
+            0.5
+        ));
+    }
+
+    #[test]
+    fn test_values_equal_uid() {
+        let uid = |n| Value::Uid(plist::Uid::new(n));
+        assert!(values_equal(&uid(3), &uid(3)));
+        assert!(!values_equal(&uid(3), &uid(4)));
+        assert!(!values_equal(&uid(3), &Value::Integer(3.into())));
+    }
+
+    #[test]
+    fn test_values_equal_date_across_representations() {
+        let date = |s| Value::Date(plist::Date::from_xml_format(s).unwrap());
+        assert!(values_equal(
+            &date("2024-01-01T09:00:00+09:00"),
+            &date("2024-01-01T00:00:00.25Z")
+        ));
+        assert!(!values_equal(
+            &date("2024-01-01T00:00:00Z"),
+            &date("2024-01-01T00:00:01Z")
+        ));
+    }
+
+    #[test]
+    fn test_values_equal_nan() {
+        assert!(values_equal(&Value::Real(f64::NAN), &Value::Real(f64::NAN)));
+        assert!(!values_equal(&Value::Real(f64::NAN), &Value::Real(0.0)));
+    }
+
+    #[test]
+    fn test_detect_diff_uses_float_epsilon() {
+        let before = make_snapshot(vec![("com.test", vec![("x", Value::Real(10.0))])]);
+        let after = make_snapshot(vec![("com.test", vec![("x", Value::Real(10.25))])]);
+        assert_eq!(
+            detect_diff(&before, &after, &DiffOptions::default()).total_changes,
+            1
+        );
+        let loose = DiffOptions {
+            float_epsilon: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(detect_diff(&before, &after, &loose).total_changes, 0);
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)] // 3.14 is just a float, not pi
     fn test_values_equal_real() {
         assert!(values_equal(&Value::Real(3.14), &Value::Real(3.14)));
         assert!(!values_equal(&Value::Real(1.0), &Value::Real(2.0)));
@@ -318,4 +856,250 @@ mod tests {
             &Value::Integer(1.into())
         ));
     }
+
+    #[test]
+    fn test_detect_diff_skips_identical_exports() {
+        // Values differ, but matching export hashes mean the domain is never compared
+        let mut before = make_snapshot(vec![("com.test", vec![("k", Value::Boolean(true))])]);
+        let mut after = make_snapshot(vec![("com.test", vec![("k", Value::Boolean(false))])]);
+        before.export_hashes.insert("com.test".to_string(), 42);
+        after.export_hashes.insert("com.test".to_string(), 42);
+        assert_eq!(
+            detect_diff(&before, &after, &DiffOptions::default()).total_changes,
+            0
+        );
+
+        after.export_hashes.insert("com.test".to_string(), 43);
+        assert_eq!(
+            detect_diff(&before, &after, &DiffOptions::default()).total_changes,
+            1
+        );
+    }
+
+    #[test]
+    fn test_detect_diff_keeps_export_key_order() {
+        let before = make_snapshot(vec![(
+            "com.test",
+            vec![("gone", Value::Boolean(true)), ("b", Value::Boolean(true))],
+        )]);
+        let after = make_snapshot(vec![(
+            "com.test",
+            vec![
+                ("zeta", Value::Boolean(true)),
+                ("b", Value::Boolean(false)),
+                ("alpha", Value::Boolean(true)),
+            ],
+        )]);
+
+        let result = detect_diff(&before, &after, &DiffOptions::default());
+        let keys: Vec<&str> = result.domain_diffs[0]
+            .changes
+            .iter()
+            .map(|c| c.key())
+            .collect();
+        assert_eq!(keys, vec!["zeta", "b", "alpha", "gone"]);
+    }
+
+    fn dict(entries: Vec<(&str, Value)>) -> Value {
+        Value::Dictionary(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_detect_diff_nested_dict_reports_leaf_paths() {
+        let before = make_snapshot(vec![(
+            "com.test",
+            vec![(
+                "windowSettings",
+                dict(vec![
+                    ("width", Value::Integer(800.into())),
+                    ("height", Value::Integer(600.into())),
+                    ("frame", dict(vec![("x", Value::Integer(0.into()))])),
+                ]),
+            )],
+        )]);
+        let after = make_snapshot(vec![(
+            "com.test",
+            vec![(
+                "windowSettings",
+                dict(vec![
+                    ("width", Value::Integer(1024.into())),
+                    ("height", Value::Integer(600.into())),
+                    (
+                        "frame",
+                        dict(vec![
+                            ("x", Value::Integer(0.into())),
+                            ("y", Value::Integer(5.into())),
+                        ]),
+                    ),
+                ]),
+            )],
+        )]);
+
+        let result = detect_diff(&before, &after, &DiffOptions::default());
+        let changes = &result.domain_diffs[0].changes;
+        let keys: Vec<&str> = changes.iter().map(|c| c.key()).collect();
+        assert_eq!(keys, vec!["windowSettings.width", "windowSettings.frame.y"]);
+
+        match &changes[0] {
+            Change::Nested {
+                root_key,
+                old_value,
+                new_value,
+                new_root,
+                ..
+            } => {
+                assert_eq!(root_key, "windowSettings");
+                assert_eq!(old_value, &Some(Value::Integer(800.into())));
+                assert_eq!(new_value, &Some(Value::Integer(1024.into())));
+                assert_eq!(
+                    new_root.as_ref(),
+                    &after.domains["com.test"].values["windowSettings"]
+                );
+            }
+            other => panic!("Expected Nested change, got {:?}", other),
+        }
+        assert!(matches!(
+            &changes[1],
+            Change::Nested {
+                old_value: None,
+                new_value: Some(_),
+                ..
+            }
+        ));
+        // Leaves of one top-level key share its copied roots
+        match (&changes[0], &changes[1]) {
+            (
+                Change::Nested {
+                    old_root: a_old,
+                    new_root: a_new,
+                    ..
+                },
+                Change::Nested {
+                    old_root: b_old,
+                    new_root: b_new,
+                    ..
+                },
+            ) => assert!(Arc::ptr_eq(a_old, b_old) && Arc::ptr_eq(a_new, b_new)),
+            other => panic!("Expected Nested changes, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_detect_diff_nested_array_inside_dict() {
+        let items = |names: &[&str]| {
+            Value::Array(names.iter().map(|n| Value::String(n.to_string())).collect())
+        };
+        let before = make_snapshot(vec![(
+            "com.test",
+            vec![(
+                "prefs",
+                dict(vec![
+                    ("items", items(&["a"])),
+                    ("gone", Value::Boolean(true)),
+                ]),
+            )],
+        )]);
+        let after = make_snapshot(vec![(
+            "com.test",
+            vec![("prefs", dict(vec![("items", items(&["a", "b"]))]))],
+        )]);
+
+        let result = detect_diff(&before, &after, &DiffOptions::default());
+        let changes = &result.domain_diffs[0].changes;
+        let keys: Vec<&str> = changes.iter().map(|c| c.key()).collect();
+        assert_eq!(keys, vec!["prefs.items[1]", "prefs.gone"]);
+        assert!(matches!(
+            &changes[0],
+            Change::Nested { old_value: None, new_value: Some(Value::String(s)), .. } if s == "b"
+        ));
+        assert!(matches!(
+            &changes[1],
+            Change::Nested {
+                old_value: Some(_),
+                new_value: None,
+                ..
+            }
+        ));
+    }
+
+    fn strings(items: &[&str]) -> Vec<Value> {
+        items.iter().map(|s| Value::String(s.to_string())).collect()
+    }
+
+    #[test]
+    fn test_diff_arrays_positional_change() {
+        let before = strings(&["x", "y", "a", "z"]);
+        let after = strings(&["x", "y", "b", "z"]);
+        let changes = diff_arrays(
+            "com.test",
+            "recentItems",
+            &before,
+            &after,
+            &DiffOptions::default(),
+        );
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].key(), "recentItems[2]");
+        assert_eq!(changes[0].root_key(), "recentItems");
+        assert_eq!(
+            crate::diff::format::format_change(&changes[0], crate::diff::format::Detail::Full),
+            r#"recentItems[2]: "a" → "b""#
+        );
+    }
+
+    #[test]
+    fn test_diff_arrays_tail_additions_and_removals() {
+        let grown = diff_arrays(
+            "com.test",
+            "items",
+            &strings(&["a"]),
+            &strings(&["a", "b", "c"]),
+            &DiffOptions::default(),
+        );
+        let keys: Vec<&str> = grown.iter().map(|c| c.key()).collect();
+        assert_eq!(keys, vec!["items[1]", "items[2]"]);
+        assert!(grown.iter().all(|c| matches!(
+            c,
+            Change::Nested {
+                old_value: None,
+                ..
+            }
+        )));
+
+        let shrunk = diff_arrays(
+            "com.test",
+            "items",
+            &strings(&["a", "b"]),
+            &strings(&["a"]),
+            &DiffOptions::default(),
+        );
+        assert_eq!(shrunk.len(), 1);
+        assert!(matches!(
+            &shrunk[0],
+            Change::Nested {
+                new_value: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_detect_diff_top_level_array_uses_element_changes() {
+        let before = make_snapshot(vec![(
+            "com.test",
+            vec![("list", Value::Array(strings(&["a", "b"])))],
+        )]);
+        let after = make_snapshot(vec![(
+            "com.test",
+            vec![("list", Value::Array(strings(&["a", "c"])))],
+        )]);
+        let result = detect_diff(&before, &after, &DiffOptions::default());
+        assert_eq!(result.total_changes, 1);
+        assert_eq!(result.domain_diffs[0].changes[0].key(), "list[1]");
+    }
 }
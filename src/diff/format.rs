@@ -0,0 +1,380 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use plist::{Value, XmlWriteOptions};
+
+use super::types::Change;
+
+/// How much of a value to show when formatting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Detail {
+    /// Truncated strings and rounded floats, for list rows
+    Compact,
+    /// Complete values, for reports and patches
+    Full,
+}
+
+/// Format a change as `key: value` (or `key: old → new`)
+pub fn format_change(change: &Change, detail: Detail) -> String {
+    change_parts(change, detail)
+        .into_iter()
+        .map(|(text, _)| text)
+        .collect()
+}
+
+/// The pieces `format_change` joins, each value paired with the plist value it
+/// shows, so the UI can style values by type
+pub fn change_parts(change: &Change, detail: Detail) -> Vec<(String, Option<&Value>)> {
+    let value = |v| value_part(v, detail);
+    let arrow = || (" → ".to_string(), None);
+    match change {
+        Change::Added { key, value: v, .. }
+        | Change::Removed {
+            key, old_value: v, ..
+        } => vec![(format!("{}: ", key), None), value(v)],
+        Change::Modified {
+            key,
+            old_value,
+            new_value,
+            ..
+        } => vec![
+            (format!("{}: ", key), None),
+            value(old_value),
+            arrow(),
+            value(new_value),
+        ],
+        Change::Normalized {
+            key,
+            old_value,
+            new_value,
+            reason,
+            ..
+        } => vec![
+            (format!("{}: ", key), None),
+            value(old_value),
+            arrow(),
+            value(new_value),
+            (format!(" [normalized away: {}]", reason.label()), None),
+        ],
+        Change::Nested {
+            key,
+            old_value,
+            new_value,
+            ..
+        } => match (old_value, new_value) {
+            (None, Some(v)) | (Some(v), None) => vec![(format!("{}: ", key), None), value(v)],
+            (old, new) => vec![
+                (format!("{}: ", key), None),
+                (format_optional_value(old.as_ref(), detail), old.as_ref()),
+                arrow(),
+                (format_optional_value(new.as_ref(), detail), new.as_ref()),
+            ],
+        },
+    }
+}
+
+fn value_part(value: &Value, detail: Detail) -> (String, Option<&Value>) {
+    (format_value(value, detail), Some(value))
+}
+
+/// Format a value that may be missing on one side of a nested change
+fn format_optional_value(value: Option<&Value>, detail: Detail) -> String {
+    value.map_or_else(|| "(none)".to_string(), |v| format_value(v, detail))
+}
+
+/// Format a change as a human summary: `domain key = new (was old)`
+pub fn format_summary(change: &Change) -> String {
+    match change {
+        Change::Added { domain, key, value } => {
+            format!(
+                "{} {} = {} (new)",
+                domain,
+                key,
+                format_value(value, Detail::Full)
+            )
+        }
+        Change::Removed {
+            domain,
+            key,
+            old_value,
+        } => format!(
+            "{} {} removed (was {})",
+            domain,
+            key,
+            format_value(old_value, Detail::Full)
+        ),
+        Change::Modified {
+            domain,
+            key,
+            old_value,
+            new_value,
+        } => format!(
+            "{} {} = {} (was {})",
+            domain,
+            key,
+            format_value(new_value, Detail::Full),
+            format_value(old_value, Detail::Full)
+        ),
+        Change::Normalized {
+            domain,
+            key,
+            old_value,
+            new_value,
+            reason,
+        } => format!(
+            "{} {} = {} (was {}, equal after {} normalization)",
+            domain,
+            key,
+            format_value(new_value, Detail::Full),
+            format_value(old_value, Detail::Full),
+            reason.label()
+        ),
+        Change::Nested {
+            domain,
+            key,
+            old_value,
+            new_value,
+            ..
+        } => match (old_value, new_value) {
+            (None, Some(value)) => format!(
+                "{} {} = {} (new)",
+                domain,
+                key,
+                format_value(value, Detail::Full)
+            ),
+            (Some(value), None) => format!(
+                "{} {} removed (was {})",
+                domain,
+                key,
+                format_value(value, Detail::Full)
+            ),
+            (old, new) => format!(
+                "{} {} = {} (was {})",
+                domain,
+                key,
+                format_optional_value(new.as_ref(), Detail::Full),
+                format_optional_value(old.as_ref(), Detail::Full)
+            ),
+        },
+    }
+}
+
+/// Format a plist value for display
+pub fn format_value(value: &Value, detail: Detail) -> String {
+    match value {
+        Value::Boolean(b) => format!("{}", b),
+        Value::Integer(i) => format_integer(i),
+        Value::Real(f) => match detail {
+            Detail::Compact => format!("{:.2}", f),
+            Detail::Full => format!("{}", f),
+        },
+        Value::String(s) => {
+            if detail == Detail::Compact && s.chars().count() > 30 {
+                format!("\"{}...\"", s.chars().take(27).collect::<String>())
+            } else {
+                format!("\"{}\"", s)
+            }
+        }
+        Value::Data(d) => format!("<data {} bytes>", d.len()),
+        Value::Array(a) => format!("[{} items]", a.len()),
+        Value::Dictionary(d) => format!("{{{}}} keys", d.len()),
+        Value::Date(d) => format_date(d),
+        Value::Uid(u) => format!("UID({})", u.get()),
+        _ => "<unknown>".to_string(),
+    }
+}
+
+/// Whole seconds since the Unix epoch, in UTC.
+///
+/// Captures of the same date can differ in sub-second precision (binary plists
+/// store a float offset from 2001), so comparisons and display use this.
+pub fn date_seconds(date: &plist::Date) -> i64 {
+    let time = SystemTime::from(*date);
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => {
+            let before = before.duration();
+            // Round toward negative infinity so pre-1970 dates truncate like later ones
+            -(before.as_secs() as i64) - i64::from(before.subsec_nanos() > 0)
+        }
+    }
+}
+
+/// ISO 8601 UTC text of a date to the second, e.g. `2024-01-01T00:00:00Z`
+pub fn format_date(date: &plist::Date) -> String {
+    let seconds = date_seconds(date);
+    let time = if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    };
+    plist::Date::from(time).to_xml_format()
+}
+
+/// Decimal text of a plist integer, including values that only fit in `u64`
+pub fn format_integer(i: &plist::Integer) -> String {
+    match (i.as_signed(), i.as_unsigned()) {
+        (Some(signed), _) => signed.to_string(),
+        (None, Some(unsigned)) => unsigned.to_string(),
+        (None, None) => "0".to_string(),
+    }
+}
+
+/// Bytes shown per hex dump row
+const HEX_DUMP_WIDTH: usize = 16;
+
+/// Render a value in full, one entry per line: complete strings, pretty XML for
+/// arrays and dictionaries, and a hex dump for data
+pub fn format_value_lines(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => s.lines().map(str::to_string).collect(),
+        Value::Data(d) => hex_dump(d),
+        Value::Array(_) | Value::Dictionary(_) => {
+            let mut xml = Vec::new();
+            let options = XmlWriteOptions::default()
+                .indent(b' ', 2)
+                .root_element(false);
+            match value.to_writer_xml_with_options(&mut xml, &options) {
+                Ok(()) => String::from_utf8_lossy(&xml)
+                    .lines()
+                    .map(str::to_string)
+                    .collect(),
+                Err(e) => vec![format!("<unrenderable: {}>", e)],
+            }
+        }
+        _ => vec![format_value(value, Detail::Full)],
+    }
+}
+
+/// `offset  hex bytes  |ascii|` rows, like `hexdump -C`
+fn hex_dump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(HEX_DUMP_WIDTH)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:08x}  {:<width$}  |{}|",
+                row * HEX_DUMP_WIDTH,
+                hex.join(" "),
+                ascii,
+                width = HEX_DUMP_WIDTH * 3 - 1
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_summary_modified() {
+        let change = Change::Modified {
+            domain: "com.apple.dock".to_string(),
+            key: "orientation".to_string(),
+            old_value: Value::String("bottom".to_string()),
+            new_value: Value::String("left".to_string()),
+        };
+        assert_eq!(
+            format_summary(&change),
+            r#"com.apple.dock orientation = "left" (was "bottom")"#
+        );
+    }
+
+    #[test]
+    fn test_change_parts_pair_values() {
+        let change = Change::Modified {
+            domain: "com.a".to_string(),
+            key: "on".to_string(),
+            old_value: Value::Boolean(false),
+            new_value: Value::Boolean(true),
+        };
+        let parts = change_parts(&change, Detail::Compact);
+        let texts: Vec<&str> = parts.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(texts, vec!["on: ", "false", " → ", "true"]);
+        assert!(matches!(parts[3].1, Some(Value::Boolean(true))));
+        assert!(parts[2].1.is_none());
+        assert_eq!(format_change(&change, Detail::Compact), "on: false → true");
+    }
+
+    #[test]
+    fn test_format_integer_unsigned() {
+        assert_eq!(format_integer(&(-5).into()), "-5");
+        assert_eq!(format_integer(&u64::MAX.into()), "18446744073709551615");
+    }
+
+    #[test]
+    fn test_format_date_utc_seconds() {
+        let date = |s| plist::Date::from_xml_format(s).unwrap();
+        assert_eq!(
+            format_date(&date("2024-01-01T09:00:00.75+09:00")),
+            "2024-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            format_date(&date("1969-12-31T23:59:59.5Z")),
+            "1969-12-31T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn test_format_value_full_keeps_long_strings() {
+        let long = "x".repeat(40);
+        let value = Value::String(long.clone());
+        assert_eq!(format_value(&value, Detail::Full), format!("\"{}\"", long));
+        assert!(format_value(&value, Detail::Compact).ends_with("...\""));
+    }
+
+    #[test]
+    fn test_format_value_lines_data_hex_dump() {
+        let lines = format_value_lines(&Value::Data(b"hello, world!\n\x00\xffmore".to_vec()));
+        assert_eq!(
+            lines,
+            vec![
+                "00000000  68 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a 00 ff  |hello, world!...|",
+                "00000010  6d 6f 72 65                                      |more|",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_value_lines_pretty_xml() {
+        let mut dict = plist::Dictionary::new();
+        dict.insert("name".to_string(), Value::String("Dock".to_string()));
+        dict.insert(
+            "sizes".to_string(),
+            Value::Array(vec![Value::Integer(1.into())]),
+        );
+        assert_eq!(
+            format_value_lines(&Value::Dictionary(dict)),
+            vec![
+                "<dict>",
+                "  <key>name</key>",
+                "  <string>Dock</string>",
+                "  <key>sizes</key>",
+                "  <array>",
+                "    <integer>1</integer>",
+                "  </array>",
+                "</dict>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_value_lines_full_string() {
+        let long = "y".repeat(80);
+        assert_eq!(
+            format_value_lines(&Value::String(format!("{}\nsecond", long))),
+            vec![long, "second".to_string()]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+/// Patterns shipped when `~/.config/defaults-util/ignore` does not exist
+pub const DEFAULT_IGNORE: &str = "\
+# Domains and keys that change on their own
+domain = com.apple.spotlight
+domain = com.apple.xpc.activity2
+domain = com.apple.knowledge-agent
+domain = com.apple.CallHistorySyncHelper
+domain = com.apple.cloudd
+key = *Timestamp
+key = *LastSync*
+key = NSWindow Frame *
+key = NSStatusItem Preferred Position *
+";
+
+/// Domain and key globs whose changes are left out of the diff
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IgnoreList {
+    pub domains: Vec<String>,
+    pub keys: Vec<String>,
+}
+
+impl IgnoreList {
+    /// Parse `domain = <glob>` and `key = <glob>` lines; anything else is ignored
+    pub fn parse(content: &str) -> Self {
+        let mut list = Self::default();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((kind, pattern)) = line.split_once('=') else {
+                continue;
+            };
+            let pattern = pattern.trim().to_string();
+            if pattern.is_empty() {
+                continue;
+            }
+            match kind.trim() {
+                "domain" => list.domains.push(pattern),
+                "key" => list.keys.push(pattern),
+                _ => {}
+            }
+        }
+
+        list
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty() && self.keys.is_empty()
+    }
+
+    /// Whether every change in a domain is ignored
+    pub fn ignores_domain(&self, domain: &str) -> bool {
+        self.domains.iter().any(|p| glob_match(p, domain))
+    }
+
+    /// Whether changes to a top-level key are ignored
+    pub fn ignores_key(&self, key: &str) -> bool {
+        self.keys.iter().any(|p| glob_match(p, key))
+    }
+}
+
+/// Match `text` against a glob where `*` is any run of characters and `?` is one character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text index it was tried against
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("com.apple.spotlight", "com.apple.spotlight"));
+        assert!(!glob_match("com.apple.spotlight", "com.apple.spotlight2"));
+        assert!(glob_match("com.apple.*", "com.apple.dock"));
+        assert!(glob_match("*Timestamp", "lastSyncTimestamp"));
+        assert!(glob_match("*Sync*", "lastSyncTimestamp"));
+        assert!(glob_match("tile?ize", "tilesize"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("*Timestamp", "TimestampFormat"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+    }
+
+    #[test]
+    fn test_parse_ignore_list() {
+        let list = IgnoreList::parse(
+            "# comment\ndomain = com.apple.spotlight\nkey=*Timestamp\nbogus = x\nkey =\n",
+        );
+        assert_eq!(list.domains, vec!["com.apple.spotlight"]);
+        assert_eq!(list.keys, vec!["*Timestamp"]);
+        assert!(list.ignores_domain("com.apple.spotlight"));
+        assert!(list.ignores_key("lastSyncTimestamp"));
+        assert!(!list.ignores_key("autohide"));
+    }
+
+    #[test]
+    fn test_default_ignore_list_parses() {
+        let list = IgnoreList::parse(DEFAULT_IGNORE);
+        assert!(list.ignores_domain("com.apple.spotlight"));
+        assert!(list.ignores_key("NSWindow Frame Main"));
+        assert!(!list.ignores_domain("com.apple.dock"));
+    }
+}
@@ -1,5 +1,48 @@
+use std::sync::Arc;
+
 use plist::Value as PlistValue;
 
+use crate::defaults::types::{Scope, value_type_label};
+
+/// Why a difference was treated as equal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    /// Strings differ only in whitespace
+    Whitespace,
+    /// Integer and real with the same numeric value
+    Numeric,
+    /// Boolean and integer 0/1 with the same meaning
+    BoolInt,
+}
+
+impl Normalization {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Normalization::Whitespace => "whitespace",
+            Normalization::Numeric => "numeric",
+            Normalization::BoolInt => "bool/int",
+        }
+    }
+}
+
+/// Effect of a change on its key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl ChangeKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+        }
+    }
+}
+
 /// Represents a single change
 #[derive(Debug, Clone)]
 pub enum Change {
@@ -22,6 +65,29 @@ pub enum Change {
         old_value: PlistValue,
         new_value: PlistValue,
     },
+    /// Value differs but is equal after normalization (shown only on request)
+    Normalized {
+        domain: String,
+        key: String,
+        old_value: PlistValue,
+        new_value: PlistValue,
+        reason: Normalization,
+    },
+    /// A value inside a top-level dictionary or array changed.
+    ///
+    /// `key` is the path to the leaf (`windowSettings.width`, `items[3]`) and the
+    /// leaf values are `None` when it was added or removed. The whole top-level
+    /// values are kept because `defaults` can only write `root_key` as a unit;
+    /// the leaves under one `root_key` share them.
+    Nested {
+        domain: String,
+        key: String,
+        root_key: String,
+        old_value: Option<PlistValue>,
+        new_value: Option<PlistValue>,
+        old_root: Arc<PlistValue>,
+        new_root: Arc<PlistValue>,
+    },
 }
 
 impl Change {
@@ -30,6 +96,76 @@ impl Change {
             Change::Added { key, .. } => key,
             Change::Removed { key, .. } => key,
             Change::Modified { key, .. } => key,
+            Change::Normalized { key, .. } => key,
+            Change::Nested { key, .. } => key,
+        }
+    }
+
+    /// Top-level key in the domain (differs from `key` for nested changes)
+    pub fn root_key(&self) -> &str {
+        match self {
+            Change::Nested { root_key, .. } => root_key,
+            _ => self.key(),
+        }
+    }
+
+    pub fn domain(&self) -> &str {
+        match self {
+            Change::Added { domain, .. } => domain,
+            Change::Removed { domain, .. } => domain,
+            Change::Modified { domain, .. } => domain,
+            Change::Normalized { domain, .. } => domain,
+            Change::Nested { domain, .. } => domain,
+        }
+    }
+
+    /// Whether the change adds, removes or modifies a value (None when normalized away)
+    pub fn kind(&self) -> Option<ChangeKind> {
+        match self {
+            Change::Added { .. } => Some(ChangeKind::Added),
+            Change::Removed { .. } => Some(ChangeKind::Removed),
+            Change::Modified { .. } => Some(ChangeKind::Modified),
+            Change::Normalized { .. } => None,
+            Change::Nested {
+                old_value: None, ..
+            } => Some(ChangeKind::Added),
+            Change::Nested {
+                new_value: None, ..
+            } => Some(ChangeKind::Removed),
+            Change::Nested { .. } => Some(ChangeKind::Modified),
+        }
+    }
+
+    /// Value before the change (None for additions)
+    pub fn old_value(&self) -> Option<&PlistValue> {
+        match self {
+            Change::Added { .. } => None,
+            Change::Removed { old_value, .. } => Some(old_value),
+            Change::Modified { old_value, .. } => Some(old_value),
+            Change::Normalized { old_value, .. } => Some(old_value),
+            Change::Nested { old_value, .. } => old_value.as_ref(),
+        }
+    }
+
+    /// Old and new type labels when a modification changed the value's type
+    /// (`int` to `bool`), which apps reading the key may not expect
+    pub fn type_change(&self) -> Option<(&'static str, &'static str)> {
+        if self.kind() != Some(ChangeKind::Modified) {
+            return None;
+        }
+        let old = value_type_label(self.old_value()?);
+        let new = value_type_label(self.new_value()?);
+        (old != new).then_some((old, new))
+    }
+
+    /// Value after the change (None for removals)
+    pub fn new_value(&self) -> Option<&PlistValue> {
+        match self {
+            Change::Added { value, .. } => Some(value),
+            Change::Removed { .. } => None,
+            Change::Modified { new_value, .. } => Some(new_value),
+            Change::Normalized { new_value, .. } => Some(new_value),
+            Change::Nested { new_value, .. } => new_value.as_ref(),
         }
     }
 }
@@ -39,11 +175,151 @@ impl Change {
 pub struct DomainDiff {
     pub domain: String,
     pub changes: Vec<Change>,
+    /// Scope the domain was captured at, deciding whether commands need sudo
+    pub scope: Scope,
+}
+
+impl DomainDiff {
+    /// Changes that need a command; normalized differences don't count
+    pub fn change_count(&self) -> usize {
+        self.changes.iter().filter(|c| c.kind().is_some()).count()
+    }
+
+    /// Every change is a removal (typically an uninstalled app)
+    pub fn is_removed_only(&self) -> bool {
+        !self.changes.is_empty()
+            && self
+                .changes
+                .iter()
+                .all(|c| matches!(c, Change::Removed { .. }))
+    }
+}
+
+/// Number of changes of each kind (normalized entries are not counted)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChangeCounts {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
 }
 
 /// Overall diff result
 #[derive(Debug, Clone)]
 pub struct DiffResult {
     pub domain_diffs: Vec<DomainDiff>,
+    /// Number of changes, not counting normalized ones (see `DomainDiff::change_count`)
     pub total_changes: usize,
 }
+
+impl DiffResult {
+    /// Tally changes by kind
+    pub fn counts(&self) -> ChangeCounts {
+        let mut counts = ChangeCounts::default();
+        for change in self.domain_diffs.iter().flat_map(|d| &d.changes) {
+            match change.kind() {
+                Some(ChangeKind::Added) => counts.added += 1,
+                Some(ChangeKind::Removed) => counts.removed += 1,
+                Some(ChangeKind::Modified) => counts.modified += 1,
+                None => {}
+            }
+        }
+        counts
+    }
+
+    /// Keep only domains matching the predicate
+    pub fn filter_domains(&self, keep: impl Fn(&DomainDiff) -> bool) -> DiffResult {
+        let domain_diffs: Vec<DomainDiff> = self
+            .domain_diffs
+            .iter()
+            .filter(|d| keep(d))
+            .cloned()
+            .collect();
+        let total_changes = domain_diffs.iter().map(DomainDiff::change_count).sum();
+
+        DiffResult {
+            domain_diffs,
+            total_changes,
+        }
+    }
+
+    /// Keep only changes matching the predicate, dropping domains left empty
+    pub fn filter_changes(&self, mut keep: impl FnMut(&Change) -> bool) -> DiffResult {
+        let domain_diffs: Vec<DomainDiff> = self
+            .domain_diffs
+            .iter()
+            .map(|domain_diff| DomainDiff {
+                domain: domain_diff.domain.clone(),
+                changes: domain_diff
+                    .changes
+                    .iter()
+                    .filter(|c| keep(c))
+                    .cloned()
+                    .collect(),
+                scope: domain_diff.scope,
+            })
+            .filter(|domain_diff| !domain_diff.changes.is_empty())
+            .collect();
+        let total_changes = domain_diffs.iter().map(DomainDiff::change_count).sum();
+
+        DiffResult {
+            domain_diffs,
+            total_changes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn removed(key: &str) -> Change {
+        Change::Removed {
+            domain: "com.test".to_string(),
+            key: key.to_string(),
+            old_value: PlistValue::Boolean(true),
+        }
+    }
+
+    #[test]
+    fn test_type_change() {
+        let modified = |old_value, new_value| Change::Modified {
+            domain: "com.test".to_string(),
+            key: "k".to_string(),
+            old_value,
+            new_value,
+        };
+        assert_eq!(
+            modified(PlistValue::Integer(1.into()), PlistValue::Boolean(true)).type_change(),
+            Some(("int", "bool"))
+        );
+        assert_eq!(
+            modified(PlistValue::Integer(1.into()), PlistValue::Integer(2.into())).type_change(),
+            None
+        );
+        assert_eq!(removed("a").type_change(), None);
+    }
+
+    #[test]
+    fn test_is_removed_only() {
+        let removed_only = DomainDiff {
+            domain: "com.test".to_string(),
+            changes: vec![removed("a"), removed("b")],
+            scope: Scope::User,
+        };
+        assert!(removed_only.is_removed_only());
+
+        let mixed = DomainDiff {
+            domain: "com.test".to_string(),
+            changes: vec![
+                removed("a"),
+                Change::Added {
+                    domain: "com.test".to_string(),
+                    key: "b".to_string(),
+                    value: PlistValue::Boolean(false),
+                },
+            ],
+            scope: Scope::User,
+        };
+        assert!(!mixed.is_removed_only());
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Why reading defaults failed, kept structured so the UI can suggest a fix.
+///
+/// Reader functions still return `anyhow::Result`; callers recover the cause
+/// with `downcast_ref::<AppError>()`.
+#[derive(Debug)]
+pub enum AppError {
+    /// A `defaults` (or `sudo`) command exited with a non-zero status
+    DefaultsCommand {
+        command: String,
+        status: Option<i32>,
+        stderr: String,
+    },
+    /// A command did not finish in time and was killed
+    Timeout { command: String, after: Duration },
+    /// A domain's export was not a readable plist
+    Parse { domain: String, message: String },
+    /// The capture found no domains to read, or could read none of them
+    NoDomains { failed: usize },
+    /// Spawning or waiting on a process failed
+    Io(io::Error),
+}
+
+impl AppError {
+    /// What the user can try next
+    pub fn hint(&self) -> &'static str {
+        match self {
+            AppError::DefaultsCommand { .. } => {
+                "Check that the domain exists and that this terminal may read it (Full Disk Access)."
+            }
+            AppError::Timeout { .. } => {
+                "The system may be busy; retry, or raise the limit with --timeout <SECONDS>."
+            }
+            AppError::Parse { .. } => "The domain's file may be corrupt; try `plutil -lint` on it.",
+            AppError::NoDomains { .. } => {
+                "This is a permissions or environment problem, not \"no changes\": check that this terminal has Full Disk Access and runs as the user whose settings you want."
+            }
+            AppError::Io(_) => "Make sure the `defaults` command is available (macOS only).",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::DefaultsCommand {
+                command,
+                status,
+                stderr,
+            } => {
+                write!(f, "`{}` failed", command)?;
+                if let Some(code) = status {
+                    write!(f, " with exit code {}", code)?;
+                }
+                if !stderr.trim().is_empty() {
+                    write!(f, ": {}", stderr.trim())?;
+                }
+                Ok(())
+            }
+            AppError::Timeout { command, after } => {
+                write!(f, "`{}` timed out after {:?}", command, after)
+            }
+            AppError::Parse { domain, message } => {
+                write!(f, "Could not parse domain '{}': {}", domain, message)
+            }
+            AppError::NoDomains { failed: 0 } => {
+                write!(f, "`defaults domains` listed no preference domains")
+            }
+            AppError::NoDomains { failed } => {
+                write!(f, "none of the {} preference domains could be read", failed)
+            }
+            AppError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(e: io::Error) -> Self {
+        AppError::Io(e)
+    }
+}
+
+/// Error screen text for a failed capture, with a hint when the cause is known
+pub fn describe_capture_error(error: &anyhow::Error) -> String {
+    match error.downcast_ref::<AppError>() {
+        Some(cause) => format!("Failed to capture snapshot: {}\n{}", cause, cause.hint()),
+        None => format!("Failed to capture snapshot: {}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_defaults_command() {
+        let err = AppError::DefaultsCommand {
+            command: "defaults export com.x -".to_string(),
+            status: Some(1),
+            stderr: "Domain com.x does not exist\n".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "`defaults export com.x -` failed with exit code 1: Domain com.x does not exist"
+        );
+    }
+
+    #[test]
+    fn test_describe_capture_error_adds_hint() {
+        let err = anyhow::Error::new(AppError::Timeout {
+            command: "defaults domains".to_string(),
+            after: Duration::from_secs(10),
+        });
+        let text = describe_capture_error(&err);
+        assert!(
+            text.starts_with(
+                "Failed to capture snapshot: `defaults domains` timed out after 10s\n"
+            )
+        );
+        assert!(
+            text.ends_with(
+                AppError::Timeout {
+                    command: String::new(),
+                    after: Duration::ZERO,
+                }
+                .hint()
+            )
+        );
+
+        let plain = describe_capture_error(&anyhow::anyhow!("boom"));
+        assert_eq!(plain, "Failed to capture snapshot: boom");
+    }
+
+    #[test]
+    fn test_display_no_domains() {
+        assert_eq!(
+            AppError::NoDomains { failed: 0 }.to_string(),
+            "`defaults domains` listed no preference domains"
+        );
+        assert_eq!(
+            AppError::NoDomains { failed: 3 }.to_string(),
+            "none of the 3 preference domains could be read"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+pub mod ansible;
+pub mod bundle;
+pub mod markdown;
+pub mod nix;
+pub mod patch;
+
+pub use ansible::export_ansible;
+pub use bundle::write_domain_bundle;
+pub use markdown::export_markdown;
+pub use nix::{export_nix, export_nix_change};
+pub use patch::export_diff_patch;
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Get the user's home directory
+pub fn home_dir() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Turn a domain into a safe file name.
+///
+/// Path-style domains (`/Library/Preferences/...`) contain separators, so `/`,
+/// `\`, `%`, `:`, spaces, and control characters are percent-encoded.
+pub fn sanitize_domain_filename(domain: &str) -> String {
+    let mut out = String::with_capacity(domain.len());
+    for c in domain.chars() {
+        match c {
+            '/' | '\\' | '%' | ':' | ' ' => out.push_str(&format!("%{:02X}", c as u32)),
+            c if c.is_control() => out.push_str(&format!("%{:02X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Seconds since the Unix epoch, used to make export names unique
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_plain_domain() {
+        assert_eq!(sanitize_domain_filename("com.apple.dock"), "com.apple.dock");
+    }
+
+    #[test]
+    fn test_sanitize_path_domain() {
+        assert_eq!(
+            sanitize_domain_filename("/Library/Preferences/com.apple.loginwindow"),
+            "%2FLibrary%2FPreferences%2Fcom.apple.loginwindow"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_domain_with_spaces() {
+        assert_eq!(
+            sanitize_domain_filename("Apple Global Domain"),
+            "Apple%20Global%20Domain"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_is_unambiguous() {
+        assert_ne!(
+            sanitize_domain_filename("a/b"),
+            sanitize_domain_filename("a%2Fb")
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashSet;
+
+use plist::Value;
+
+use crate::defaults::parser::ROOT_KEY;
+use crate::diff::{Change, DiffResult};
+
+/// Render the diff as Ansible `community.general.osx_defaults` tasks
+pub fn export_ansible(diff: &DiffResult) -> String {
+    let mut out = String::new();
+
+    for domain_diff in &diff.domain_diffs {
+        // Nested changes under one top-level key share its new value, so set it once
+        let mut set_keys = HashSet::new();
+        for change in &domain_diff.changes {
+            let (domain, key) = (change.domain(), change.root_key());
+            let value = match change {
+                Change::Normalized { .. } => continue,
+                _ if !set_keys.insert(key) => continue,
+                // The whole value of a domain that isn't a dictionary has no key to write
+                _ if key == ROOT_KEY => {
+                    out.push_str(&format!(
+                        "# Skipped {}: the domain is not a dictionary, so osx_defaults cannot write it\n",
+                        domain
+                    ));
+                    continue;
+                }
+                Change::Removed { .. } => {
+                    push_task_header(&mut out, "Remove", domain, key);
+                    out.push_str("    state: absent\n");
+                    continue;
+                }
+                Change::Nested { new_root, .. } => new_root.as_ref(),
+                _ => change
+                    .new_value()
+                    .expect("added and modified changes have a value"),
+            };
+            match ansible_value(value) {
+                Some((ty, rendered)) => {
+                    push_task_header(&mut out, "Set", domain, key);
+                    out.push_str(&format!("    type: {}\n", ty));
+                    out.push_str(&format!("    value: {}\n", rendered));
+                    out.push_str("    state: present\n");
+                }
+                None => out.push_str(&format!(
+                    "# Skipped {} {}: nested values are not supported by osx_defaults\n",
+                    domain, key
+                )),
+            }
+        }
+    }
+
+    out
+}
+
+fn push_task_header(out: &mut String, verb: &str, domain: &str, key: &str) {
+    out.push_str(&format!(
+        "- name: {}\n",
+        yaml_quote(&format!("{} {} {}", verb, domain, key))
+    ));
+    out.push_str("  community.general.osx_defaults:\n");
+    out.push_str(&format!("    domain: {}\n", yaml_quote(domain)));
+    out.push_str(&format!("    key: {}\n", yaml_quote(key)));
+}
+
+/// Map a plist value to the module's `type` field and a YAML value.
+/// Returns `None` for types osx_defaults cannot write.
+fn ansible_value(value: &Value) -> Option<(&'static str, String)> {
+    match value {
+        Value::Boolean(b) => Some(("bool", b.to_string())),
+        Value::Integer(i) => Some(("int", i.to_string())),
+        Value::Real(f) => Some(("float", f.to_string())),
+        Value::String(s) => Some(("string", yaml_quote(s))),
+        Value::Date(d) => Some(("date", yaml_quote(&d.to_xml_format()))),
+        Value::Array(arr) => {
+            let items = arr
+                .iter()
+                .map(|item| match item {
+                    Value::Array(_) | Value::Dictionary(_) | Value::Data(_) => None,
+                    _ => ansible_value(item).map(|(_, rendered)| rendered),
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(("array", format!("[{}]", items.join(", "))))
+        }
+        _ => None,
+    }
+}
+
+/// Double-quote a YAML scalar, escaping backslashes and quotes
+fn yaml_quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defaults::types::Scope;
+    use crate::diff::types::DomainDiff;
+    use std::sync::Arc;
+
+    fn diff_of(changes: Vec<Change>) -> DiffResult {
+        let total_changes = changes.len();
+        DiffResult {
+            domain_diffs: vec![DomainDiff {
+                domain: "com.apple.dock".to_string(),
+                changes,
+                scope: Scope::User,
+            }],
+            total_changes,
+        }
+    }
+
+    #[test]
+    fn test_export_ansible_bool() {
+        let diff = diff_of(vec![Change::Modified {
+            domain: "com.apple.dock".to_string(),
+            key: "autohide".to_string(),
+            old_value: Value::Boolean(false),
+            new_value: Value::Boolean(true),
+        }]);
+        assert_eq!(
+            export_ansible(&diff),
+            "- name: \"Set com.apple.dock autohide\"\n\
+             \x20 community.general.osx_defaults:\n\
+             \x20   domain: \"com.apple.dock\"\n\
+             \x20   key: \"autohide\"\n\
+             \x20   type: bool\n\
+             \x20   value: true\n\
+             \x20   state: present\n"
+        );
+    }
+
+    #[test]
+    fn test_export_ansible_string_is_quoted() {
+        let diff = diff_of(vec![Change::Added {
+            domain: "com.apple.dock".to_string(),
+            key: "orientation".to_string(),
+            value: Value::String("say \"left\"".to_string()),
+        }]);
+        let yaml = export_ansible(&diff);
+        assert!(yaml.contains("    type: string\n"));
+        assert!(yaml.contains("    value: \"say \\\"left\\\"\"\n"));
+    }
+
+    #[test]
+    fn test_export_ansible_removed_is_absent() {
+        let diff = diff_of(vec![Change::Removed {
+            domain: "com.apple.dock".to_string(),
+            key: "tilesize".to_string(),
+            old_value: Value::Integer(36.into()),
+        }]);
+        let yaml = export_ansible(&diff);
+        assert!(yaml.starts_with("- name: \"Remove com.apple.dock tilesize\"\n"));
+        assert!(yaml.contains("    state: absent\n"));
+        assert!(!yaml.contains("type:"));
+    }
+
+    #[test]
+    fn test_export_ansible_skips_dict() {
+        let diff = diff_of(vec![Change::Added {
+            domain: "com.apple.dock".to_string(),
+            key: "persistent".to_string(),
+            value: Value::Dictionary(plist::Dictionary::new()),
+        }]);
+        assert_eq!(
+            export_ansible(&diff),
+            "# Skipped com.apple.dock persistent: nested values are not supported by osx_defaults\n"
+        );
+    }
+
+    #[test]
+    fn test_export_ansible_sets_nested_array_once() {
+        let ints = |items: &[i64]| {
+            Arc::new(Value::Array(
+                items.iter().map(|&i| Value::Integer(i.into())).collect(),
+            ))
+        };
+        let (old_root, new_root) = (ints(&[1]), ints(&[1, 2, 3]));
+        let diff = diff_of(
+            ["persistent[1]", "persistent[2]"]
+                .into_iter()
+                .map(|key| Change::Nested {
+                    domain: "com.apple.dock".to_string(),
+                    key: key.to_string(),
+                    root_key: "persistent".to_string(),
+                    old_value: None,
+                    new_value: Some(Value::Integer(2.into())),
+                    old_root: Arc::clone(&old_root),
+                    new_root: Arc::clone(&new_root),
+                })
+                .collect(),
+        );
+        let yaml = export_ansible(&diff);
+        assert_eq!(yaml.matches("- name:").count(), 1);
+        assert!(yaml.contains("    value: [1, 2, 3]\n"));
+    }
+
+    #[test]
+    fn test_export_ansible_skips_array_rooted_domain() {
+        let strings = |items: &[&str]| {
+            Arc::new(Value::Array(
+                items.iter().map(|s| Value::String(s.to_string())).collect(),
+            ))
+        };
+        let diff = diff_of(vec![Change::Nested {
+            domain: "com.apple.dock".to_string(),
+            key: format!("{}[1]", ROOT_KEY),
+            root_key: ROOT_KEY.to_string(),
+            old_value: None,
+            new_value: Some(Value::String("b".to_string())),
+            old_root: strings(&["a"]),
+            new_root: strings(&["a", "b"]),
+        }]);
+        assert_eq!(
+            export_ansible(&diff),
+            "# Skipped com.apple.dock: the domain is not a dictionary, so osx_defaults cannot write it\n"
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::command::generator::{CommandOptions, domain_commands, restart_command_for_domain};
+use crate::diff::DiffResult;
+use crate::export::sanitize_domain_filename;
+
+/// Write one shell script per domain into `dir`.
+///
+/// `on_progress` is called after each file is written with `(written, total, path)`.
+pub fn write_domain_bundle(
+    diff: &DiffResult,
+    dir: &Path,
+    options: &CommandOptions,
+    mut on_progress: impl FnMut(usize, usize, &Path),
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+
+    let total = diff.domain_diffs.len();
+    let mut written = Vec::with_capacity(total);
+
+    for domain_diff in &diff.domain_diffs {
+        let mut script = String::from("#!/bin/bash\n\n");
+        for (_, command) in domain_commands(domain_diff, options) {
+            script.push_str(&command);
+            script.push('\n');
+        }
+        if let Some(restart) = restart_command_for_domain(&domain_diff.domain) {
+            script.push_str(&restart);
+            script.push('\n');
+        }
+
+        let path = dir.join(format!(
+            "{}.sh",
+            sanitize_domain_filename(&domain_diff.domain)
+        ));
+        fs::write(&path, script)?;
+        written.push(path);
+        on_progress(written.len(), total, &written[written.len() - 1]);
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defaults::types::Scope;
+    use crate::diff::Change;
+    use crate::diff::types::DomainDiff;
+    use plist::Value;
+
+    fn make_diff(domains: &[&str]) -> DiffResult {
+        let domain_diffs: Vec<DomainDiff> = domains
+            .iter()
+            .map(|domain| DomainDiff {
+                domain: domain.to_string(),
+                changes: vec![Change::Added {
+                    domain: domain.to_string(),
+                    key: "key".to_string(),
+                    value: Value::Boolean(true),
+                }],
+                scope: Scope::User,
+            })
+            .collect();
+        DiffResult {
+            total_changes: domain_diffs.len(),
+            domain_diffs,
+        }
+    }
+
+    #[test]
+    fn test_write_domain_bundle_progress_per_file() {
+        let dir = std::env::temp_dir().join(format!("defaults-util-bundle-{}", std::process::id()));
+        let diff = make_diff(&[
+            "com.example.a",
+            "com.example.b",
+            "/Library/Preferences/com.example.c",
+        ]);
+
+        let mut calls = Vec::new();
+        let written =
+            write_domain_bundle(&diff, &dir, &CommandOptions::default(), |n, total, _| {
+                calls.push((n, total))
+            })
+            .unwrap();
+
+        assert_eq!(written.len(), 3);
+        assert_eq!(calls, vec![(1, 3), (2, 3), (3, 3)]);
+
+        let script = fs::read_to_string(dir.join("com.example.b.sh")).unwrap();
+        assert!(script.contains(r#"defaults write "com.example.b" "key" -bool true"#));
+        assert!(
+            dir.join("%2FLibrary%2FPreferences%2Fcom.example.c.sh")
+                .exists()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use plist::Value;
+
+use crate::diff::format::{Detail, format_value};
+use crate::diff::{Change, DiffResult};
+
+/// Render the diff as a Markdown report with one table per domain
+pub fn export_markdown(diff: &DiffResult) -> String {
+    let mut out = String::from("# defaults changes\n");
+
+    for domain_diff in &diff.domain_diffs {
+        out.push_str(&format!("\n## {}\n\n", domain_diff.domain));
+        out.push_str("| Key | Change | Old | New |\n");
+        out.push_str("| --- | --- | --- | --- |\n");
+        for change in &domain_diff.changes {
+            out.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                table_cell(change.key()),
+                change_label(change),
+                report_value(change.old_value()),
+                report_value(change.new_value())
+            ));
+        }
+    }
+
+    out
+}
+
+fn change_label(change: &Change) -> String {
+    if let Some((old, new)) = change.type_change() {
+        return format!("modified ({} → {})", old, new);
+    }
+    match (change, change.kind()) {
+        (Change::Normalized { reason, .. }, _) => format!("normalized ({})", reason.label()),
+        (_, Some(kind)) => kind.label().to_string(),
+        (_, None) => String::new(),
+    }
+}
+
+/// Full value as a table cell, empty when the key has no value on that side
+fn report_value(value: Option<&Value>) -> String {
+    value
+        .map(|v| table_cell(&format!("`{}`", format_value(v, Detail::Full))))
+        .unwrap_or_default()
+}
+
+/// Escape text so it stays inside one table cell
+fn table_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defaults::types::Scope;
+    use crate::diff::types::DomainDiff;
+
+    #[test]
+    fn test_export_markdown_table() {
+        let long = "a string value that is definitely longer than thirty chars";
+        let diff = DiffResult {
+            domain_diffs: vec![DomainDiff {
+                domain: "com.apple.dock".to_string(),
+                changes: vec![
+                    Change::Modified {
+                        domain: "com.apple.dock".to_string(),
+                        key: "tilesize".to_string(),
+                        old_value: Value::Integer(36.into()),
+                        new_value: Value::Integer(48.into()),
+                    },
+                    Change::Added {
+                        domain: "com.apple.dock".to_string(),
+                        key: "a|b".to_string(),
+                        value: Value::String(long.to_string()),
+                    },
+                ],
+                scope: Scope::User,
+            }],
+            total_changes: 2,
+        };
+
+        assert_eq!(
+            export_markdown(&diff),
+            format!(
+                "# defaults changes\n\n\
+                 ## com.apple.dock\n\n\
+                 | Key | Change | Old | New |\n\
+                 | --- | --- | --- | --- |\n\
+                 | tilesize | modified | `36` | `48` |\n\
+                 | a\\|b | added |  | `\"{}\"` |\n",
+                long
+            )
+        );
+    }
+
+    #[test]
+    fn test_change_label_type_change() {
+        let change = Change::Modified {
+            domain: "com.a".to_string(),
+            key: "k".to_string(),
+            old_value: Value::Integer(1.into()),
+            new_value: Value::Boolean(true),
+        };
+        assert_eq!(change_label(&change), "modified (int → bool)");
+    }
+}
@@ -0,0 +1,302 @@
+use plist::Value;
+
+use crate::defaults::parser::ROOT_KEY;
+use crate::defaults::types::Scope;
+use crate::diff::{Change, DiffResult};
+
+/// Render the diff as a nix-darwin `system.defaults.CustomUserPreferences`
+/// fragment, with system-scoped domains under `CustomSystemPreferences`
+pub fn export_nix(diff: &DiffResult) -> String {
+    nix_fragment(
+        diff.domain_diffs
+            .iter()
+            .flat_map(|d| d.changes.iter().map(|change| (change, d.scope))),
+    )
+}
+
+/// Render one change of a domain captured at `scope` as a nix-darwin fragment
+pub fn export_nix_change(change: &Change, scope: Scope) -> String {
+    nix_fragment(std::iter::once((change, scope)))
+}
+
+/// One attribute set per domain, in the order the changes arrive
+fn nix_fragment<'a>(changes: impl Iterator<Item = (&'a Change, Scope)>) -> String {
+    // Nested changes under the same key share one new root value, so each key is set once
+    let mut domains: Vec<NixDomain> = Vec::new();
+    for (change, scope) in changes {
+        let setting = match change {
+            Change::Normalized { .. } => continue,
+            Change::Removed { .. } => Setting::Removed,
+            _ if change.root_key() == ROOT_KEY => Setting::Unsupported,
+            Change::Nested { new_root, .. } => {
+                nix_value(new_root, 2).map_or(Setting::Unsupported, Setting::Value)
+            }
+            _ => change
+                .new_value()
+                .and_then(|v| nix_value(v, 2))
+                .map_or(Setting::Unsupported, Setting::Value),
+        };
+        let domain = change.domain();
+        if domains.last().is_none_or(|(last, _, _)| *last != domain) {
+            domains.push((domain, scope, Vec::new()));
+        }
+        let settings = &mut domains.last_mut().expect("pushed above").2;
+        let key = change.root_key();
+        match settings.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = setting,
+            None => settings.push((key, setting)),
+        }
+    }
+
+    let (system, user): (Vec<_>, Vec<_>) = domains
+        .into_iter()
+        .partition(|(_, scope, _)| *scope == Scope::System);
+    let mut out = String::new();
+    // A fragment with nothing to set is still an empty user set
+    if !user.is_empty() || system.is_empty() {
+        push_preferences(&mut out, "CustomUserPreferences", user);
+    }
+    if !system.is_empty() {
+        push_preferences(&mut out, "CustomSystemPreferences", system);
+    }
+    out
+}
+
+/// `system.defaults.<option> = { ... };` for the given domains
+fn push_preferences(out: &mut String, option: &str, domains: Vec<NixDomain>) {
+    out.push_str(&format!("system.defaults.{} = {{\n", option));
+    for (domain, _, settings) in domains {
+        out.push_str(&format!("  {} = {{\n", nix_attr_name(domain)));
+        for (key, setting) in settings {
+            match setting {
+                Setting::Value(value) => {
+                    out.push_str(&format!("    {} = {};\n", nix_attr_name(key), value))
+                }
+                Setting::Removed => out.push_str(&format!(
+                    "    # {} was removed; nix-darwin cannot delete keys\n",
+                    key
+                )),
+                Setting::Unsupported => out.push_str(&format!(
+                    "    # Skipped {}: the value has no Nix equivalent nix-darwin can write\n",
+                    key
+                )),
+            }
+        }
+        out.push_str("  };\n");
+    }
+    out.push_str("};\n");
+}
+
+/// A domain, its scope and what each of its top-level keys is set to
+type NixDomain<'a> = (&'a str, Scope, Vec<(&'a str, Setting)>);
+
+/// What a key is set to in the fragment
+enum Setting {
+    Value(String),
+    Removed,
+    Unsupported,
+}
+
+/// A plist value as a Nix expression, with nested lines indented past `depth`
+/// levels. Returns `None` for dates, data, UIDs and non-finite reals, which
+/// nix-darwin cannot write.
+fn nix_value(value: &Value, depth: usize) -> Option<String> {
+    let indent = "  ".repeat(depth + 1);
+    let closing = "  ".repeat(depth);
+    match value {
+        Value::Boolean(b) => Some(b.to_string()),
+        Value::Integer(i) => i
+            .as_signed()
+            .map(|i| i.to_string())
+            .or(i.as_unsigned().map(|u| u.to_string())),
+        Value::Real(f) if f.is_finite() => {
+            // `2` would be an integer in Nix
+            let text = f.to_string();
+            Some(if text.contains('.') {
+                text
+            } else {
+                format!("{}.0", text)
+            })
+        }
+        Value::String(s) => Some(nix_string(s)),
+        Value::Array(arr) if arr.is_empty() => Some("[ ]".to_string()),
+        Value::Array(arr) => {
+            let items = arr
+                .iter()
+                .map(|item| nix_value(item, depth + 1).map(|v| format!("{}{}\n", indent, v)))
+                .collect::<Option<String>>()?;
+            Some(format!("[\n{}{}]", items, closing))
+        }
+        Value::Dictionary(dict) if dict.is_empty() => Some("{ }".to_string()),
+        Value::Dictionary(dict) => {
+            let entries = dict
+                .iter()
+                .map(|(k, v)| {
+                    nix_value(v, depth + 1)
+                        .map(|v| format!("{}{} = {};\n", indent, nix_attr_name(k), v))
+                })
+                .collect::<Option<String>>()?;
+            Some(format!("{{\n{}{}}}", entries, closing))
+        }
+        _ => None,
+    }
+}
+
+/// Words that cannot be bare attribute names
+const NIX_KEYWORDS: &[&str] = &[
+    "assert", "else", "if", "in", "inherit", "let", "or", "rec", "then", "with",
+];
+
+/// A bare attribute name when Nix allows it, otherwise a quoted one
+fn nix_attr_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let bare = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-'));
+    if bare && !NIX_KEYWORDS.contains(&name) {
+        name.to_string()
+    } else {
+        nix_string(name)
+    }
+}
+
+/// Double-quote a Nix string, escaping `"`, `\`, `${` and control characters
+fn nix_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::types::DomainDiff;
+
+    #[test]
+    fn test_export_nix() {
+        let mut window = plist::Dictionary::new();
+        window.insert("width".to_string(), Value::Integer(1024.into()));
+        let changes = vec![
+            Change::Modified {
+                domain: "com.apple.dock".to_string(),
+                key: "autohide".to_string(),
+                old_value: Value::Boolean(false),
+                new_value: Value::Boolean(true),
+            },
+            Change::Added {
+                domain: "com.apple.dock".to_string(),
+                key: "tile-size".to_string(),
+                value: Value::Real(48.0),
+            },
+            Change::Added {
+                domain: "com.apple.dock".to_string(),
+                key: "persistent apps".to_string(),
+                value: Value::Array(vec![Value::String("a${b}".to_string())]),
+            },
+            Change::Nested {
+                domain: "com.apple.dock".to_string(),
+                key: "window.width".to_string(),
+                root_key: "window".to_string(),
+                old_value: None,
+                new_value: Some(Value::Integer(1024.into())),
+                old_root: Value::Dictionary(plist::Dictionary::new()).into(),
+                new_root: Value::Dictionary(window).into(),
+            },
+            Change::Removed {
+                domain: "com.apple.dock".to_string(),
+                key: "old".to_string(),
+                old_value: Value::Integer(1.into()),
+            },
+        ];
+        let diff = DiffResult {
+            total_changes: changes.len(),
+            domain_diffs: vec![DomainDiff {
+                domain: "com.apple.dock".to_string(),
+                changes,
+                scope: Scope::User,
+            }],
+        };
+        assert_eq!(
+            export_nix(&diff),
+            "system.defaults.CustomUserPreferences = {\n\
+             \x20 \"com.apple.dock\" = {\n\
+             \x20   autohide = true;\n\
+             \x20   tile-size = 48.0;\n\
+             \x20   \"persistent apps\" = [\n\
+             \x20     \"a\\${b}\"\n\
+             \x20   ];\n\
+             \x20   window = {\n\
+             \x20     width = 1024;\n\
+             \x20   };\n\
+             \x20   # old was removed; nix-darwin cannot delete keys\n\
+             \x20 };\n\
+             };\n"
+        );
+    }
+
+    #[test]
+    fn test_export_nix_change_skips_data() {
+        let change = Change::Added {
+            domain: "NSGlobalDomain".to_string(),
+            key: "blob".to_string(),
+            value: Value::Data(vec![1, 2]),
+        };
+        assert!(
+            export_nix_change(&change, Scope::User).contains(
+                "  NSGlobalDomain = {\n    # Skipped blob: the value has no Nix equivalent"
+            )
+        );
+    }
+
+    #[test]
+    fn test_export_nix_system_domains() {
+        let added = |domain: &str| Change::Added {
+            domain: domain.to_string(),
+            key: "on".to_string(),
+            value: Value::Boolean(true),
+        };
+        let domain_diff = |domain: &str, scope| DomainDiff {
+            domain: domain.to_string(),
+            changes: vec![added(domain)],
+            scope,
+        };
+        let diff = DiffResult {
+            domain_diffs: vec![
+                domain_diff("com.apple.loginwindow", Scope::System),
+                domain_diff("com.apple.dock", Scope::User),
+            ],
+            total_changes: 2,
+        };
+        assert_eq!(
+            export_nix(&diff),
+            "system.defaults.CustomUserPreferences = {\n\
+             \x20 \"com.apple.dock\" = {\n\
+             \x20   on = true;\n\
+             \x20 };\n\
+             };\n\
+             system.defaults.CustomSystemPreferences = {\n\
+             \x20 \"com.apple.loginwindow\" = {\n\
+             \x20   on = true;\n\
+             \x20 };\n\
+             };\n"
+        );
+        assert!(
+            export_nix_change(&added("com.apple.loginwindow"), Scope::System)
+                .starts_with("system.defaults.CustomSystemPreferences = {\n")
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::diff::format::{Detail, format_change};
+use crate::diff::{Change, DiffResult};
+
+/// Render the diff as a unified-diff-style patch for pasting into reviews
+pub fn export_diff_patch(diff: &DiffResult) -> String {
+    let mut out = String::new();
+
+    for domain_diff in &diff.domain_diffs {
+        out.push_str(&format!("@@ {} @@\n", domain_diff.domain));
+        for change in &domain_diff.changes {
+            let prefix = match change {
+                Change::Added { .. } => '+',
+                Change::Removed { .. } => '-',
+                Change::Modified { .. } => '~',
+                Change::Normalized { .. } => '=',
+                Change::Nested {
+                    old_value: None, ..
+                } => '+',
+                Change::Nested {
+                    new_value: None, ..
+                } => '-',
+                Change::Nested { .. } => '~',
+            };
+            out.push_str(&format!(
+                "{} {}\n",
+                prefix,
+                format_change(change, Detail::Full)
+            ));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defaults::types::Scope;
+    use crate::diff::types::DomainDiff;
+    use plist::Value;
+
+    #[test]
+    fn test_export_diff_patch_mixed() {
+        let long = "a string value that is definitely longer than thirty chars";
+        let diff = DiffResult {
+            domain_diffs: vec![
+                DomainDiff {
+                    domain: "com.apple.dock".to_string(),
+                    changes: vec![
+                        Change::Modified {
+                            domain: "com.apple.dock".to_string(),
+                            key: "tilesize".to_string(),
+                            old_value: Value::Integer(36.into()),
+                            new_value: Value::Integer(48.into()),
+                        },
+                        Change::Removed {
+                            domain: "com.apple.dock".to_string(),
+                            key: "autohide".to_string(),
+                            old_value: Value::Boolean(true),
+                        },
+                    ],
+                    scope: Scope::User,
+                },
+                DomainDiff {
+                    domain: "com.example".to_string(),
+                    changes: vec![Change::Added {
+                        domain: "com.example".to_string(),
+                        key: "note".to_string(),
+                        value: Value::String(long.to_string()),
+                    }],
+                    scope: Scope::User,
+                },
+            ],
+            total_changes: 3,
+        };
+
+        let expected = format!(
+            "@@ com.apple.dock @@\n\
+             ~ tilesize: 36 → 48\n\
+             - autohide: true\n\
+             @@ com.example @@\n\
+             + note: \"{}\"\n",
+            long
+        );
+        assert_eq!(export_diff_patch(&diff), expected);
+    }
+}
@@ -0,0 +1,149 @@
+//! Library half of `defaults-util`: the binary calls `run`, and the
+//! benchmarks reach the diff code through `bench`
+
+mod app;
+mod cli;
+mod command;
+mod config;
+mod defaults;
+mod diff;
+mod error;
+mod export;
+mod ui;
+
+use std::io;
+
+use clap::Parser;
+use crossterm::{
+    cursor,
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+};
+use ratatui::{Terminal, backend::CrosstermBackend};
+
+use app::App;
+use defaults::Snapshot;
+use ui::{handle_input, render};
+
+/// Parse the command line, then run a headless subcommand or the TUI
+pub fn run() -> anyhow::Result<()> {
+    let cli = cli::Cli::parse();
+    let capture_options = cli.capture_options();
+    let command_options = cli.command_options();
+    // Load `compare` snapshots before touching the terminal, so a bad path
+    // is reported like any other CLI error
+    let snapshots = match cli.command {
+        Some(cli::Commands::Compare { before, after }) => Some(cli::load_pair(&before, &after)?),
+        Some(command) => return cli::run(command, capture_options, command_options),
+        None => None,
+    };
+
+    // Restore the terminal before the panic message is printed, so a panic
+    // mid-render doesn't leave the shell in raw mode on the alternate screen
+    let original_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        let _ = restore_terminal();
+        original_hook(panic_info);
+    }));
+
+    // Initialize terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    // Run application
+    let result = run_app(
+        &mut terminal,
+        cli.inspect,
+        capture_options,
+        command_options,
+        snapshots,
+    );
+
+    restore_terminal()?;
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+    }
+
+    Ok(())
+}
+
+/// Leave raw mode and the alternate screen, and show the cursor again
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        cursor::Show
+    )
+}
+
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    read_only: bool,
+    capture_options: defaults::CaptureOptions,
+    command_options: command::generator::CommandOptions,
+    snapshots: Option<(Snapshot, Snapshot)>,
+) -> anyhow::Result<()> {
+    let mut app = App::new();
+    app.read_only = read_only;
+    app.capture_options = capture_options;
+    app.command_options = command_options;
+    app.config = config::Config::load();
+    app.diff_options = app.config.diff_options.clone();
+    app.theme = app.config.theme;
+    app.load_factory_baseline(&config::factory_baseline_path());
+    if let Some((before, after)) = snapshots {
+        app.load_snapshots(before, after);
+    }
+
+    loop {
+        // Draw screen
+        terminal.draw(|f| render(f, &mut app))?;
+
+        // Start a watch-mode capture when one is due
+        app.tick_watch();
+
+        // Check whether a background capture finished
+        if app.capture_job.is_some() {
+            app.poll_capture();
+        }
+
+        // If an export is queued, run it after "Exporting..." is drawn
+        if app.pending_export.is_some() {
+            app.execute_export();
+            continue;
+        }
+
+        // Hand the terminal to the editor, then take it back
+        if let Some(path) = app.pending_edit.take() {
+            restore_terminal()?;
+            let result = command::apply::edit_file(&path);
+            enable_raw_mode()?;
+            execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+            terminal.clear()?;
+            app.finish_edit(&path, result);
+            continue;
+        }
+
+        // Handle user input
+        if handle_input(&mut app)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// What the criterion benchmarks use; not a supported API
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::defaults::Snapshot;
+    pub use crate::defaults::types::DomainSettings;
+    pub use crate::diff::{DiffOptions, detect_diff};
+}
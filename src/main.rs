@@ -1,74 +1,3 @@
-mod app;
-mod command;
-mod defaults;
-mod diff;
-mod ui;
-
-use std::io;
-
-use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
-    execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
-};
-use ratatui::{Terminal, backend::CrosstermBackend};
-
-use app::App;
-use ui::{handle_input, render};
-
 fn main() -> anyhow::Result<()> {
-    // Initialize terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    // Set panic hook to restore terminal on panic
-    let original_hook = std::panic::take_hook();
-    std::panic::set_hook(Box::new(move |panic_info| {
-        let _ = crossterm::terminal::disable_raw_mode();
-        let _ = crossterm::execute!(std::io::stderr(), crossterm::terminal::LeaveAlternateScreen);
-        original_hook(panic_info);
-    }));
-
-    // Run application
-    let result = run_app(&mut terminal);
-
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-
-    if let Err(e) = result {
-        eprintln!("Error: {}", e);
-    }
-
-    Ok(())
-}
-
-fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> anyhow::Result<()> {
-    let mut app = App::new();
-
-    loop {
-        // Draw screen
-        terminal.draw(|f| render(f, &mut app))?;
-
-        // If loading, execute capture after screen draw
-        if app.is_loading() {
-            app.execute_capture();
-            continue;
-        }
-
-        // Handle user input
-        if handle_input(&mut app)? {
-            break;
-        }
-    }
-
-    Ok(())
+    defaults_util::run()
 }
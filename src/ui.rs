@@ -1,5 +1,6 @@
 pub mod input;
 pub mod render;
+pub mod theme;
 
 pub use input::handle_input;
 pub use render::render;
@@ -1,65 +1,483 @@
 use std::io;
 use std::process::Command;
 
-use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 
-use crate::app::{App, Focus, Screen, StatusMessage};
-use crate::command::generator::generate_command;
+use crate::app::{App, ExportAction, Focus, Screen, StatusMessage};
+use crate::command::generator::{
+    base64_encode, generate_structured, restart_command_for_domain, with_restart,
+};
+use crate::command::script::collect_commands;
+use crate::config::saved_snapshot_path;
+use crate::diff::types::ChangeKind;
+use crate::export::{export_ansible, export_diff_patch, export_nix, export_nix_change};
 
 pub fn handle_input(app: &mut App) -> io::Result<bool> {
-    if event::poll(std::time::Duration::from_millis(100))?
-        && let Event::Key(key) = event::read()?
-    {
+    if !event::poll(std::time::Duration::from_millis(100))? {
+        return Ok(app.should_quit);
+    }
+    let key = match event::read()? {
+        Event::Key(key) => key,
+        Event::Mouse(mouse) => {
+            handle_mouse(app, mouse);
+            return Ok(app.should_quit);
+        }
+        _ => return Ok(app.should_quit),
+    };
+    Ok(handle_key(app, key))
+}
+
+/// Apply one keystroke, returning whether the app should quit
+fn handle_key(app: &mut App, key: KeyEvent) -> bool {
+    // Any key closes the help, stats and failed-domains overlays
+    if app.show_help || app.show_stats || app.show_failed_domains {
+        app.show_help = false;
+        app.show_stats = false;
+        app.show_failed_domains = false;
+        return app.should_quit;
+    }
+
+    if app.pending_apply.is_some() {
         match key.code {
-            // Quit
-            KeyCode::Char('q') => {
-                app.should_quit = true;
-            }
-            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                app.should_quit = true;
-            }
+            KeyCode::Char('y') => app.confirm_apply(),
+            _ => app.cancel_apply(),
+        }
+        return app.should_quit;
+    }
 
-            // Reset
-            KeyCode::Char('r') => {
-                app.reset();
-            }
+    if app.confirm_quit {
+        match key.code {
+            KeyCode::Char('y') => app.should_quit = true,
+            _ => app.cancel_quit(),
+        }
+        return app.should_quit;
+    }
 
-            // Enter: Capture snapshot
-            KeyCode::Enter => {
-                handle_enter(app);
-            }
+    if app.value_popup.is_some() {
+        handle_value_popup_input(app, key.code);
+        return app.should_quit;
+    }
 
-            // Navigation
-            KeyCode::Up | KeyCode::Char('k') => {
-                app.move_up();
-            }
-            KeyCode::Down | KeyCode::Char('j') => {
-                app.move_down();
-            }
+    if app.all_keys_domain.is_some() {
+        handle_all_keys_input(app, key.code);
+        return app.should_quit;
+    }
+
+    if app.screen == Screen::History {
+        handle_history_input(app, key);
+        return app.should_quit;
+    }
 
-            // Esc: Quit
+    if app.input_mode.is_some() {
+        handle_text_input(app, key);
+        return app.should_quit;
+    }
+
+    // After `'`, characters jump in the domain list until the keyboard goes idle
+    if app.type_ahead_active() {
+        match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.type_ahead(c);
+                return app.should_quit;
+            }
             KeyCode::Esc => {
-                app.should_quit = true;
+                app.stop_type_ahead();
+                return app.should_quit;
             }
+            _ => app.stop_type_ahead(),
+        }
+    }
 
-            // Toggle focus
-            KeyCode::Tab
-            | KeyCode::Char('h')
-            | KeyCode::Char('l')
-            | KeyCode::Left
-            | KeyCode::Right => {
-                app.toggle_focus();
-            }
+    match key.code {
+        // Quit
+        KeyCode::Char('q') => {
+            app.request_quit();
+        }
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.should_quit = true;
+        }
+
+        // Keybinding help
+        KeyCode::Char('?') => {
+            app.show_help = true;
+        }
 
-            // Copy (only when focused on Changes pane)
-            KeyCode::Char('y') => {
-                handle_copy(app);
+        // Re-capture the second snapshot, keeping the selection
+        KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.refresh_second_snapshot();
+        }
+
+        // Reset
+        KeyCode::Char('r') => {
+            app.reset();
+        }
+
+        // Save the "before" snapshot / load a saved one as the "before"
+        KeyCode::Char('S') => {
+            app.save_before_snapshot(&saved_snapshot_path());
+        }
+        KeyCode::Char('L') => {
+            app.load_before_snapshot(&saved_snapshot_path());
+        }
+
+        // Diff any two captures from this session
+        KeyCode::Char('b') => {
+            app.open_history();
+        }
+
+        // Enter: Capture snapshot
+        KeyCode::Enter => {
+            handle_enter(app);
+        }
+
+        // Navigation
+        KeyCode::Up | KeyCode::Char('k') => {
+            app.move_up();
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            app.move_down();
+        }
+        KeyCode::PageUp => {
+            app.page_up();
+        }
+        KeyCode::PageDown => {
+            app.page_down();
+        }
+        KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.page_up();
+        }
+        KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.page_down();
+        }
+        KeyCode::Home | KeyCode::Char('g') => {
+            app.move_to_top();
+        }
+        KeyCode::End | KeyCode::Char('G') => {
+            app.move_to_bottom();
+        }
+
+        // Esc: Cancel capture, clear the filter, otherwise quit
+        KeyCode::Esc => {
+            if app.is_loading() {
+                app.cancel_capture();
+            } else if app.filter.is_some() {
+                app.clear_filter();
+            } else {
+                app.request_quit();
             }
+        }
+
+        // Re-capture periodically and update the diff
+        KeyCode::Char('w') => {
+            app.toggle_watch();
+        }
+
+        // Scope captures to a single domain
+        KeyCode::Char('O') if app.screen == Screen::Initial => {
+            app.start_domain_scope();
+        }
+
+        // Cycle the order of changes within each domain
+        KeyCode::Char('O') => {
+            app.cycle_change_sort();
+        }
+
+        // Filter domains and changes by substring
+        KeyCode::Char('/') => {
+            app.start_filter();
+        }
+
+        // Jump to a domain by fuzzy name
+        KeyCode::Char(':') => {
+            app.start_jump();
+        }
+
+        // Type-ahead jump in the domain list
+        KeyCode::Char('\'') => {
+            app.start_type_ahead();
+        }
+
+        // Toggle focus
+        KeyCode::Tab | KeyCode::Char('h') | KeyCode::Char('l') | KeyCode::Left | KeyCode::Right => {
+            app.toggle_focus();
+        }
+
+        // Toggle preview between command and value summary
+        KeyCode::Char('p') => {
+            app.toggle_preview_mode();
+        }
+
+        // Toggle -currentHost commands
+        KeyCode::Char('H') => {
+            app.toggle_command_host();
+        }
+
+        // Cycle the color theme
+        KeyCode::Char('m') => {
+            app.cycle_theme();
+        }
+
+        // Toggle single/double-quoted arguments
+        KeyCode::Char('Q') => {
+            app.toggle_quote_style();
+        }
+
+        // Open the selected domain's plist file in $EDITOR
+        KeyCode::Char('e') => {
+            app.open_in_editor();
+        }
+
+        // Toggle plutil commands for nested changes
+        KeyCode::Char('U') => {
+            app.toggle_plutil();
+        }
+
+        // Toggle a verification read after each write
+        KeyCode::Char('v') => {
+            app.toggle_verify();
+        }
+
+        // Toggle delta comments for numeric changes
+        KeyCode::Char('d') => {
+            app.toggle_delta();
+        }
+
+        // Hide changes that restore factory defaults
+        KeyCode::Char('F') => {
+            app.toggle_factory_defaults();
+        }
+
+        // Detect keys that change merely by being read
+        KeyCode::Char('V') => {
+            app.toggle_volatile_detection();
+        }
+
+        // Every key of the selected domain, changed or not
+        KeyCode::Char('I') => {
+            app.toggle_all_keys();
+        }
+
+        // Include/exclude Apple background service domains
+        KeyCode::Char('x') => {
+            app.toggle_apple_services();
+        }
+
+        // Cycle capture of managed preference domains
+        KeyCode::Char('M') => {
+            app.cycle_managed_preferences();
+        }
+
+        // Snapshot statistics
+        KeyCode::Char('T') => {
+            app.toggle_stats();
+        }
+
+        // Domains the latest capture skipped
+        KeyCode::Char('f') => {
+            app.toggle_failed_domains();
+        }
+
+        // Diff keys only (ignore value changes)
+        KeyCode::Char('K') => {
+            app.toggle_keys_only();
+        }
+
+        // Show differences that normalization treats as equal
+        KeyCode::Char('N') => {
+            app.toggle_show_normalized();
+        }
+
+        // Show changes the ignore list hides
+        KeyCode::Char('i') => {
+            app.toggle_show_ignored();
+        }
+
+        // Show only added / removed / modified keys (again for all)
+        KeyCode::Char('+') => {
+            app.toggle_change_type_filter(ChangeKind::Added);
+        }
+        KeyCode::Char('-') => {
+            app.toggle_change_type_filter(ChangeKind::Removed);
+        }
+        KeyCode::Char('~') => {
+            app.toggle_change_type_filter(ChangeKind::Modified);
+        }
+
+        // Cycle the domain list order
+        KeyCode::Char('o') => {
+            app.cycle_sort_mode();
+        }
+
+        // Group domains by reverse-DNS prefix / expand or collapse a group
+        KeyCode::Char('t') => {
+            app.toggle_domain_grouping();
+        }
+        KeyCode::Char(' ') if app.focus == Focus::Domain => {
+            app.toggle_group();
+        }
+
+        // Hide domains that only have removed keys
+        KeyCode::Char('X') => {
+            app.toggle_removed_only();
+        }
+
+        // Export one script per domain
+        KeyCode::Char('E') => {
+            app.start_export(ExportAction::DomainBundle);
+        }
+
+        // Save the whole diff as one shell script
+        KeyCode::Char('s') => {
+            app.start_export(ExportAction::Script);
+        }
+
+        // Save the diff as a Markdown report
+        KeyCode::Char('R') => {
+            app.start_export(ExportAction::Report);
+        }
+
+        // Copy all commands / toggle per-domain header comments
+        KeyCode::Char('C') => {
+            handle_copy_all(app);
+        }
+        KeyCode::Char('#') => {
+            app.toggle_domain_headers();
+        }
+
+        // Copy every command for the selected domain
+        KeyCode::Char('Y') => {
+            handle_copy_domain_commands(app);
+        }
+
+        // Copy the selected domain name
+        KeyCode::Char('D') => {
+            handle_copy_domain(app);
+        }
+
+        // Copy the whole diff as a patch
+        KeyCode::Char('P') => {
+            handle_copy_patch(app);
+        }
+
+        // Copy the whole diff as Ansible osx_defaults tasks
+        KeyCode::Char('A') => {
+            handle_copy_ansible(app);
+        }
 
-            _ => {}
+        // Copy (only when focused on Changes pane)
+        KeyCode::Char('y') => {
+            handle_copy(app);
         }
+
+        // Run the selected change's command after confirmation
+        KeyCode::Char('a') if app.focus == Focus::Diff => {
+            app.request_apply();
+        }
+
+        // Copy the command that reverts the selected change
+        KeyCode::Char('u') => {
+            handle_copy_undo(app);
+        }
+
+        // Copy the write and its restart as one line
+        KeyCode::Char('W') => {
+            handle_copy_with_restart(app);
+        }
+
+        // Copy the selected change as a JSON object
+        KeyCode::Char('J') => {
+            handle_copy_structured(app);
+        }
+
+        // Copy the selected change / the whole diff as a nix-darwin fragment
+        KeyCode::Char('n') => {
+            handle_copy_nix_change(app);
+        }
+        KeyCode::Char('Z') => {
+            handle_copy_nix(app);
+        }
+
+        _ => {}
+    }
+    app.should_quit
+}
+
+/// Clicks select a row in the diff view lists; the wheel moves the selection
+fn handle_mouse(app: &mut App, mouse: MouseEvent) {
+    // Popups and the input line take keyboard input only
+    if app.show_help
+        || app.show_stats
+        || app.show_failed_domains
+        || app.pending_apply.is_some()
+        || app.confirm_quit
+        || app.value_popup.is_some()
+        || app.all_keys_domain.is_some()
+        || app.input_mode.is_some()
+    {
+        return;
+    }
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => app.click(mouse.column, mouse.row),
+        MouseEventKind::ScrollUp => app.scroll_at(mouse.column, mouse.row, -1),
+        MouseEventKind::ScrollDown => app.scroll_at(mouse.column, mouse.row, 1),
+        _ => {}
+    }
+}
+
+/// Keystrokes while the value popup is open
+fn handle_value_popup_input(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Up | KeyCode::Char('k') => app.scroll_value_popup(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.scroll_value_popup(1),
+        KeyCode::PageUp => app.scroll_value_popup(-(app.page_size as i32)),
+        KeyCode::PageDown => app.scroll_value_popup(app.page_size as i32),
+        KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => app.close_value_popup(),
+        _ => {}
+    }
+}
+
+/// Keystrokes while the all-keys popup is open
+fn handle_all_keys_input(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Up | KeyCode::Char('k') => app.scroll_all_keys(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.scroll_all_keys(1),
+        KeyCode::PageUp => app.scroll_all_keys(-(app.page_size as i32)),
+        KeyCode::PageDown => app.scroll_all_keys(app.page_size as i32),
+        KeyCode::Esc | KeyCode::Char('I') | KeyCode::Char('q') => app.toggle_all_keys(),
+        _ => {}
+    }
+}
+
+/// Keystrokes on the history screen
+fn handle_history_input(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.should_quit = true;
+        }
+        KeyCode::Up | KeyCode::Char('k') => app.move_history(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.move_history(1),
+        KeyCode::Home | KeyCode::Char('g') => app.move_history(isize::MIN / 2),
+        KeyCode::End | KeyCode::Char('G') => app.move_history(isize::MAX / 2),
+        KeyCode::Enter | KeyCode::Char(' ') => app.pick_history_entry(),
+        KeyCode::Esc | KeyCode::Char('b') | KeyCode::Char('q') => app.close_history(),
+        _ => {}
+    }
+}
+
+/// Keystrokes while the text input line is open
+fn handle_text_input(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.should_quit = true;
+        }
+        KeyCode::Esc => app.cancel_input(),
+        KeyCode::Enter => app.submit_input(),
+        KeyCode::Backspace => app.pop_input_char(),
+        KeyCode::Char(c) => app.push_input_char(c),
+        _ => {}
     }
-    Ok(app.should_quit)
 }
 
 fn handle_enter(app: &mut App) {
@@ -67,9 +485,15 @@ fn handle_enter(app: &mut App) {
         Screen::Initial => {
             app.start_first_snapshot();
         }
-        Screen::WaitingForChanges => {
+        Screen::WaitingForChanges | Screen::NoChanges => {
             app.start_second_snapshot();
         }
+        Screen::DiffView if app.focus == Focus::Domain => {
+            app.toggle_group();
+        }
+        Screen::DiffView => {
+            app.open_value_popup();
+        }
         Screen::Error(_) => {
             app.reset();
         }
@@ -79,29 +503,300 @@ fn handle_enter(app: &mut App) {
 
 fn handle_copy(app: &mut App) {
     // Copy only when focused on Changes pane in DiffView
+    if app.screen == Screen::DiffView
+        && app.focus == Focus::Diff
+        && let Some(cmd) = app.selected_command_with_verify()
+    {
+        let cmd = with_restart_hint(app, cmd);
+        copy_with_status(app, &cmd, "✓ Command copied to clipboard");
+    }
+}
+
+fn handle_copy_with_restart(app: &mut App) {
+    if app.screen != Screen::DiffView || app.focus != Focus::Diff {
+        return;
+    }
+    let (Some(cmd), Some(domain)) = (app.selected_command(), app.selected_domain()) else {
+        return;
+    };
+    match with_restart(&cmd, domain) {
+        Some(line) => copy_with_status(app, &line, "✓ Command and restart copied to clipboard"),
+        None if restart_command_for_domain(domain).is_some() => {
+            app.status = Some(StatusMessage::warning(
+                "Nothing to write for this change".to_string(),
+            ));
+        }
+        None => {
+            app.status = Some(StatusMessage::warning(format!(
+                "No app to restart for {}; use y to copy the command",
+                domain
+            )));
+        }
+    }
+}
+
+fn handle_copy_structured(app: &mut App) {
     if app.screen == Screen::DiffView
         && app.focus == Focus::Diff
         && let Some(change) = app.selected_change()
     {
-        let cmd = generate_command(change);
-
-        // Use macOS pbcopy to copy to clipboard
-        if let Ok(mut child) = Command::new("pbcopy")
-            .stdin(std::process::Stdio::piped())
-            .spawn()
-        {
-            if let Some(mut stdin) = child.stdin.take() {
-                use std::io::Write;
-                let _ = stdin.write_all(cmd.as_bytes());
-            }
-            // stdin is dropped here, so pbcopy receives EOF
-            if child.wait().is_ok() {
-                app.set_status(StatusMessage::success("✓ Command copied to clipboard"));
-            } else {
-                app.set_status(StatusMessage::warning("Failed to copy to clipboard"));
-            }
-        } else {
-            app.set_status(StatusMessage::warning("Failed to copy to clipboard"));
+        let json = generate_structured(change);
+        copy_with_status(app, &json, "✓ Change copied as JSON");
+    }
+}
+
+fn handle_copy_nix_change(app: &mut App) {
+    if app.screen == Screen::DiffView
+        && app.focus == Focus::Diff
+        && let Some(domain_diff) = app.selected_domain_diff()
+        && let Some(change) = app.selected_change()
+    {
+        let nix = export_nix_change(change, domain_diff.scope);
+        copy_with_status(app, &nix, "✓ Change copied as nix-darwin Nix");
+    }
+}
+
+fn handle_copy_nix(app: &mut App) {
+    if app.screen == Screen::DiffView
+        && let Some(diff) = &app.diff_result
+    {
+        let nix = export_nix(diff);
+        copy_with_status(app, &nix, "✓ Diff copied as nix-darwin Nix");
+    }
+}
+
+fn handle_copy_undo(app: &mut App) {
+    if app.screen == Screen::DiffView
+        && app.focus == Focus::Diff
+        && let Some(cmd) = app.selected_undo_command()
+    {
+        let cmd = with_restart_hint(app, cmd);
+        copy_with_status(app, &cmd, "✓ Undo command copied to clipboard");
+    }
+}
+
+fn handle_copy_all(app: &mut App) {
+    if app.screen == Screen::DiffView
+        && let Some(diff) = &app.diff_result
+    {
+        let script = collect_commands(diff, &app.command_options, app.group_commands_by_domain);
+        let message = format!(
+            "✓ Commands for {} change{} copied to clipboard",
+            diff.total_changes,
+            if diff.total_changes == 1 { "" } else { "s" }
+        );
+        copy_with_status(app, &script, &message);
+    }
+}
+
+fn handle_copy_domain(app: &mut App) {
+    if app.screen == Screen::DiffView
+        && let Some(domain) = app.selected_domain()
+    {
+        let domain = domain.to_string();
+        let message = format!("✓ Copied domain {}", domain);
+        copy_with_status(app, &domain, &message);
+    }
+}
+
+fn handle_copy_domain_commands(app: &mut App) {
+    if app.screen == Screen::DiffView
+        && app.focus == Focus::Domain
+        && let Some(commands) = app.selected_domain_commands()
+    {
+        let message = format!("✓ Copied {} commands", commands.len());
+        let block = with_restart_hint(app, commands.join("\n"));
+        copy_with_status(app, &block, &message);
+    }
+}
+
+/// Append the selected domain's `killall` restart command as a second line
+fn with_restart_hint(app: &App, commands: String) -> String {
+    match app.selected_domain().and_then(restart_command_for_domain) {
+        Some(restart) => format!("{}\n{}", commands, restart),
+        None => commands,
+    }
+}
+
+fn handle_copy_patch(app: &mut App) {
+    if app.screen == Screen::DiffView
+        && let Some(diff) = &app.diff_result
+    {
+        let patch = export_diff_patch(diff);
+        copy_with_status(app, &patch, "✓ Diff patch copied to clipboard");
+    }
+}
+
+fn handle_copy_ansible(app: &mut App) {
+    if app.screen == Screen::DiffView
+        && let Some(diff) = &app.diff_result
+    {
+        let tasks = export_ansible(diff);
+        copy_with_status(app, &tasks, "✓ Ansible tasks copied to clipboard");
+    }
+}
+
+/// How copied text reached the clipboard
+#[derive(Debug, Clone, PartialEq)]
+enum ClipboardMethod {
+    Pbcopy,
+    /// Terminal escape sequence, which works over SSH. Holds why pbcopy
+    /// failed when it was tried first.
+    Osc52 {
+        pbcopy_error: Option<String>,
+    },
+}
+
+/// Copy text and report the outcome in the status bar
+fn copy_with_status(app: &mut App, text: &str, success: &str) {
+    match copy_to_clipboard(text) {
+        Ok(ClipboardMethod::Pbcopy) => {
+            app.exported = true;
+            app.set_status(StatusMessage::success(success))
+        }
+        Ok(ClipboardMethod::Osc52 { pbcopy_error: None }) => {
+            app.exported = true;
+            app.set_status(StatusMessage::success(format!("{} (via OSC 52)", success)))
         }
+        // The terminal may ignore OSC 52, so say why pbcopy wasn't used
+        Ok(ClipboardMethod::Osc52 {
+            pbcopy_error: Some(error),
+        }) => {
+            app.exported = true;
+            app.set_status(StatusMessage::warning(format!(
+                "{} via OSC 52 ({})",
+                success, error
+            )))
+        }
+        Err(error) => app.set_status(StatusMessage::warning(format!(
+            "Failed to copy to clipboard: {}",
+            error
+        ))),
+    }
+}
+
+/// Copy text with pbcopy, falling back to OSC 52 over SSH or when pbcopy fails
+fn copy_to_clipboard(text: &str) -> Result<ClipboardMethod, String> {
+    // pbcopy on the remote Mac would fill the wrong clipboard
+    let over_ssh = std::env::var_os("SSH_TTY").is_some();
+    let pbcopy_error = if over_ssh {
+        None
+    } else {
+        match pipe_to(&mut Command::new("pbcopy"), text) {
+            Ok(()) => return Ok(ClipboardMethod::Pbcopy),
+            Err(error) => Some(error),
+        }
+    };
+    match copy_with_osc52(text) {
+        Ok(()) => Ok(ClipboardMethod::Osc52 { pbcopy_error }),
+        Err(error) => Err(match pbcopy_error {
+            Some(pbcopy_error) => format!("{}; {}", pbcopy_error, error),
+            None => error,
+        }),
+    }
+}
+
+/// Write text to a command's stdin and wait for it to succeed
+fn pipe_to(command: &mut Command, text: &str) -> Result<(), String> {
+    use std::io::Write;
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run {}: {}", program, e))?;
+
+    let written = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(text.as_bytes()),
+        None => Ok(()),
+    };
+    // stdin is dropped above, so the child receives EOF; wait even when the
+    // write failed so no zombie is left behind
+    let status = child
+        .wait()
+        .map_err(|e| format!("waiting for {} failed: {}", program, e))?;
+    written.map_err(|e| format!("writing to {} failed: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
+/// Ask the terminal to set its clipboard through an OSC 52 escape sequence
+fn copy_with_osc52(text: &str) -> Result<(), String> {
+    use std::io::Write;
+    let mut stdout = io::stdout();
+    stdout
+        .write_all(osc52_sequence(text).as_bytes())
+        .and_then(|()| stdout.flush())
+        .map_err(|e| format!("writing the OSC 52 sequence failed: {}", e))
+}
+
+/// `ESC ] 52 ; c ; <base64> BEL`, which sets the system clipboard
+fn osc52_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::SortMode;
+    use crate::app::tests::diff_view_app;
+
+    fn type_keys(app: &mut App, text: &str) {
+        for c in text.chars() {
+            handle_key(app, KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    #[test]
+    fn test_jump_by_typed_keys() {
+        let mut app = diff_view_app(&["com.apple.dock", "com.apple.finder", "org.example"]);
+        type_keys(&mut app, ":finder");
+        handle_key(&mut app, KeyEvent::from(KeyCode::Enter));
+        assert!(app.input_mode.is_none());
+        assert_eq!(app.selected_domain(), Some("com.apple.finder"));
+
+        // Outside the jump line, letters are commands, not a search
+        type_keys(&mut app, "o");
+        assert_eq!(app.sort_mode, SortMode::ChangeCountDesc);
+    }
+
+    #[test]
+    fn test_type_ahead_after_prefix_key() {
+        let mut app = diff_view_app(&["com.apple.dock", "com.apple.finder", "org.example"]);
+        type_keys(&mut app, "'org");
+        assert_eq!(app.selected_domain(), Some("org.example"));
+        // `o` went to the type-ahead, not to sorting
+        assert_eq!(app.sort_mode, SortMode::default());
+
+        // Esc ends it, and letters are commands again
+        handle_key(&mut app, KeyEvent::from(KeyCode::Esc));
+        type_keys(&mut app, "o");
+        assert_eq!(app.sort_mode, SortMode::ChangeCountDesc);
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(
+            osc52_sequence("defaults write -g x -bool true"),
+            "\x1b]52;c;ZGVmYXVsdHMgd3JpdGUgLWcgeCAtYm9vbCB0cnVl\x07"
+        );
+    }
+
+    #[test]
+    fn test_pipe_to_reports_each_failure() {
+        assert_eq!(
+            pipe_to(Command::new("sh").args(["-c", "cat >/dev/null"]), "text"),
+            Ok(())
+        );
+        assert_eq!(
+            pipe_to(
+                Command::new("sh").args(["-c", "cat >/dev/null; exit 3"]),
+                "text"
+            ),
+            Err("sh exited with exit status: 3".to_string())
+        );
+        let err = pipe_to(&mut Command::new("/nonexistent/pbcopy"), "text").unwrap_err();
+        assert!(err.starts_with("could not run /nonexistent/pbcopy: "));
     }
 }
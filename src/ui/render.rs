@@ -3,12 +3,22 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, Gauge, List, ListItem, Paragraph, Wrap},
 };
 
-use crate::app::{App, Focus, Screen, StatusKind};
-use crate::command::generator::generate_command;
-use crate::diff::Change;
+use crate::app::{
+    App, ChangeSort, DomainRow, Focus, InputMode, PreviewMode, Screen, SortMode, StatusKind,
+    domain_group,
+};
+use crate::command::generator::{CommandScope, write_restriction};
+use crate::config::StatusPosition;
+use crate::defaults::types::value_type_label;
+use crate::diff::format::{
+    Detail, change_parts, format_change, format_summary, format_value, format_value_lines,
+};
+use crate::diff::{Change, DiffResult};
+
+use super::theme::Theme;
 
 pub fn render(frame: &mut Frame, app: &mut App) {
     match app.screen.clone() {
@@ -16,11 +26,463 @@ pub fn render(frame: &mut Frame, app: &mut App) {
         Screen::LoadingFirst | Screen::LoadingSecond => render_loading_screen(frame, app),
         Screen::WaitingForChanges => render_waiting_screen(frame, app),
         Screen::DiffView => render_diff_screen(frame, app),
-        Screen::Error(msg) => render_error_screen(frame, &msg),
+        Screen::NoChanges => render_no_changes_screen(frame, app),
+        Screen::History => render_history_screen(frame, app),
+        Screen::Error(msg) => render_error_screen(frame, &msg, app.theme.theme()),
+    }
+
+    if app.show_stats {
+        render_stats_popup(frame, app);
+    }
+    if app.show_failed_domains {
+        render_failed_domains_popup(frame, app);
+    }
+    if app.value_popup.is_some() {
+        render_value_popup(frame, app);
+    }
+    if app.all_keys_domain.is_some() {
+        render_all_keys_popup(frame, app);
+    }
+    if app.pending_apply.is_some() {
+        render_apply_confirmation(frame, app);
+    }
+    if app.confirm_quit {
+        render_quit_confirmation(frame, app.theme.theme());
+    }
+    if app.show_help {
+        render_help(frame, app.theme.theme());
+    }
+}
+
+/// Rect of the given percentage size centered in `area`
+/// Furthest a bordered popup drawn at `area` can scroll through `lines` lines
+/// while its last line stays in view
+fn max_scroll(lines: usize, area: Rect) -> u16 {
+    u16::try_from(lines)
+        .unwrap_or(u16::MAX)
+        .saturating_sub(area.height.saturating_sub(2))
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+/// Keybindings shown by the help overlay, by section
+const HELP_SECTIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "General",
+        &[
+            ("Enter", "Capture snapshot / show change values"),
+            ("r", "Reset to the start screen"),
+            (
+                "Ctrl+r",
+                "Re-capture the second snapshot, keeping the selection",
+            ),
+            (
+                "q / Esc",
+                "Quit (Esc cancels a capture or clears the filter first)",
+            ),
+            ("?", "Toggle this help"),
+            ("S / L", "Save / load the first snapshot"),
+            ("b", "History: diff any two earlier captures"),
+            ("O", "Capture only one domain (start screen)"),
+            ("w", "Watch: re-capture every few seconds"),
+            ("V", "Detect keys that change when read"),
+            ("M", "Cycle managed preference domains"),
+            ("x", "Include / exclude Apple service domains"),
+            ("T / f", "Snapshot stats / skipped domains"),
+        ],
+    ),
+    (
+        "Navigation",
+        &[
+            ("j / k", "Move down / up"),
+            ("PgUp / PgDn", "Move a page (also Ctrl-u / Ctrl-d)"),
+            ("g / G", "Jump to top / bottom"),
+            ("Tab / h / l", "Switch between domains and changes"),
+            ("/", "Filter domains and keys"),
+            (":", "Jump to a domain by fuzzy name"),
+            ("' then letters", "Type-ahead jump in the domain list"),
+        ],
+    ),
+    (
+        "View",
+        &[
+            ("+ / - / ~", "Only added / removed / modified keys"),
+            ("o", "Sort domains by name or change count"),
+            ("t", "Group domains by prefix (Enter/Space expands)"),
+            ("O", "Sort changes by export order, key or type"),
+            ("p", "Preview command or value summary"),
+            ("m", "Cycle color theme"),
+            ("I", "Every key of the domain, changed or not"),
+            ("K", "Diff keys only"),
+            ("N / i", "Show normalized / ignored changes"),
+            ("F / X", "Hide factory defaults / removed-only domains"),
+        ],
+    ),
+    (
+        "Commands",
+        &[
+            ("y / u", "Copy command / undo command"),
+            ("W", "Copy command; killall <app> as one line"),
+            ("J", "Copy change as JSON {domain, key, type, value}"),
+            ("n / Z", "Copy change / whole diff as nix-darwin Nix"),
+            ("Y / D", "Copy domain's commands / domain name"),
+            ("C / #", "Copy all commands / toggle domain headers"),
+            ("P / A", "Copy as patch / Ansible tasks"),
+            ("a", "Apply the selected change"),
+            ("s / E / R", "Save script / per-domain scripts / report"),
+            ("H / Q", "Toggle -currentHost / quote style"),
+            ("U", "Write nested changes with plutil"),
+            ("e", "Open the domain's plist in $EDITOR"),
+            ("d / v", "Toggle delta comments / verify reads"),
+        ],
+    ),
+];
+
+/// Full-screen list of every keybinding, two sections per column
+fn render_help(frame: &mut Frame, theme: &Theme) {
+    let area = frame.area();
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.accent))
+            .title(" Help (any key to close) "),
+        area,
+    );
+
+    let inner = Rect {
+        x: area.x + 1,
+        y: area.y + 1,
+        width: area.width.saturating_sub(2),
+        height: area.height.saturating_sub(2),
+    };
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(inner);
+
+    for (column, sections) in columns.iter().zip(HELP_SECTIONS.chunks(2)) {
+        let mut lines = Vec::new();
+        for (title, keys) in sections {
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                format!("  {}", title),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            lines.extend(keys.iter().map(|(key, action)| {
+                Line::from(vec![
+                    Span::styled(format!("  {:<13}", key), Style::default().fg(theme.accent)),
+                    Span::raw(*action),
+                ])
+            }));
+        }
+        frame.render_widget(Paragraph::new(lines), *column);
+    }
+}
+
+fn render_stats_popup(frame: &mut Frame, app: &App) {
+    let theme = app.theme.theme();
+    let Some(snapshot) = app.latest_snapshot() else {
+        return;
+    };
+
+    let mut histogram: Vec<(&str, usize)> = snapshot.type_histogram().into_iter().collect();
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let total: usize = histogram.iter().map(|(_, count)| count).sum();
+
+    let mut lines = vec![
+        Line::from(""),
+        Line::from(format!(
+            "  {} domains, {} values",
+            snapshot.domain_count(),
+            total
+        )),
+        Line::from(""),
+    ];
+    lines.extend(histogram.into_iter().map(|(label, count)| {
+        Line::from(vec![
+            Span::styled(format!("  {:<8}", label), Style::default().fg(theme.accent)),
+            Span::raw(format!("{:>8}", count)),
+        ])
+    }));
+
+    let area = centered_rect(50, 60, frame.area());
+    let stats = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.accent))
+            .title(" Snapshot Stats (any key to close) "),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(stats, area);
+}
+
+fn render_failed_domains_popup(frame: &mut Frame, app: &App) {
+    let theme = app.theme.theme();
+    let Some(snapshot) = app.latest_snapshot() else {
+        return;
+    };
+
+    let mut lines = vec![
+        Line::from(""),
+        Line::from(format!(
+            "  {} domains could not be captured",
+            snapshot.failed_domains.len()
+        )),
+        Line::from(""),
+    ];
+    for (domain, error) in &snapshot.failed_domains {
+        lines.push(Line::from(Span::styled(
+            format!("  {}", domain),
+            Style::default().fg(theme.warning),
+        )));
+        lines.push(Line::from(Span::styled(
+            format!("    {}", error.trim()),
+            Style::default().fg(theme.muted),
+        )));
+    }
+
+    let area = centered_rect(70, 70, frame.area());
+    let popup = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.warning))
+            .title(" Skipped Domains (any key to close) "),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+fn render_value_popup(frame: &mut Frame, app: &mut App) {
+    let theme = app.theme.theme();
+    let Some(change) = &app.value_popup else {
+        return;
+    };
+
+    let heading = Style::default()
+        .fg(theme.accent)
+        .add_modifier(Modifier::BOLD);
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!("{} {}", change.domain(), change.key()),
+            heading,
+        )),
+        Line::from(""),
+    ];
+    for (label, value, color) in [
+        ("Old value", change.old_value(), theme.removed),
+        ("New value", change.new_value(), theme.added),
+    ] {
+        let Some(value) = value else {
+            continue;
+        };
+        lines.push(Line::from(Span::styled(
+            format!("{} ({})", label, value_type_label(value)),
+            heading,
+        )));
+        lines.extend(format_value_lines(value).into_iter().map(|line| {
+            Line::from(Span::styled(
+                format!("  {}", line),
+                Style::default().fg(color),
+            ))
+        }));
+        lines.push(Line::from(""));
+    }
+
+    let area = centered_rect(80, 80, frame.area());
+    app.value_popup_max_scroll = max_scroll(lines.len(), area);
+    app.value_popup_scroll = app.value_popup_scroll.min(app.value_popup_max_scroll);
+    let popup = Paragraph::new(lines)
+        .scroll((app.value_popup_scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Value (j/k to scroll, Esc to close) "),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+fn render_all_keys_popup(frame: &mut Frame, app: &mut App) {
+    let theme = app.theme.theme();
+    let Some(domain) = &app.all_keys_domain else {
+        return;
+    };
+
+    let mut lines = vec![Line::from("")];
+    match app.all_keys() {
+        Some(keys) => {
+            let changed = keys.iter().filter(|(_, _, changed)| *changed).count();
+            lines.push(Line::from(format!(
+                "  {} keys, {} changed (marked *)",
+                keys.len(),
+                changed
+            )));
+            lines.push(Line::from(""));
+            lines.extend(keys.into_iter().map(|(key, value, changed)| {
+                let (marker, color) = if changed {
+                    ("*", theme.modified)
+                } else {
+                    (" ", theme.text)
+                };
+                Line::from(vec![
+                    Span::styled(format!(" {} {}", marker, key), Style::default().fg(color)),
+                    Span::styled(
+                        format!(" = {}", format_value(value, Detail::Compact)),
+                        Style::default().fg(theme.muted),
+                    ),
+                ])
+            }));
+        }
+        None => lines.push(Line::from("  The domain is not in the second snapshot")),
+    }
+
+    let area = centered_rect(80, 80, frame.area());
+    app.all_keys_max_scroll = max_scroll(lines.len(), area);
+    app.all_keys_scroll = app.all_keys_scroll.min(app.all_keys_max_scroll);
+    let popup = Paragraph::new(lines)
+        .scroll((app.all_keys_scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(format!(
+                    " All Keys in {} (j/k to scroll, Esc to close) ",
+                    domain
+                )),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+fn render_apply_confirmation(frame: &mut Frame, app: &App) {
+    let theme = app.theme.theme();
+    let Some(command) = &app.pending_apply else {
+        return;
+    };
+
+    render_confirmation(
+        frame,
+        theme,
+        " Apply Change ",
+        vec![
+            Line::from("  Run this command?"),
+            Line::from(""),
+            Line::from(Span::styled(
+                format!("  $ {}", command),
+                Style::default().fg(theme.text),
+            )),
+        ],
+        "Apply",
+    );
+}
+
+fn render_quit_confirmation(frame: &mut Frame, theme: &Theme) {
+    render_confirmation(
+        frame,
+        theme,
+        " Quit ",
+        vec![
+            Line::from("  Nothing from this diff was copied or exported."),
+            Line::from("  Quit and lose the capture?"),
+        ],
+        "Quit",
+    );
+}
+
+/// Yes/no popup: `question` lines followed by a `[y] <confirm>` hint
+fn render_confirmation(
+    frame: &mut Frame,
+    theme: &Theme,
+    title: &str,
+    question: Vec<Line>,
+    confirm: &str,
+) {
+    let mut lines = vec![Line::from("")];
+    lines.extend(question);
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        format!("  [y] {}  [any other key] Cancel", confirm),
+        Style::default().fg(theme.muted),
+    )));
+
+    let area = centered_rect(70, 40, frame.area());
+    let popup = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.warning))
+            .title(title.to_string()),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Screen region that can hold the status message
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Region {
+    Header,
+    Footer,
+}
+
+/// Pick the region for the status message given the screen's usual region
+fn status_region(position: StatusPosition, natural: Region) -> Option<Region> {
+    match position {
+        StatusPosition::Auto => Some(natural),
+        StatusPosition::Header => Some(Region::Header),
+        StatusPosition::Footer => Some(Region::Footer),
+        StatusPosition::Hidden => None,
+    }
+}
+
+/// Status text and color to draw in `region`, if the status belongs there
+fn status_for_region(app: &App, region: Region, natural: Region) -> Option<(String, Color)> {
+    let status = app.get_status()?;
+    (status_region(app.config.status_position, natural) == Some(region)).then(|| {
+        (
+            status.text.clone(),
+            status_color(&status.kind, app.theme.theme()),
+        )
+    })
+}
+
+/// Right-aligned "READ-ONLY" block title shown in inspect mode
+fn read_only_banner(app: &App) -> Line<'static> {
+    if app.read_only {
+        Line::from(Span::styled(" READ-ONLY ", app.theme.theme().banner)).right_aligned()
+    } else {
+        Line::default()
+    }
+}
+
+fn status_color(kind: &StatusKind, theme: &Theme) -> Color {
+    match kind {
+        StatusKind::Success => theme.success,
+        StatusKind::Warning => theme.warning,
+        StatusKind::Info => theme.accent,
     }
 }
 
 fn render_initial_screen(frame: &mut Frame, app: &mut App) {
+    let theme = app.theme.theme();
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
@@ -31,13 +493,22 @@ fn render_initial_screen(frame: &mut Frame, app: &mut App) {
         ])
         .split(frame.area());
 
-    let title = Paragraph::new("defaults-util - macOS Settings Diff Tool")
+    let (title_text, title_color) = status_for_region(app, Region::Header, Region::Footer)
+        .unwrap_or((
+            "defaults-util - macOS Settings Diff Tool".to_string(),
+            theme.accent,
+        ));
+    let title = Paragraph::new(title_text)
         .style(
             Style::default()
-                .fg(Color::Cyan)
+                .fg(title_color)
                 .add_modifier(Modifier::BOLD),
         )
-        .block(Block::default().borders(Borders::ALL));
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(read_only_banner(app)),
+        );
     frame.render_widget(title, chunks[0]);
 
     let instructions = Paragraph::new(vec![
@@ -47,6 +518,26 @@ fn render_initial_screen(frame: &mut Frame, app: &mut App) {
         Line::from("  3. Press [Enter] again to capture the second snapshot"),
         Line::from("  4. View the differences and copy commands"),
         Line::from(""),
+        Line::from(format!(
+            "  [V] Volatile key detection: {}",
+            if app.capture_options.detect_volatile {
+                "on"
+            } else {
+                "off"
+            }
+        )),
+        Line::from(format!(
+            "  [M] Managed preferences: {}",
+            app.capture_options.managed.label()
+        )),
+        Line::from(format!(
+            "  [O] Capture only: {}",
+            app.capture_options
+                .domain
+                .as_deref()
+                .unwrap_or("every domain")
+        )),
+        Line::from("  [L] Load the saved snapshot as the first snapshot"),
         Line::from("  Press [q] to quit"),
     ])
     .block(
@@ -57,27 +548,19 @@ fn render_initial_screen(frame: &mut Frame, app: &mut App) {
     frame.render_widget(instructions, chunks[1]);
 
     // Status bar
-    let status_text = if let Some(status) = app.get_status() {
-        status.text.as_str()
-    } else {
-        "Ready - Press [Enter] to start"
-    };
-    let status_color = if let Some(status) = app.get_status() {
-        match status.kind {
-            StatusKind::Success => Color::Green,
-            StatusKind::Warning => Color::Yellow,
-            StatusKind::Info => Color::Cyan,
-        }
-    } else {
-        Color::Green
-    };
+    let (status_text, status_color, status_title) = input_line(app).unwrap_or_else(|| {
+        let (text, color) = status_for_region(app, Region::Footer, Region::Footer)
+            .unwrap_or(("Ready - Press [Enter] to start".to_string(), theme.success));
+        (text, color, " Status ")
+    });
     let status = Paragraph::new(status_text)
         .style(Style::default().fg(status_color))
-        .block(Block::default().borders(Borders::ALL).title(" Status "));
+        .block(Block::default().borders(Borders::ALL).title(status_title));
     frame.render_widget(status, chunks[2]);
 }
 
 fn render_loading_screen(frame: &mut Frame, app: &mut App) {
+    let theme = app.theme.theme();
     let area = frame.area();
 
     // Display loading message in center
@@ -85,7 +568,7 @@ fn render_loading_screen(frame: &mut Frame, app: &mut App) {
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Percentage(35),
-            Constraint::Length(7),
+            Constraint::Length(10),
             Constraint::Percentage(35),
         ])
         .split(area);
@@ -106,27 +589,44 @@ fn render_loading_screen(frame: &mut Frame, app: &mut App) {
 
     let loading_block = Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Yellow))
+        .border_style(Style::default().fg(theme.warning))
         .title(" Loading ");
 
     let loading = Paragraph::new(vec![
         Line::from(""),
         Line::from(vec![
-            Span::styled("  ◐ ", Style::default().fg(Color::Yellow)),
+            Span::styled("  ◐ ", Style::default().fg(theme.warning)),
             Span::raw(msg),
         ]),
         Line::from(""),
         Line::from(Span::styled(
-            "  Please wait...",
-            Style::default().fg(Color::DarkGray),
+            "  Please wait... Press [Esc] to cancel",
+            Style::default().fg(theme.muted),
         )),
     ])
     .block(loading_block);
 
-    frame.render_widget(loading, center[1]);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(7), Constraint::Length(3)])
+        .split(center[1]);
+    frame.render_widget(loading, rows[0]);
+
+    if let Some(progress) = &app.capture_progress {
+        let gauge = Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title(" Progress "))
+            .gauge_style(Style::default().fg(theme.warning))
+            .ratio(progress.ratio())
+            .label(format!(
+                "{}/{} {}",
+                progress.done, progress.total, progress.domain
+            ));
+        frame.render_widget(gauge, rows[1]);
+    }
 }
 
 fn render_waiting_screen(frame: &mut Frame, app: &mut App) {
+    let theme = app.theme.theme();
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
@@ -137,10 +637,12 @@ fn render_waiting_screen(frame: &mut Frame, app: &mut App) {
         ])
         .split(frame.area());
 
-    let title = Paragraph::new("First Snapshot Captured!")
+    let (title_text, title_color) = status_for_region(app, Region::Header, Region::Footer)
+        .unwrap_or(("First Snapshot Captured!".to_string(), theme.success));
+    let title = Paragraph::new(title_text)
         .style(
             Style::default()
-                .fg(Color::Green)
+                .fg(title_color)
                 .add_modifier(Modifier::BOLD),
         )
         .block(Block::default().borders(Borders::ALL));
@@ -155,7 +657,7 @@ fn render_waiting_screen(frame: &mut Frame, app: &mut App) {
     let instructions = Paragraph::new(vec![
         Line::from(""),
         Line::from(vec![
-            Span::styled("  ✓ ", Style::default().fg(Color::Green)),
+            Span::styled("  ✓ ", Style::default().fg(theme.success)),
             Span::raw(format!("Captured {} domains", domain_count)),
         ]),
         Line::from(""),
@@ -165,8 +667,12 @@ fn render_waiting_screen(frame: &mut Frame, app: &mut App) {
         Line::from("  and detect changes."),
         Line::from(""),
         Line::from(Span::styled(
-            "  [r] Reset  [q] Quit",
-            Style::default().fg(Color::DarkGray),
+            if app.read_only {
+                "  [r] Reset  [q] Quit"
+            } else {
+                "  [S] Save snapshot  [r] Reset  [q] Quit"
+            },
+            Style::default().fg(theme.muted),
         )),
     ])
     .block(
@@ -176,13 +682,174 @@ fn render_waiting_screen(frame: &mut Frame, app: &mut App) {
     );
     frame.render_widget(instructions, chunks[1]);
 
-    let status = Paragraph::new("Waiting for changes - Press [Enter] when ready")
-        .style(Style::default().fg(Color::Yellow))
+    let (status_text, status_color) = status_for_region(app, Region::Footer, Region::Footer)
+        .unwrap_or((
+            "Waiting for changes - Press [Enter] when ready".to_string(),
+            theme.warning,
+        ));
+    let status = Paragraph::new(status_text)
+        .style(Style::default().fg(status_color))
         .block(Block::default().borders(Borders::ALL).title(" Status "));
     frame.render_widget(status, chunks[2]);
 }
 
+fn render_no_changes_screen(frame: &mut Frame, app: &mut App) {
+    let theme = app.theme.theme();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(2)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(10),
+            Constraint::Length(3),
+        ])
+        .split(frame.area());
+
+    let title = Paragraph::new("All Clear!")
+        .style(
+            Style::default()
+                .fg(theme.success)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(Block::default().borders(Borders::ALL));
+    frame.render_widget(title, chunks[0]);
+
+    let message = Paragraph::new(vec![
+        Line::from(""),
+        Line::from(vec![
+            Span::styled(
+                "  ✓ ",
+                Style::default()
+                    .fg(theme.success)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                "No changes detected",
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(""),
+        Line::from("  Make changes in System Settings and press [Enter] to retry."),
+        Line::from("  The first snapshot is kept, so changes are compared against it."),
+        Line::from(""),
+        Line::from(Span::styled(
+            "  [r] Reset  [q] Quit",
+            Style::default().fg(theme.muted),
+        )),
+    ])
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.success))
+            .title(" Result "),
+    );
+    frame.render_widget(message, chunks[1]);
+
+    let (status_text, status_color) = status_for_region(app, Region::Footer, Region::Footer)
+        .unwrap_or((
+            "No changes - Press [Enter] to capture again".to_string(),
+            theme.success,
+        ));
+    let status = Paragraph::new(status_text)
+        .style(Style::default().fg(status_color))
+        .block(Block::default().borders(Borders::ALL).title(" Status "));
+    frame.render_widget(status, chunks[2]);
+}
+
+fn render_history_screen(frame: &mut Frame, app: &mut App) {
+    let theme = app.theme.theme();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(3),
+        ])
+        .split(frame.area());
+
+    let (title_text, title_color) = status_for_region(app, Region::Header, Region::Header)
+        .unwrap_or(match app.history_marked {
+            Some(marked) => (
+                format!(
+                    "Comparing from #{} - pick the other capture",
+                    app.history[marked].number
+                ),
+                theme.warning,
+            ),
+            None => ("Pick two captures to compare".to_string(), theme.accent),
+        });
+    let title = Paragraph::new(title_text)
+        .style(
+            Style::default()
+                .fg(title_color)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(Block::default().borders(Borders::ALL).title(" History "));
+    frame.render_widget(title, chunks[0]);
+
+    let items: Vec<ListItem> = app
+        .history
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let marked = app.history_marked == Some(index);
+            let style = if marked {
+                Style::default().fg(theme.warning)
+            } else {
+                Style::default().fg(theme.text)
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!(
+                        "{} #{:<3} {:<14} {:>5} domains",
+                        if marked { "*" } else { " " },
+                        entry.number,
+                        entry.source,
+                        entry.snapshot.domain_count()
+                    ),
+                    style,
+                ),
+                Span::styled(
+                    format!("  {}", format_age(entry.taken_at.elapsed())),
+                    Style::default().fg(theme.muted),
+                ),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Captures (oldest first) "),
+        )
+        .highlight_style(theme.selection)
+        .highlight_symbol(">> ");
+    frame.render_stateful_widget(list, chunks[1], &mut app.history_state);
+
+    let (footer_text, footer_color) = status_for_region(app, Region::Footer, Region::Header)
+        .unwrap_or((
+            "[j/k] Move  [Enter/Space] Mark / compare with marked  [Esc] Back".to_string(),
+            theme.muted,
+        ));
+    let footer = Paragraph::new(footer_text)
+        .style(Style::default().fg(footer_color))
+        .block(Block::default().borders(Borders::ALL).title(" Help "));
+    frame.render_widget(footer, chunks[2]);
+}
+
+/// Rough age of a capture, like `just now` or `5 min ago`
+fn format_age(age: std::time::Duration) -> String {
+    match age.as_secs() {
+        0..60 => "just now".to_string(),
+        secs @ 60..3600 => format!("{} min ago", secs / 60),
+        secs => format!("{} h ago", secs / 3600),
+    }
+}
+
 fn render_diff_screen(frame: &mut Frame, app: &mut App) {
+    let theme = app.theme.theme();
     // Show command preview when focusing on Changes pane with a selection
     let show_preview = app.focus == Focus::Diff && app.selected_change().is_some();
 
@@ -206,34 +873,27 @@ fn render_diff_screen(frame: &mut Frame, app: &mut App) {
         .split(frame.area());
 
     // Header (show status message if available)
-    let header_text = if let Some(status) = app.get_status() {
-        status.text.clone()
-    } else {
-        let total_changes = app
+    let header_line = match status_for_region(app, Region::Header, Region::Header) {
+        Some((text, color)) => Line::from(Span::styled(text, Style::default().fg(color))),
+        None => app
             .diff_result
             .as_ref()
-            .map(|d| d.total_changes)
-            .unwrap_or(0);
-        format!("Found {} changes", total_changes)
-    };
-
-    let header_color = if let Some(status) = app.get_status() {
-        match status.kind {
-            StatusKind::Success => Color::Green,
-            StatusKind::Warning => Color::Yellow,
-            StatusKind::Info => Color::Cyan,
-        }
-    } else {
-        Color::Cyan
+            .map(|diff| change_tally_line(diff, theme))
+            .unwrap_or_default(),
     };
 
-    let header = Paragraph::new(header_text)
-        .style(
-            Style::default()
-                .fg(header_color)
-                .add_modifier(Modifier::BOLD),
-        )
-        .block(Block::default().borders(Borders::ALL).title(" Diff View "));
+    let header = Paragraph::new(header_line)
+        .style(Style::default().add_modifier(Modifier::BOLD))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(if app.watch {
+                    " Diff View [watching] "
+                } else {
+                    " Diff View "
+                })
+                .title(read_only_banner(app)),
+        );
     frame.render_widget(header, chunks[0]);
 
     // Main content
@@ -242,75 +902,160 @@ fn render_diff_screen(frame: &mut Frame, app: &mut App) {
         .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
         .split(chunks[1]);
 
+    // List rows between the borders, used for paging
+    app.page_size = usize::from(main_chunks[1].height.saturating_sub(2)).max(1);
     render_domain_list(frame, app, main_chunks[0]);
     render_diff_details(frame, app, main_chunks[1]);
 
     // Command preview (only when focused on Changes)
     if show_preview && let Some(change) = app.selected_change() {
-        let cmd = generate_command(change);
+        let (prompt, text, title) = match app.preview_mode {
+            PreviewMode::Command => (
+                "  $ ",
+                app.selected_command().unwrap_or_default(),
+                format!(
+                    " Command Preview{} (y to copy, p for summary, H for host) ",
+                    match app.command_options.host {
+                        CommandScope::Global => "",
+                        CommandScope::CurrentHost => " [-currentHost]",
+                    }
+                ),
+            ),
+            PreviewMode::Summary => (
+                "  ",
+                format_summary(change),
+                " Value Summary (y to copy command, p for command) ".to_string(),
+            ),
+        };
         let preview = Paragraph::new(vec![
             Line::from(""),
             Line::from(vec![
-                Span::styled("  $ ", Style::default().fg(Color::DarkGray)),
-                Span::styled(cmd, Style::default().fg(Color::White)),
+                Span::styled(prompt, Style::default().fg(theme.muted)),
+                Span::styled(text, Style::default().fg(theme.text)),
             ]),
         ])
         .wrap(Wrap { trim: false })
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Cyan))
-                .title(" Command Preview (y to copy) "),
+                .border_style(Style::default().fg(theme.accent))
+                .title(title),
         );
         frame.render_widget(preview, chunks[2]);
     }
 
     // Footer
     let footer_idx = if show_preview { 3 } else { 2 };
-    let footer_text = if app.focus == Focus::Diff {
-        "[j/k] Move  [Tab] Switch focus  [y] Copy command  [r] Reset  [q] Quit"
-    } else {
-        "[j/k] Move  [Tab] Switch focus  [r] Reset  [q] Quit"
-    };
+    let footer_text = diff_footer_hint(app);
+    let (footer_text, footer_color, footer_title) = input_line(app).unwrap_or_else(|| {
+        let (text, color) = status_for_region(app, Region::Footer, Region::Header)
+            .unwrap_or((footer_text.to_string(), theme.muted));
+        (text, color, " Help ")
+    });
     let footer = Paragraph::new(footer_text)
-        .style(Style::default().fg(Color::DarkGray))
-        .block(Block::default().borders(Borders::ALL).title(" Help "));
+        .style(Style::default().fg(footer_color))
+        .block(Block::default().borders(Borders::ALL).title(footer_title));
     frame.render_widget(footer, chunks[footer_idx]);
 }
 
+/// Key hints for the diff view footer, leaving out apply when it is refused
+fn diff_footer_hint(app: &App) -> &'static str {
+    let can_apply = !app.read_only && app.capture_options.user.is_none();
+    match (&app.focus, can_apply) {
+        (Focus::Diff, true) => {
+            "[j/k] Move  [Tab] Switch focus  [y] Copy command  [a] Apply  [r] Reset  [?] Help  [q] Quit"
+        }
+        (Focus::Diff, false) => {
+            "[j/k] Move  [Tab] Switch focus  [y] Copy command  [r] Reset  [?] Help  [q] Quit"
+        }
+        (Focus::Domain, _) => "[j/k] Move  [Tab] Switch focus  [r] Reset  [?] Help  [q] Quit",
+    }
+}
+
+/// `Found N changes (A+ R- M~)` with each count in its change color
+fn change_tally_line(diff: &DiffResult, theme: &Theme) -> Line<'static> {
+    let counts = diff.counts();
+    Line::from(vec![
+        Span::styled(
+            format!("Found {} changes (", diff.total_changes),
+            Style::default().fg(theme.accent),
+        ),
+        Span::styled(
+            format!("{}+", counts.added),
+            Style::default().fg(theme.added),
+        ),
+        Span::raw(" "),
+        Span::styled(
+            format!("{}-", counts.removed),
+            Style::default().fg(theme.removed),
+        ),
+        Span::raw(" "),
+        Span::styled(
+            format!("{}~", counts.modified),
+            Style::default().fg(theme.modified),
+        ),
+        Span::styled(")", Style::default().fg(theme.accent)),
+    ])
+}
+
 fn render_domain_list(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme.theme();
+    app.domain_list_area = area;
+    let filter = app.filter.as_deref().unwrap_or_default();
     let items: Vec<ListItem> = app
         .diff_result
         .as_ref()
         .map(|diff| {
-            diff.domain_diffs
+            app.domain_rows
                 .iter()
-                .map(|domain_diff| {
-                    ListItem::new(format!(
-                        "{} ({})",
-                        domain_diff.domain,
-                        domain_diff.changes.len()
-                    ))
+                .filter_map(|row| match row {
+                    DomainRow::Group {
+                        prefix,
+                        domains,
+                        changes,
+                        expanded,
+                    } => {
+                        let style = Style::default()
+                            .fg(prefix_color(prefix, theme))
+                            .add_modifier(Modifier::BOLD);
+                        let mut spans =
+                            vec![Span::styled(if *expanded { "▾ " } else { "▸ " }, style)];
+                        spans.extend(highlight_matches(prefix, filter, style, theme));
+                        spans.push(Span::styled(
+                            format!(" ({} domains, {})", domains, changes),
+                            style,
+                        ));
+                        Some(ListItem::new(Line::from(spans)))
+                    }
+                    DomainRow::Domain { index, nested } => {
+                        let domain_diff = diff.domain_diffs.get(*index)?;
+                        let style = Style::default().fg(prefix_color(&domain_diff.domain, theme));
+                        let mut spans = Vec::new();
+                        if *nested {
+                            spans.push(Span::raw("  "));
+                        }
+                        spans.extend(highlight_matches(&domain_diff.domain, filter, style, theme));
+                        spans.push(Span::styled(
+                            format!(" ({})", domain_diff.changes.len()),
+                            style,
+                        ));
+                        Some(ListItem::new(Line::from(spans)))
+                    }
                 })
                 .collect()
         })
         .unwrap_or_default();
 
     let border_style = if app.focus == Focus::Domain {
-        Style::default().fg(Color::Cyan)
+        Style::default().fg(theme.accent)
     } else {
         Style::default()
     };
 
     let highlight_style = if app.focus == Focus::Domain {
-        Style::default()
-            .fg(Color::Black)
-            .bg(Color::Cyan)
-            .add_modifier(Modifier::BOLD)
+        theme.selection
     } else {
-        Style::default()
-            .fg(Color::Cyan)
-            .add_modifier(Modifier::BOLD)
+        theme.selection_unfocused
     };
 
     let list = List::new(items)
@@ -318,57 +1063,152 @@ fn render_domain_list(frame: &mut Frame, app: &mut App, area: Rect) {
             Block::default()
                 .borders(Borders::ALL)
                 .border_style(border_style)
-                .title(" Domains "),
+                .title(format!(
+                    " Domains{}{} ",
+                    match &app.filter {
+                        Some(query) if !query.is_empty() => format!(" [/{}]", query),
+                        _ if app.sort_mode == SortMode::ChangeCountDesc => {
+                            " [by count]".to_string()
+                        }
+                        _ => String::new(),
+                    },
+                    if app.group_domains { " [grouped]" } else { "" }
+                )),
         )
         .highlight_style(highlight_style)
         .highlight_symbol(">> ");
     frame.render_stateful_widget(list, area, &mut app.domain_list_state);
 }
 
+/// Reverse-DNS prefix of a domain (`com.apple.dock` → `com.apple`)
+fn domain_prefix(domain: &str) -> &str {
+    match domain.match_indices('.').nth(1) {
+        Some((index, _)) => &domain[..index],
+        None => domain,
+    }
+}
+
+/// Stable color for a domain's prefix (FNV-1a hash into the theme's palette)
+fn prefix_color(domain: &str, theme: &Theme) -> Color {
+    let hash = domain_prefix(domain)
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+    theme.palette[(hash % theme.palette.len() as u64) as usize]
+}
+
+/// Domains of the group header under the cursor, listed in place of changes
+fn group_member_items(app: &App) -> Vec<ListItem<'static>> {
+    let (Some(prefix), Some(diff)) = (&app.selected_group, &app.diff_result) else {
+        return Vec::new();
+    };
+    let theme = app.theme.theme();
+    diff.domain_diffs
+        .iter()
+        .filter(|d| domain_group(&d.domain) == Some(prefix.as_str()))
+        .map(|d| {
+            ListItem::new(Line::from(Span::styled(
+                format!("{} ({})", d.domain, d.changes.len()),
+                Style::default().fg(theme.muted),
+            )))
+        })
+        .collect()
+}
+
 fn render_diff_details(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme.theme();
+    app.diff_list_area = area;
+    // Inside the borders and the ">> " highlight symbol
+    let text_width = usize::from(area.width.saturating_sub(5));
+    let mut extra_rows = 0;
+    let filter = app.filter.as_deref().unwrap_or_default();
     let items: Vec<ListItem> = app
-        .diff_result
-        .as_ref()
-        .and_then(|diff| diff.domain_diffs.get(app.selected_domain_index))
+        .selected_domain_diff()
         .map(|domain_diff| {
+            let restriction = write_restriction(&domain_diff.domain);
             domain_diff
                 .changes
                 .iter()
-                .map(|change| {
+                .enumerate()
+                .map(|(index, change)| {
+                    let type_change = change.type_change();
                     let (prefix, color) = match change {
-                        Change::Added { .. } => ("+", Color::Green),
-                        Change::Removed { .. } => ("-", Color::Red),
-                        Change::Modified { .. } => ("~", Color::Yellow),
+                        _ if type_change.is_some() => ("~", theme.type_change),
+                        Change::Added { .. } => ("+", theme.added),
+                        Change::Removed { .. } => ("-", theme.removed),
+                        Change::Modified { .. } => ("~", theme.modified),
+                        Change::Normalized { .. } => ("=", theme.muted),
+                        Change::Nested {
+                            old_value: None, ..
+                        } => ("+", theme.added),
+                        Change::Nested {
+                            new_value: None, ..
+                        } => ("-", theme.removed),
+                        Change::Nested { .. } => ("~", theme.modified),
                     };
 
-                    let text = format_change(change);
-                    ListItem::new(Line::from(vec![
-                        Span::styled(format!("{} ", prefix), Style::default().fg(color)),
-                        Span::styled(text, Style::default().fg(color)),
-                    ]))
+                    let text = format_change(change, Detail::Compact);
+                    let style = Style::default().fg(color);
+                    let mut spans = vec![Span::styled(format!("{} ", prefix), style)];
+                    for (part, value) in change_parts(change, Detail::Compact) {
+                        let part_style = value
+                            .and_then(|v| value_color(v, theme))
+                            .map_or(style, |c| style.fg(c));
+                        spans.extend(highlight_matches(&part, filter, part_style, theme));
+                    }
+                    if let Some((old, new)) = type_change {
+                        spans.push(Span::styled(format!(" [{} → {}]", old, new), style));
+                    }
+                    if let Some(restriction) = restriction {
+                        spans.push(Span::styled(
+                            format!(" ⚠ {}", restriction.label()),
+                            Style::default().fg(theme.warning),
+                        ));
+                    }
+                    if app.is_applied(change) {
+                        spans.push(Span::styled(" ✓ applied", Style::default().fg(theme.muted)));
+                    }
+                    let mut lines = vec![Line::from(spans)];
+                    if index == app.selected_diff_index {
+                        let wrapped = wrapped_values(change, &text, text_width);
+                        extra_rows = wrapped.len();
+                        lines.extend(wrapped.into_iter().map(|line| {
+                            Line::from(Span::styled(line, Style::default().fg(theme.detail)))
+                        }));
+                    }
+                    ListItem::new(lines)
                 })
                 .collect()
         })
-        .unwrap_or_default();
+        .unwrap_or_else(|| group_member_items(app));
 
     let border_style = if app.focus == Focus::Diff {
-        Style::default().fg(Color::Cyan)
+        Style::default().fg(theme.accent)
     } else {
         Style::default()
     };
 
     // Show copy hint in title when focused on Changes pane
-    let title = if app.focus == Focus::Diff {
-        " Changes (y to copy) "
-    } else {
-        " Changes "
-    };
+    let title = format!(
+        " Changes{}{}{} ",
+        match app.change_type_filter {
+            Some(kind) => format!(" [{} only]", kind.label()),
+            None => String::new(),
+        },
+        match app.change_sort {
+            ChangeSort::SourceOrder => String::new(),
+            sort => format!(" [by {}]", sort.label()),
+        },
+        if app.focus == Focus::Diff {
+            " (y to copy)"
+        } else {
+            ""
+        }
+    );
 
     let highlight_style = if app.focus == Focus::Diff {
-        Style::default()
-            .fg(Color::Black)
-            .bg(Color::White)
-            .add_modifier(Modifier::BOLD)
+        theme.change_selection
     } else {
         Style::default().add_modifier(Modifier::BOLD)
     };
@@ -383,74 +1223,344 @@ fn render_diff_details(frame: &mut Frame, app: &mut App, area: Rect) {
         .highlight_style(highlight_style)
         .highlight_symbol(">> ");
     frame.render_stateful_widget(list, area, &mut app.diff_list_state);
+    app.selected_change_extra_rows = extra_rows;
 }
 
-fn format_change(change: &Change) -> String {
-    match change {
-        Change::Added { key, value, .. } => {
-            format!("{}: {}", key, format_value(value))
-        }
-        Change::Removed { key, old_value, .. } => {
-            format!("{}: {}", key, format_value(old_value))
+/// Tint for a value in a change row, by plist type; containers and data keep
+/// the change color
+fn value_color(value: &plist::Value, theme: &Theme) -> Option<Color> {
+    match value {
+        plist::Value::Boolean(_) => theme.boolean,
+        plist::Value::Integer(_) | plist::Value::Real(_) => theme.number,
+        plist::Value::String(_) => theme.string,
+        plist::Value::Date(_) => theme.date,
+        _ => None,
+    }
+}
+
+/// Full old and new values of a change wrapped to `width`, for a row whose
+/// compact text was truncated or is too wide. Empty when the row shows it all.
+fn wrapped_values(change: &Change, compact: &str, width: usize) -> Vec<String> {
+    if compact == format_change(change, Detail::Full) && compact.chars().count() <= width {
+        return Vec::new();
+    }
+    let values: Vec<(&str, &plist::Value)> = match (change.old_value(), change.new_value()) {
+        (Some(old), Some(new)) => vec![("old: ", old), ("new: ", new)],
+        (Some(value), None) | (None, Some(value)) => vec![("value: ", value)],
+        (None, None) => Vec::new(),
+    };
+
+    let mut lines = Vec::new();
+    for (label, value) in values {
+        let indent = format!("  {}", " ".repeat(label.len()));
+        let text = format_value(value, Detail::Full);
+        for (i, line) in wrap_text(&text, width.saturating_sub(indent.len()))
+            .into_iter()
+            .enumerate()
+        {
+            let prefix = if i == 0 {
+                format!("  {}", label)
+            } else {
+                indent.clone()
+            };
+            lines.push(format!("{}{}", prefix, line));
         }
-        Change::Modified {
-            key,
-            old_value,
-            new_value,
-            ..
-        } => {
-            format!(
-                "{}: {} → {}",
-                key,
-                format_value(old_value),
-                format_value(new_value)
-            )
+    }
+    lines
+}
+
+/// Break `text` into lines of at most `width` characters, at spaces where possible
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut len = 0;
+        for word in paragraph.split(' ') {
+            if len > 0 && len + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+                len = 0;
+            }
+            if len > 0 {
+                line.push(' ');
+                len += 1;
+            }
+            // Words longer than a line are broken wherever they hit the edge
+            for c in word.chars() {
+                if len == width {
+                    lines.push(std::mem::take(&mut line));
+                    len = 0;
+                }
+                line.push(c);
+                len += 1;
+            }
         }
+        lines.push(line);
     }
+    lines
 }
 
-fn format_value(value: &plist::Value) -> String {
-    match value {
-        plist::Value::Boolean(b) => format!("{}", b),
-        plist::Value::Integer(i) => format!("{}", i.as_signed().unwrap_or(0)),
-        plist::Value::Real(f) => format!("{:.2}", f),
-        plist::Value::String(s) => {
-            if s.chars().count() > 30 {
-                format!("\"{}...\"", s.chars().take(27).collect::<String>())
-            } else {
-                format!("\"{}\"", s)
+/// Text, color and title of the open input line, drawn in place of the footer
+fn input_line(app: &App) -> Option<(String, Color, &'static str)> {
+    let mode = app.input_mode?;
+    let (prompt, title) = match mode {
+        InputMode::Filter => ("/", " Filter (Enter to keep, Esc to clear) "),
+        InputMode::DomainScope => (
+            "Domain: ",
+            " Capture Only (Enter to set, blank for all, Esc to cancel) ",
+        ),
+        InputMode::Jump => (":", " Jump to Domain (Enter to stay, Esc to go back) "),
+    };
+    let no_match =
+        mode == InputMode::Jump && !app.jump_query.is_empty() && app.jump_match.is_none();
+    Some((
+        format!("{}{}_", prompt, app.input_text()),
+        if no_match {
+            app.theme.theme().error
+        } else {
+            app.theme.theme().warning
+        },
+        title,
+    ))
+}
+
+/// Split `text` into spans, highlighting case-insensitive occurrences of `query`
+fn highlight_matches(text: &str, query: &str, style: Style, theme: &Theme) -> Vec<Span<'static>> {
+    if query.is_empty() {
+        return vec![Span::styled(text.to_string(), style)];
+    }
+    let highlight = style.patch(theme.search_match);
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+
+    let mut spans = Vec::new();
+    let mut plain_start = 0;
+    let mut index = 0;
+    while index < text.len() {
+        let mut end = index;
+        let mut chars = text[index..].chars().flat_map(char::to_lowercase);
+        let matched = query.iter().all(|q| chars.next() == Some(*q));
+        if matched {
+            // Advance `end` past as many source chars as the query covered
+            let mut consumed = 0;
+            for c in text[index..].chars() {
+                if consumed >= query.len() {
+                    break;
+                }
+                consumed += c.to_lowercase().count();
+                end += c.len_utf8();
             }
+            if plain_start < index {
+                spans.push(Span::styled(text[plain_start..index].to_string(), style));
+            }
+            spans.push(Span::styled(text[index..end].to_string(), highlight));
+            plain_start = end;
+            index = end;
+        } else {
+            index += text[index..].chars().next().map_or(1, char::len_utf8);
         }
-        plist::Value::Data(d) => format!("<data {} bytes>", d.len()),
-        plist::Value::Array(a) => format!("[{} items]", a.len()),
-        plist::Value::Dictionary(d) => format!("{{{}}} keys", d.len()),
-        plist::Value::Date(d) => d.to_xml_format().to_string(),
-        plist::Value::Uid(u) => format!("UID({})", u.get()),
-        _ => "<unknown>".to_string(),
     }
+    if plain_start < text.len() {
+        spans.push(Span::styled(text[plain_start..].to_string(), style));
+    }
+    spans
 }
 
-fn render_error_screen(frame: &mut Frame, msg: &str) {
+fn render_error_screen(frame: &mut Frame, msg: &str, theme: &Theme) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(frame.area());
 
-    let error = Paragraph::new(vec![
-        Line::from(""),
-        Line::from(vec![
-            Span::styled("  ✗ ", Style::default().fg(Color::Red)),
-            Span::raw(msg),
-        ]),
-    ])
-    .style(Style::default().fg(Color::Red))
-    .wrap(Wrap { trim: false })
-    .block(Block::default().borders(Borders::ALL).title(" Error "));
+    // The first line is the error, any further lines suggest a fix
+    let mut lines = vec![Line::from("")];
+    let mut msg_lines = msg.lines();
+    lines.push(Line::from(vec![
+        Span::styled("  ✗ ", Style::default().fg(theme.error)),
+        Span::raw(msg_lines.next().unwrap_or_default().to_string()),
+    ]));
+    lines.extend(msg_lines.map(|hint| {
+        Line::from(Span::styled(
+            format!("    {}", hint),
+            Style::default().fg(theme.warning),
+        ))
+    }));
+    let error = Paragraph::new(lines)
+        .style(Style::default().fg(theme.error))
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title(" Error "));
     frame.render_widget(error, chunks[0]);
 
     let help = Paragraph::new("Press [r] to reset or [q] to quit")
-        .style(Style::default().fg(Color::DarkGray))
+        .style(Style::default().fg(theme.muted))
         .block(Block::default().borders(Borders::ALL).title(" Help "));
     frame.render_widget(help, chunks[1]);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span_texts(spans: &[Span]) -> Vec<String> {
+        spans.iter().map(|s| s.content.to_string()).collect()
+    }
+
+    #[test]
+    fn test_change_tally_line() {
+        use crate::defaults::types::Scope;
+        use crate::diff::types::DomainDiff;
+        use plist::Value;
+
+        let change = |key: &str, old: Option<i64>, new: Option<i64>| match (old, new) {
+            (None, Some(n)) => Change::Added {
+                domain: "com.a".to_string(),
+                key: key.to_string(),
+                value: Value::Integer(n.into()),
+            },
+            (Some(o), None) => Change::Removed {
+                domain: "com.a".to_string(),
+                key: key.to_string(),
+                old_value: Value::Integer(o.into()),
+            },
+            (o, n) => Change::Modified {
+                domain: "com.a".to_string(),
+                key: key.to_string(),
+                old_value: Value::Integer(o.unwrap_or_default().into()),
+                new_value: Value::Integer(n.unwrap_or_default().into()),
+            },
+        };
+        let diff = DiffResult {
+            domain_diffs: vec![DomainDiff {
+                domain: "com.a".to_string(),
+                changes: vec![
+                    change("a", None, Some(1)),
+                    change("b", None, Some(1)),
+                    change("c", Some(1), None),
+                    change("d", Some(1), Some(2)),
+                ],
+                scope: Scope::User,
+            }],
+            total_changes: 4,
+        };
+
+        let line = change_tally_line(&diff, &Theme::DEFAULT);
+        assert_eq!(line.to_string(), "Found 4 changes (2+ 1- 1~)");
+        assert_eq!(line.spans[1].style.fg, Some(Color::Green));
+        assert_eq!(line.spans[3].style.fg, Some(Color::Red));
+        assert_eq!(line.spans[5].style.fg, Some(Color::Yellow));
+    }
+
+    #[test]
+    fn test_format_age() {
+        use std::time::Duration;
+        assert_eq!(format_age(Duration::from_secs(5)), "just now");
+        assert_eq!(format_age(Duration::from_secs(150)), "2 min ago");
+        assert_eq!(format_age(Duration::from_secs(7300)), "2 h ago");
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(wrap_text("short", 10), vec!["short"]);
+        assert_eq!(
+            wrap_text("the quick brown fox", 10),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(wrap_text("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap_text("a\nb", 10), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_wrapped_values_only_for_long_rows() {
+        let change = Change::Modified {
+            domain: "com.a".to_string(),
+            key: "k".to_string(),
+            old_value: plist::Value::Integer(1.into()),
+            new_value: plist::Value::Integer(2.into()),
+        };
+        let compact = format_change(&change, Detail::Compact);
+        assert!(wrapped_values(&change, &compact, 40).is_empty());
+        assert_eq!(
+            wrapped_values(&change, &compact, 5),
+            vec!["  old: 1", "  new: 2"]
+        );
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let style = Style::default();
+        let theme = &Theme::DEFAULT;
+        assert_eq!(
+            span_texts(&highlight_matches("com.apple.dock", "", style, theme)),
+            vec!["com.apple.dock"]
+        );
+        let spans = highlight_matches("com.apple.Dock dock", "DOCK", style, theme);
+        assert_eq!(span_texts(&spans), vec!["com.apple.", "Dock", " ", "dock"]);
+        assert_eq!(spans[1].style.bg, Some(Color::Yellow));
+        assert_eq!(spans[2].style.bg, None);
+        assert_eq!(
+            span_texts(&highlight_matches("café crème", "É", style, theme)),
+            vec!["caf", "é", " crème"]
+        );
+    }
+
+    #[test]
+    fn test_domain_prefix() {
+        assert_eq!(domain_prefix("com.apple.dock"), "com.apple");
+        assert_eq!(domain_prefix("com.apple"), "com.apple");
+        assert_eq!(domain_prefix("NSGlobalDomain"), "NSGlobalDomain");
+    }
+
+    #[test]
+    fn test_prefix_color_is_deterministic_per_prefix() {
+        let theme = &Theme::DEFAULT;
+        assert_eq!(
+            prefix_color("com.apple.dock", theme),
+            prefix_color("com.apple.finder", theme)
+        );
+        assert_eq!(
+            prefix_color("com.apple.dock", theme),
+            prefix_color("com.apple.dock", theme)
+        );
+        assert!(
+            theme
+                .palette
+                .contains(&prefix_color("org.mozilla.firefox", theme))
+        );
+        assert_ne!(prefix_color("com.apple.dock", theme), Color::Red);
+    }
+
+    #[test]
+    fn test_diff_footer_hides_apply_when_read_only() {
+        let mut app = App::new();
+        app.focus = Focus::Diff;
+        assert!(diff_footer_hint(&app).contains("[a] Apply"));
+        app.read_only = true;
+        assert!(!diff_footer_hint(&app).contains("[a] Apply"));
+        assert!(diff_footer_hint(&app).contains("[y] Copy command"));
+    }
+
+    #[test]
+    fn test_status_region_auto_uses_natural() {
+        assert_eq!(
+            status_region(StatusPosition::Auto, Region::Header),
+            Some(Region::Header)
+        );
+        assert_eq!(
+            status_region(StatusPosition::Auto, Region::Footer),
+            Some(Region::Footer)
+        );
+    }
+
+    #[test]
+    fn test_status_region_explicit_and_hidden() {
+        assert_eq!(
+            status_region(StatusPosition::Footer, Region::Header),
+            Some(Region::Footer)
+        );
+        assert_eq!(
+            status_region(StatusPosition::Header, Region::Footer),
+            Some(Region::Header)
+        );
+        assert_eq!(status_region(StatusPosition::Hidden, Region::Header), None);
+    }
+}
@@ -0,0 +1,216 @@
+use ratatui::style::{Color, Modifier, Style};
+
+/// Built-in color themes, picked with `theme = <name>` in the config or `m`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ThemePreset {
+    #[default]
+    Default,
+    /// Few, strong colors that read on light and dark backgrounds alike and
+    /// keep added (blue) and removed (red) apart for red-green color blindness
+    HighContrast,
+    /// Terminal colors only; selection and matches use reverse video and underline
+    Monochrome,
+}
+
+impl ThemePreset {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(ThemePreset::Default),
+            "high-contrast" => Some(ThemePreset::HighContrast),
+            "monochrome" => Some(ThemePreset::Monochrome),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ThemePreset::Default => "default",
+            ThemePreset::HighContrast => "high-contrast",
+            ThemePreset::Monochrome => "monochrome",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ThemePreset::Default => ThemePreset::HighContrast,
+            ThemePreset::HighContrast => ThemePreset::Monochrome,
+            ThemePreset::Monochrome => ThemePreset::Default,
+        }
+    }
+
+    pub fn theme(self) -> &'static Theme {
+        match self {
+            ThemePreset::Default => &Theme::DEFAULT,
+            ThemePreset::HighContrast => &Theme::HIGH_CONTRAST,
+            ThemePreset::Monochrome => &Theme::MONOCHROME,
+        }
+    }
+}
+
+/// Named colors and styles the UI draws with
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Focused borders, headings, key hints and info messages
+    pub accent: Color,
+    pub added: Color,
+    pub removed: Color,
+    pub modified: Color,
+    /// Modified keys whose value changed type
+    pub type_change: Color,
+    pub success: Color,
+    pub warning: Color,
+    pub error: Color,
+    /// Hints, prompts and normalized or applied changes
+    pub muted: Color,
+    /// Commands and other text to read closely
+    pub text: Color,
+    /// Full values wrapped beneath the selected change
+    pub detail: Color,
+    /// Selected row of the focused domain list
+    pub selection: Style,
+    /// Selected row of a list without focus
+    pub selection_unfocused: Style,
+    /// Selected row of the focused Changes pane
+    pub change_selection: Style,
+    /// Filter matches inside list rows
+    pub search_match: Style,
+    /// The READ-ONLY title badge
+    pub banner: Style,
+    /// Value tints by plist type; `None` keeps the change color
+    pub boolean: Option<Color>,
+    pub number: Option<Color>,
+    pub string: Option<Color>,
+    pub date: Option<Color>,
+    /// Colors for domain prefixes (the removal color is left out)
+    pub palette: [Color; 8],
+}
+
+impl Theme {
+    pub const DEFAULT: Theme = Theme {
+        accent: Color::Cyan,
+        added: Color::Green,
+        removed: Color::Red,
+        modified: Color::Yellow,
+        type_change: Color::Magenta,
+        success: Color::Green,
+        warning: Color::Yellow,
+        error: Color::Red,
+        muted: Color::DarkGray,
+        text: Color::White,
+        detail: Color::Gray,
+        selection: Style::new()
+            .fg(Color::Black)
+            .bg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+        selection_unfocused: Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        change_selection: Style::new()
+            .fg(Color::Black)
+            .bg(Color::White)
+            .add_modifier(Modifier::BOLD),
+        search_match: Style::new().fg(Color::Black).bg(Color::Yellow),
+        banner: Style::new()
+            .fg(Color::Black)
+            .bg(Color::Yellow)
+            .add_modifier(Modifier::BOLD),
+        boolean: Some(Color::Cyan),
+        number: Some(Color::LightBlue),
+        string: Some(Color::White),
+        date: Some(Color::LightMagenta),
+        palette: [
+            Color::Cyan,
+            Color::Green,
+            Color::Yellow,
+            Color::Magenta,
+            Color::Blue,
+            Color::LightCyan,
+            Color::LightGreen,
+            Color::LightMagenta,
+        ],
+    };
+
+    pub const HIGH_CONTRAST: Theme = Theme {
+        accent: Color::Blue,
+        added: Color::Blue,
+        removed: Color::Red,
+        modified: Color::Magenta,
+        type_change: Color::Magenta,
+        success: Color::Blue,
+        warning: Color::Magenta,
+        error: Color::Red,
+        muted: Color::Reset,
+        text: Color::Reset,
+        detail: Color::Reset,
+        selection: Style::new()
+            .fg(Color::White)
+            .bg(Color::Blue)
+            .add_modifier(Modifier::BOLD),
+        selection_unfocused: Style::new().fg(Color::Blue).add_modifier(Modifier::BOLD),
+        change_selection: Style::new().add_modifier(Modifier::REVERSED.union(Modifier::BOLD)),
+        search_match: Style::new().fg(Color::White).bg(Color::Magenta),
+        banner: Style::new()
+            .fg(Color::White)
+            .bg(Color::Red)
+            .add_modifier(Modifier::BOLD),
+        boolean: None,
+        number: None,
+        string: None,
+        date: None,
+        palette: [Color::Reset; 8],
+    };
+
+    pub const MONOCHROME: Theme = Theme {
+        accent: Color::Reset,
+        added: Color::Reset,
+        removed: Color::Reset,
+        modified: Color::Reset,
+        type_change: Color::Reset,
+        success: Color::Reset,
+        warning: Color::Reset,
+        error: Color::Reset,
+        muted: Color::Reset,
+        text: Color::Reset,
+        detail: Color::Reset,
+        selection: Style::new().add_modifier(Modifier::REVERSED.union(Modifier::BOLD)),
+        selection_unfocused: Style::new().add_modifier(Modifier::BOLD),
+        change_selection: Style::new().add_modifier(Modifier::REVERSED.union(Modifier::BOLD)),
+        search_match: Style::new().add_modifier(Modifier::UNDERLINED.union(Modifier::BOLD)),
+        banner: Style::new().add_modifier(Modifier::REVERSED.union(Modifier::BOLD)),
+        boolean: None,
+        number: None,
+        string: None,
+        date: None,
+        palette: [Color::Reset; 8],
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_preset_parse_and_cycle() {
+        assert_eq!(
+            ThemePreset::parse("high-contrast"),
+            Some(ThemePreset::HighContrast)
+        );
+        assert_eq!(ThemePreset::parse("solarized"), None);
+        let mut preset = ThemePreset::default();
+        for _ in 0..3 {
+            assert_eq!(ThemePreset::parse(preset.label()), Some(preset));
+            preset = preset.next();
+        }
+        assert_eq!(preset, ThemePreset::Default);
+    }
+
+    #[test]
+    fn test_palettes_leave_out_the_removal_color() {
+        for preset in [
+            ThemePreset::Default,
+            ThemePreset::HighContrast,
+            ThemePreset::Monochrome,
+        ] {
+            let theme = preset.theme();
+            assert!(theme.removed == Color::Reset || !theme.palette.contains(&theme.removed));
+        }
+    }
+}
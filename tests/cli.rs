@@ -0,0 +1,210 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn write_snapshot(name: &str, body: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "defaults-util-cli-{}-{}.plist",
+        std::process::id(),
+        name
+    ));
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0"><dict>{}</dict></plist>"#,
+        body
+    );
+    fs::write(&path, xml).unwrap();
+    path
+}
+
+#[test]
+fn test_summary_counts() {
+    let a = write_snapshot(
+        "summary-a",
+        "<key>com.apple.dock</key><dict>\
+             <key>tilesize</key><integer>36</integer>\
+             <key>autohide</key><true/>\
+         </dict>\
+         <key>com.example</key><dict><key>old</key><string>x</string></dict>",
+    );
+    let b = write_snapshot(
+        "summary-b",
+        "<key>com.apple.dock</key><dict>\
+             <key>tilesize</key><integer>48</integer>\
+             <key>autohide</key><true/>\
+             <key>orientation</key><string>left</string>\
+         </dict>\
+         <key>com.example</key><dict></dict>",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_defaults-util"))
+        .args(["summary", "--a"])
+        .arg(&a)
+        .arg("--b")
+        .arg(&b)
+        .output()
+        .unwrap();
+    fs::remove_file(&a).unwrap();
+    fs::remove_file(&b).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "Added: 1\nRemoved: 1\nModified: 1\nTotal: 3\nChanged domains:\n  com.apple.dock (2)\n  com.example (1)\n"
+    );
+}
+
+#[test]
+fn test_summary_missing_file_fails() {
+    let output = Command::new(env!("CARGO_BIN_EXE_defaults-util"))
+        .args([
+            "summary",
+            "--a",
+            "/nonexistent/a.plist",
+            "--b",
+            "/nonexistent/b.plist",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_diff_prints_commands() {
+    let a = write_snapshot(
+        "diff-a",
+        "<key>com.example</key><dict>\
+             <key>size</key><integer>36</integer>\
+             <key>old</key><true/>\
+         </dict>",
+    );
+    let b = write_snapshot(
+        "diff-b",
+        "<key>com.example</key><dict><key>size</key><integer>48</integer></dict>",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_defaults-util"))
+        .arg("diff")
+        .arg(&a)
+        .arg(&b)
+        .output()
+        .unwrap();
+    fs::remove_file(&a).unwrap();
+    fs::remove_file(&b).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "defaults write \"com.example\" \"size\" -int 48\ndefaults delete \"com.example\" \"old\"\n"
+    );
+}
+
+#[test]
+fn test_compare_missing_file_fails_before_tui() {
+    let output = Command::new(env!("CARGO_BIN_EXE_defaults-util"))
+        .args(["compare", "/nonexistent/a.plist", "/nonexistent/b.plist"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_diff_plist_path_override() {
+    let a = write_snapshot("path-a", "<key>com.example</key><dict></dict>");
+    let b = write_snapshot(
+        "path-b",
+        "<key>com.example</key><dict><key>on</key><true/></dict>",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_defaults-util"))
+        .arg("diff")
+        .arg(&a)
+        .arg(&b)
+        .args(["--plist-path", "com.example=/Apps/My App/Prefs.plist"])
+        .output()
+        .unwrap();
+    let relative = Command::new(env!("CARGO_BIN_EXE_defaults-util"))
+        .arg("diff")
+        .arg(&a)
+        .arg(&b)
+        .args(["--plist-path", "com.example=Prefs.plist"])
+        .output()
+        .unwrap();
+    fs::remove_file(&a).unwrap();
+    fs::remove_file(&b).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "defaults write \"/Apps/My App/Prefs\" \"on\" -bool true\n"
+    );
+    assert!(!relative.status.success());
+}
+
+#[test]
+fn test_diff_only_and_exclude_globs() {
+    let a = write_snapshot("globs-a", "");
+    let b = write_snapshot(
+        "globs-b",
+        "<key>com.apple.dock</key><dict><key>a</key><true/></dict>\
+         <key>com.apple.finder</key><dict><key>b</key><true/></dict>\
+         <key>com.example</key><dict><key>c</key><true/></dict>",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_defaults-util"))
+        .arg("diff")
+        .arg(&a)
+        .arg(&b)
+        .args(["--only", "com.apple.*", "--exclude", "*.finder"])
+        .output()
+        .unwrap();
+    fs::remove_file(&a).unwrap();
+    fs::remove_file(&b).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "defaults write \"com.apple.dock\" \"a\" -bool true\nkillall Dock\n"
+    );
+}
+
+#[test]
+fn test_diff_leaves_out_apple_services_unless_included() {
+    // `before` was captured with service domains, `after` without them
+    let a = write_snapshot(
+        "services-a",
+        "<key>com.apple.cloudd</key><dict><key>x</key><true/></dict>\
+         <key>com.example</key><dict></dict>",
+    );
+    let b = write_snapshot(
+        "services-b",
+        "<key>com.example</key><dict><key>on</key><true/></dict>",
+    );
+
+    let diff = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_defaults-util"))
+            .arg("diff")
+            .arg(&a)
+            .arg(&b)
+            .args(extra)
+            .output()
+            .unwrap()
+    };
+    let output = diff(&[]);
+    let included = diff(&["--include-apple-services"]);
+    fs::remove_file(&a).unwrap();
+    fs::remove_file(&b).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "defaults write \"com.example\" \"on\" -bool true\n"
+    );
+    assert!(
+        String::from_utf8(included.stdout)
+            .unwrap()
+            .contains("defaults delete \"com.apple.cloudd\" \"x\"")
+    );
+}